@@ -0,0 +1,4 @@
+// rebuild when a migration is added, `sqlx::migrate!` embeds them
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -1,4 +1,6 @@
-CREATE TABLE bots (
+-- databases created by the former deploy/init.sql already have the tables of
+-- the first migrations, they are created only when missing
+CREATE TABLE IF NOT EXISTS bots (
     id VARCHAR(255) PRIMARY KEY,
     name VARCHAR(255),
     market VARCHAR(255) NOT NULL,
@@ -13,4 +15,3 @@ CREATE TABLE bots (
     volatility_threshold DOUBLE PRECISION NOT NULL,
     is_running BOOLEAN DEFAULT FALSE
 );
--- zflub$0$1$9$8$!'aafg79ydhkhdfqagk65a6kgd12'
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS backtests (
+    id VARCHAR(255) PRIMARY KEY,
+    bot_name VARCHAR(255),
+    trading_strategy VARCHAR(255) NOT NULL,
+    symbols TEXT NOT NULL,
+    config JSONB NOT NULL,
+    report JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
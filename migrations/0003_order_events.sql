@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS order_events (
+    id BIGSERIAL PRIMARY KEY,
+    order_id VARCHAR(255) NOT NULL,
+    client_order_id VARCHAR(255),
+    symbol VARCHAR(255) NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    status VARCHAR(64) NOT NULL,
+    price NUMERIC,
+    qty NUMERIC,
+    filled_qty NUMERIC,
+    position_qty NUMERIC,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS order_events_order_id_idx ON order_events (order_id);
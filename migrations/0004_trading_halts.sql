@@ -0,0 +1,7 @@
+-- halted scopes, an empty bot_id halts the whole account
+CREATE TABLE IF NOT EXISTS trading_halts (
+    bot_id VARCHAR(255) PRIMARY KEY,
+    trigger VARCHAR(64) NOT NULL,
+    reason TEXT NOT NULL,
+    halted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+-- sizing model of the orders of a bot, see `core::sizing::SizingModel`
+ALTER TABLE bots ADD COLUMN IF NOT EXISTS sizing JSONB NOT NULL DEFAULT '{"model": "fixed_fraction"}';
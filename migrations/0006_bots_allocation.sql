@@ -0,0 +1,3 @@
+-- capital allocated to a bot, see `core::allocator::Allocation`, null when
+-- the bot shares the account
+ALTER TABLE bots ADD COLUMN IF NOT EXISTS allocation JSONB;
@@ -0,0 +1,24 @@
+-- orders placed through the api and the bots, kept up to date by the trade
+-- updates, `bot_id` is read from the client order id of the bot orders
+CREATE TABLE IF NOT EXISTS orders (
+    id VARCHAR(255) PRIMARY KEY,
+    client_order_id VARCHAR(255),
+    bot_id VARCHAR(255),
+    symbol VARCHAR(255) NOT NULL,
+    side VARCHAR(16) NOT NULL,
+    order_type VARCHAR(32) NOT NULL,
+    time_in_force VARCHAR(16) NOT NULL,
+    order_class VARCHAR(16) NOT NULL,
+    qty NUMERIC,
+    notional NUMERIC,
+    limit_price NUMERIC,
+    stop_price NUMERIC,
+    filled_qty NUMERIC,
+    filled_avg_price NUMERIC,
+    status VARCHAR(64) NOT NULL,
+    replaces VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS orders_bot_id_idx ON orders (bot_id, created_at);
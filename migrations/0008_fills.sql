@@ -0,0 +1,14 @@
+-- executions of the orders, one row per fill or partial fill
+CREATE TABLE IF NOT EXISTS fills (
+    id BIGSERIAL PRIMARY KEY,
+    order_id VARCHAR(255) NOT NULL,
+    bot_id VARCHAR(255),
+    symbol VARCHAR(255) NOT NULL,
+    side VARCHAR(16) NOT NULL,
+    qty NUMERIC NOT NULL,
+    price NUMERIC NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS fills_bot_id_idx ON fills (bot_id, timestamp);
+CREATE INDEX IF NOT EXISTS fills_order_id_idx ON fills (order_id);
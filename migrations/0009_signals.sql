@@ -0,0 +1,17 @@
+-- signals of the bots and what became of them, `order_id` is set when an
+-- order was placed
+CREATE TABLE IF NOT EXISTS signals (
+    id BIGSERIAL PRIMARY KEY,
+    bot_id VARCHAR(255) NOT NULL,
+    symbol VARCHAR(255) NOT NULL,
+    side VARCHAR(16) NOT NULL,
+    price NUMERIC NOT NULL,
+    stop_price NUMERIC,
+    qty NUMERIC,
+    outcome VARCHAR(16) NOT NULL,
+    reason TEXT,
+    order_id VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS signals_bot_id_idx ON signals (bot_id, created_at);
@@ -0,0 +1,12 @@
+-- runs of the bots with the config they ran with, open while `stopped_at`
+-- is null
+CREATE TABLE IF NOT EXISTS bot_runs (
+    id BIGSERIAL PRIMARY KEY,
+    bot_id VARCHAR(255) NOT NULL,
+    config JSONB NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    stopped_at TIMESTAMPTZ,
+    stop_reason VARCHAR(64)
+);
+
+CREATE INDEX IF NOT EXISTS bot_runs_bot_id_idx ON bot_runs (bot_id, started_at);
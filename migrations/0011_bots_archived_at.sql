@@ -0,0 +1,3 @@
+-- removed bots are archived, their orders, signals and runs keep pointing at
+-- them
+ALTER TABLE bots ADD COLUMN archived_at TIMESTAMPTZ;
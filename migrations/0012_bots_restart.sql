@@ -0,0 +1,3 @@
+-- restart policy of the bots, as its JSON, they stay down after a crash by
+-- default
+ALTER TABLE bots ADD COLUMN restart JSONB NOT NULL DEFAULT '{"policy": "never"}';
@@ -0,0 +1,2 @@
+-- what a bot does with its orders and positions when it is stopped
+ALTER TABLE bots ADD COLUMN on_stop TEXT NOT NULL DEFAULT 'leave';
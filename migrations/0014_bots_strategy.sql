@@ -0,0 +1,30 @@
+-- each strategy has its own parameters, the strategy columns are folded into
+-- them as `{"kind": ..., "params": {...}}`
+ALTER TABLE bots ADD COLUMN strategy JSONB;
+
+UPDATE bots SET strategy = CASE trading_strategy
+    WHEN 'SmartMoney' THEN jsonb_build_object(
+        'kind', 'smart_money',
+        'params', jsonb_build_object(
+            'lookback', GREATEST(lookback, volatility_window),
+            'level_window', volatility_window,
+            'volume_threshold', threshold
+        )
+    )
+    -- the thresholds left at 0 were not set, the defaults of the strategy apply
+    ELSE jsonb_build_object(
+        'kind', 'mean_reversion',
+        'params', jsonb_strip_nulls(jsonb_build_object(
+            'lookback', lookback,
+            'threshold', NULLIF(threshold, 0),
+            'volatility_threshold', NULLIF(volatility_threshold, 0)
+        ))
+    )
+END;
+
+ALTER TABLE bots ALTER COLUMN strategy SET NOT NULL;
+ALTER TABLE bots
+    DROP COLUMN trading_strategy,
+    DROP COLUMN lookback,
+    DROP COLUMN threshold,
+    DROP COLUMN volatility_threshold;
@@ -0,0 +1,23 @@
+-- market data cache, one row per bar of a symbol and timeframe
+CREATE TABLE IF NOT EXISTS bars (
+    symbol VARCHAR(255) NOT NULL,
+    timeframe VARCHAR(16) NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    volume DOUBLE PRECISION NOT NULL,
+    trades BIGINT NOT NULL,
+    vwap DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (symbol, timeframe, timestamp)
+);
+
+-- time range of each symbol and timeframe whose bars are all in `bars`
+CREATE TABLE IF NOT EXISTS bar_coverage (
+    symbol VARCHAR(255) NOT NULL,
+    timeframe VARCHAR(16) NOT NULL,
+    start_at TIMESTAMPTZ NOT NULL,
+    end_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (symbol, timeframe)
+);
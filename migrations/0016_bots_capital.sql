@@ -0,0 +1,4 @@
+-- capital a bot with an allocation was given when it first started, a
+-- percentage allocation does not follow the equity afterwards. Reset when
+-- the allocation changes.
+ALTER TABLE bots ADD COLUMN IF NOT EXISTS capital NUMERIC;
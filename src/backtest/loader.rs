@@ -0,0 +1,140 @@
+use crate::error::Error;
+use crate::models::bar::Bar;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Json files either hold a list of bars or an alpaca style `{"bars": [...]}` object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonBars {
+    List(Vec<Bar>),
+    Response { bars: Vec<Bar> },
+}
+
+/// Load a bar series from a `.csv` or `.json` file.
+///
+/// Csv files use the alpaca field names as header (`t,o,h,l,c,v,n,vw`), `n`
+/// and `vw` are optional. Bars are returned oldest first.
+pub fn load_bars(path: impl AsRef<Path>) -> Result<Vec<Bar>, Error> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    let mut bars = match extension.as_deref() {
+        Some("csv") => read_csv(File::open(path)?)?,
+        Some("json") => read_json(File::open(path)?)?,
+        _ => {
+            return Err(Error::InvalidData(format!(
+                "unsupported bar file {}, expected .csv or .json",
+                path.display()
+            )))
+        }
+    };
+
+    bars.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(bars)
+}
+
+/// Directory the bar files of the backtests are read from,
+/// `BACKTEST_DATA_DIR` or `data` by default
+pub fn data_dir() -> PathBuf {
+    std::env::var("BACKTEST_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data"))
+}
+
+/// Path of the bar file `name` in `dir`, absolute names and names leaving
+/// the directory are refused
+pub fn resolve(dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(name);
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if name.is_empty() || !inside {
+        return Err(Error::InvalidData(format!(
+            "invalid bar file name '{}'",
+            name
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Load one bar file of `dir` per symbol
+pub fn load_symbols(
+    dir: &Path,
+    files: &HashMap<String, String>,
+) -> Result<HashMap<String, Vec<Bar>>, Error> {
+    files
+        .iter()
+        .map(|(symbol, name)| Ok((symbol.clone(), load_bars(resolve(dir, name)?)?)))
+        .collect()
+}
+
+fn read_csv(reader: impl Read) -> Result<Vec<Bar>, Error> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let bars = reader
+        .deserialize::<Bar>()
+        .collect::<Result<Vec<Bar>, csv::Error>>()?;
+    Ok(bars)
+}
+
+fn read_json(reader: impl Read) -> Result<Vec<Bar>, Error> {
+    let bars = match serde_json::from_reader::<_, JsonBars>(reader)? {
+        JsonBars::List(bars) => bars,
+        JsonBars::Response { bars } => bars,
+    };
+    Ok(bars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_csv_bars() {
+        let data = "t,o,h,l,c,v\n\
+                    2024-01-01T00:00:00Z,1.0,2.0,0.5,1.5,100\n\
+                    2024-01-01T00:01:00Z,1.5,2.5,1.0,2.0,200\n";
+
+        let bars = read_csv(data.as_bytes()).unwrap();
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[1].close_price, 2.0);
+        assert_eq!(bars[1].n, 0);
+    }
+
+    #[test]
+    fn bar_files_stay_in_the_data_directory() {
+        let dir = Path::new("data");
+        assert_eq!(
+            resolve(dir, "btc/1min.csv").unwrap(),
+            Path::new("data/btc/1min.csv")
+        );
+        for name in [
+            "/etc/passwd",
+            "../secrets.csv",
+            "btc/../../x.csv",
+            "./x.csv",
+            "",
+        ] {
+            assert!(resolve(dir, name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn read_json_bars() {
+        let bar =
+            r#"{"t":"2024-01-01T00:00:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100,"n":3,"vw":1.2}"#;
+
+        let list = read_json(format!("[{}]", bar).as_bytes()).unwrap();
+        let response = read_json(format!(r#"{{"bars":[{}]}}"#, bar).as_bytes()).unwrap();
+
+        assert_eq!(list.len(), 1);
+        assert_eq!(response[0].vw, 1.2);
+    }
+}
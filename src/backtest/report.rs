@@ -0,0 +1,214 @@
+use crate::broker::sim::SimFill;
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub timestamp: DateTime<Utc>,
+    pub equity: f64,
+    /// Whether a position was held at that time
+    pub exposed: bool,
+}
+
+/// Result of a backtest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub strategy: String,
+    pub symbols: Vec<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub initial_equity: f64,
+    pub final_equity: f64,
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub sharpe_ratio: f64,
+    pub sortino_ratio: f64,
+    pub win_rate: f64,
+    pub exposure: f64,
+    pub equity_curve: Vec<EquityPoint>,
+    pub trades: Vec<SimFill>,
+}
+
+impl BacktestReport {
+    pub fn new(
+        strategy: String,
+        symbols: Vec<String>,
+        initial_equity: f64,
+        equity_curve: Vec<EquityPoint>,
+        trades: Vec<SimFill>,
+    ) -> Self {
+        let equities: Vec<f64> = equity_curve.iter().map(|point| point.equity).collect();
+        let returns = returns(initial_equity, &equities);
+        let timestamps: Vec<DateTime<Utc>> =
+            equity_curve.iter().map(|point| point.timestamp).collect();
+        let periods = periods_per_year(&timestamps);
+        let final_equity = equities.last().copied().unwrap_or(initial_equity);
+        let exposure = if equity_curve.is_empty() {
+            0.0
+        } else {
+            equity_curve.iter().filter(|point| point.exposed).count() as f64
+                / equity_curve.len() as f64
+        };
+
+        Self {
+            strategy,
+            symbols,
+            start: timestamps.first().copied(),
+            end: timestamps.last().copied(),
+            initial_equity,
+            final_equity,
+            total_return: final_equity / initial_equity - 1.0,
+            max_drawdown: max_drawdown(initial_equity, &equities),
+            sharpe_ratio: sharpe_ratio(&returns, periods),
+            sortino_ratio: sortino_ratio(&returns, periods),
+            win_rate: win_rate(&trades),
+            exposure,
+            equity_curve,
+            trades,
+        }
+    }
+}
+
+/// Period over period returns of an equity curve
+pub fn returns(initial_equity: f64, equities: &[f64]) -> Vec<f64> {
+    std::iter::once(initial_equity)
+        .chain(equities.iter().copied())
+        .collect::<Vec<f64>>()
+        .windows(2)
+        .map(|w| if w[0] == 0.0 { 0.0 } else { w[1] / w[0] - 1.0 })
+        .collect()
+}
+
+/// Largest peak to trough loss, as a fraction of the peak
+pub fn max_drawdown(initial_equity: f64, equities: &[f64]) -> f64 {
+    let mut peak = initial_equity;
+    let mut drawdown: f64 = 0.0;
+    for &equity in equities {
+        peak = peak.max(equity);
+        if peak > 0.0 {
+            drawdown = drawdown.max((peak - equity) / peak);
+        }
+    }
+    drawdown
+}
+
+/// Annualized sharpe ratio with a zero risk free rate
+pub fn sharpe_ratio(returns: &[f64], periods_per_year: f64) -> f64 {
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(returns);
+    let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+        / (returns.len() - 1) as f64)
+        .sqrt();
+    if std == 0.0 {
+        return 0.0;
+    }
+    mean / std * periods_per_year.sqrt()
+}
+
+/// Annualized sortino ratio, only the returns below zero count as risk
+pub fn sortino_ratio(returns: &[f64], periods_per_year: f64) -> f64 {
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let downside =
+        (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
+    if downside == 0.0 {
+        return 0.0;
+    }
+    mean(returns) / downside * periods_per_year.sqrt()
+}
+
+/// Share of the closing trades that made money
+pub fn win_rate(trades: &[SimFill]) -> f64 {
+    let closed: Vec<&Num> = trades
+        .iter()
+        .filter_map(|fill| fill.realized_pnl.as_ref())
+        .collect();
+    if closed.is_empty() {
+        return 0.0;
+    }
+    closed.iter().filter(|pnl| pnl.is_positive()).count() as f64 / closed.len() as f64
+}
+
+/// Number of bars per year, from the median spacing of the timestamps
+pub fn periods_per_year(timestamps: &[DateTime<Utc>]) -> f64 {
+    let mut spacings: Vec<i64> = timestamps
+        .windows(2)
+        .map(|w| (w[1] - w[0]).num_seconds())
+        .filter(|&seconds| seconds > 0)
+        .collect();
+    if spacings.is_empty() {
+        return 252.0;
+    }
+    spacings.sort_unstable();
+    let median = spacings[spacings.len() / 2] as f64;
+    365.25 * 24.0 * 3600.0 / median
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::trade::Side;
+
+    fn fill(realized_pnl: Option<i32>) -> SimFill {
+        SimFill {
+            order_id: "id".to_string(),
+            symbol: "AAPL".to_string(),
+            side: Side::Sell,
+            qty: Num::from(1),
+            price: Num::from(1),
+            fee: Num::from(0),
+            realized_pnl: realized_pnl.map(Num::from),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn drawdown_from_running_peak() {
+        let drawdown = max_drawdown(100.0, &[110.0, 99.0, 120.0, 108.0]);
+        assert!((drawdown - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn returns_start_from_initial_equity() {
+        let returns = returns(100.0, &[110.0, 99.0]);
+        assert_eq!(returns.len(), 2);
+        assert!((returns[0] - 0.1).abs() < 1e-12);
+        assert!((returns[1] + 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn flat_curve_has_no_ratio() {
+        let returns = returns(100.0, &[100.0, 100.0, 100.0]);
+        assert_eq!(sharpe_ratio(&returns, 252.0), 0.0);
+        assert_eq!(sortino_ratio(&returns, 252.0), 0.0);
+    }
+
+    #[test]
+    fn sortino_ignores_upside_volatility() {
+        let returns = [0.02, -0.01, 0.03, -0.01];
+        assert!(sortino_ratio(&returns, 252.0) > sharpe_ratio(&returns, 252.0));
+    }
+
+    #[test]
+    fn win_rate_counts_closing_trades_only() {
+        let trades = [fill(None), fill(Some(5)), fill(Some(-1)), fill(Some(2))];
+        assert!((win_rate(&trades) - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn periods_from_daily_bars() {
+        let start = "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let timestamps: Vec<DateTime<Utc>> = (0..3)
+            .map(|day| start + chrono::Duration::days(day))
+            .collect();
+        assert_eq!(periods_per_year(&timestamps), 365.25);
+    }
+}
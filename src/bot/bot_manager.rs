@@ -1,6 +1,6 @@
 use crate::base::AppState;
-use crate::bot::{Bot, BotConfig};
-use crate::dao::bot::get_all_running_bot;
+use crate::bot::{Bot, BotConfig, Drain};
+use crate::dao::bot::get_bots;
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -18,7 +18,7 @@ impl BotManager {
 
     ///init bot from db
     pub async fn init(&mut self, db: &PgPool, app_state: Arc<AppState>) {
-        match get_all_running_bot(db).await {
+        match get_bots(db).await {
             Ok(bots) => {
                 for bot_info in bots {
                     if bot_info.clone().is_running {
@@ -39,22 +39,39 @@ impl BotManager {
         }
     }
 
-    /// Create new bot to a manager
+    /// Create new bot to a manager, a bot with the same id is stopped and
+    /// replaced. The previous bot is done before the new one starts, so two
+    /// engines never trade for the same bot.
     pub async fn create_bot(&mut self, config: BotConfig, app_state: Arc<AppState>) {
+        if let Some(mut previous) = self.bots.remove(&config.id) {
+            previous.stop(Some(Drain::Leave)).await;
+        }
         let mut bot = Bot::new(config.clone());
         bot.start(app_state).await;
         self.bots.insert(config.id.clone(), bot);
     }
 
-    pub async fn stop_bot(&mut self, id: &str) {
+    /// Whether a bot runs or is going to be restarted
+    pub fn is_running(&self, id: &str) -> bool {
+        self.bots.get(id).is_some_and(Bot::is_running)
+    }
+
+    /// Stop a bot, its orders and positions are drained as its config says
+    /// when `drain` is none
+    pub async fn stop_bot(&mut self, id: &str, drain: Option<Drain>) {
         if let Some(bot) = self.bots.get_mut(id) {
-            bot.stop().await;
+            bot.stop(drain).await;
         }
     }
 
-    pub async fn remove_bot(&mut self, id: &str) {
+    pub async fn remove_bot(&mut self, id: &str, drain: Option<Drain>) {
         if let Some(mut bot) = self.bots.remove(id) {
-            bot.stop().await;
+            bot.stop(drain).await;
         }
     }
+
+    /// Stop every bot, they wind down together
+    pub async fn stop_all(&mut self, drain: Option<Drain>) {
+        futures_util::future::join_all(self.bots.values_mut().map(|bot| bot.stop(drain))).await;
+    }
 }
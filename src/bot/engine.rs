@@ -0,0 +1,821 @@
+use crate::base::AppState;
+use crate::bot::strategies::{Signal, Strategy, StrategyContext};
+use crate::bot::{BotConfig, Drain, MarketType};
+use crate::core::allocator::Allocation;
+use crate::core::bar_aggregator::{history, BarAggregator};
+use crate::core::order_tracker::OrderUpdate;
+use crate::core::risk::same_symbol;
+use crate::core::sizing::SizingInput;
+use crate::dao::bot::{get_bot_capital, set_bot_capital};
+use crate::dao::fill::get_bot_fills;
+use crate::error::RequestError;
+use crate::handlers::account::get_account;
+use crate::handlers::bar::get_bars;
+use crate::handlers::halt::close_bot_positions;
+use crate::handlers::market::{get_positions, is_market_open};
+use crate::handlers::order::{cancel_open_orders, place_order};
+use crate::models::bar::{Bar, BarQueryParams};
+use crate::models::order::{bot_client_order_id, CancelOrdersParams, Order, OrderStatus, StopLoss};
+use crate::models::signal::SignalOutcome;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::{OrderClass, Side, TimeInForce, Type};
+use crate::models::{num_from_f64, num_to_f64};
+use crate::stream::{MarketEvent, STREAM_TIMEFRAME};
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::KeyValue;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{interval, Instant};
+
+/// Time between the checks of a closed market
+const MARKET_CLOSED_WAIT: Duration = Duration::from_secs(3600);
+
+/// Time given to a stopped bot to cancel its orders and close its positions
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Runs a [`Strategy`] against a broker.
+///
+/// The engine owns all the I/O of a bot: it fetches the bars and feeds the
+/// new ones to the strategy oldest first, then sizes and submits the orders
+/// of the resulting signals. Bars of the market stream are handled as soon
+/// as they are published, polling catches up on the other timeframes and on
+/// the bars missed while the stream was down.
+pub struct ExecutionEngine {
+    state: Arc<AppState>,
+    config: BotConfig,
+    strategy: Box<dyn Strategy>,
+    // timestamp of the latest bar fed, per symbol and timeframe
+    last_seen: HashMap<(String, Timeframe), DateTime<Utc>>,
+    // builds the higher timeframes from the 1 minute bars
+    aggregator: BarAggregator,
+    // whether the history of the timeframes was fed
+    warmed_up: bool,
+    // open orders placed by the bot
+    orders: HashMap<String, OrderStatus>,
+    // symbol and side of the open legs of the orders placed by the bot
+    legs: HashMap<String, (String, Side)>,
+    // latest bars of the first timeframe, oldest first, per symbol
+    history: HashMap<String, VecDeque<Bar>>,
+    buy_order_hist: Histogram<f64>,
+    sell_order_hist: Histogram<f64>,
+}
+
+impl ExecutionEngine {
+    pub fn new(state: Arc<AppState>, config: BotConfig, strategy: Box<dyn Strategy>) -> Self {
+        let buy_order_hist = state.meter.f64_histogram("buy_order_hist").init();
+        let sell_order_hist = state.meter.f64_histogram("sell_order_hist").init();
+        let aggregator = BarAggregator::new(config.market.clone(), &config.timeframes);
+        Self {
+            state,
+            config,
+            strategy,
+            last_seen: HashMap::new(),
+            aggregator,
+            warmed_up: false,
+            orders: HashMap::new(),
+            legs: HashMap::new(),
+            history: HashMap::new(),
+            buy_order_hist,
+            sell_order_hist,
+        }
+    }
+
+    /// Poll the broker and listen to the market stream until a stop is
+    /// requested on `stop`. A stop waits for the bars being handled, signals
+    /// are never left half submitted or unrecorded. The orders and positions
+    /// are wound down by [`drain_bot`] afterwards.
+    pub async fn run(mut self, mut stop: watch::Receiver<Option<Drain>>) {
+        let mut interval = interval(self.strategy.poll_interval());
+        let market_stream = self.state.market_stream.clone();
+        let mut subscription = market_stream.subscribe(&self.config.market, &self.config.symbols);
+        let mut order_updates = self.state.order_tracker.subscribe();
+        // polls are skipped until then while the market is closed
+        let mut closed_until: Option<Instant> = None;
+        self.open_book().await;
+        self.state.journal.run_started(&self.config).await;
+
+        loop {
+            tokio::select! {
+                _ = stop.changed() => break,
+                _ = interval.tick() => {
+                    if closed_until.is_some_and(|until| Instant::now() < until) {
+                        continue;
+                    }
+                    match market_open(&self.state, &self.config).await {
+                        Some(true) => {
+                            closed_until = None;
+                            self.poll().await;
+                        }
+                        Some(false) => {
+                            tracing::info!("Equity market is closed. Waiting for next check.");
+                            closed_until = Some(Instant::now() + MARKET_CLOSED_WAIT);
+                        }
+                        None => {}
+                    }
+                }
+                Some(MarketEvent::Bar { symbol, bar }) = subscription.recv() => {
+                    self.on_stream_bar(&symbol, &bar).await;
+                }
+                Ok(update) = order_updates.recv() => self.on_order_update(&update),
+            }
+        }
+    }
+
+    /// Open the book of the bot from its recorded fills
+    async fn open_book(&self) {
+        let mut fills = match get_bot_fills(&self.state.db, &self.config.id, None).await {
+            Ok(fills) => fills,
+            Err(e) => {
+                tracing::error!("Failed to get the fills of bot {}: {}", self.config.id, e);
+                vec![]
+            }
+        };
+        fills.reverse();
+        let capital = self.capital().await;
+        self.state.allocator.open(&self.config, &capital, &fills);
+    }
+
+    /// Capital of the allocation of the bot, the one recorded when it first
+    /// started. A percentage allocation is taken out of the account equity
+    /// then.
+    async fn capital(&self) -> Num {
+        let Some(allocation) = &self.config.allocation else {
+            return Num::from(0);
+        };
+        match get_bot_capital(&self.state.db, &self.config.id).await {
+            Ok(Some(capital)) => return capital,
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to get the capital of bot {}: {}", self.config.id, e);
+            }
+        }
+
+        let equity = match allocation {
+            Allocation::Percent(_) => match get_account(&self.state).await {
+                Ok(account) => account.equity,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to get the equity allocated to bot {}: {:?}",
+                        self.config.id,
+                        e
+                    );
+                    return Num::from(0);
+                }
+            },
+            Allocation::Amount(_) => Num::from(0),
+        };
+        let capital = allocation.capital(&equity);
+        if let Err(e) = set_bot_capital(&self.state.db, &self.config.id, &capital).await {
+            tracing::error!(
+                "Failed to record the capital of bot {}: {}",
+                self.config.id,
+                e
+            );
+        }
+        capital
+    }
+
+    /// Positions seen by the strategy, the ones of its book for a bot with
+    /// an allocation
+    async fn positions(&self) -> Option<HashMap<String, f64>> {
+        if self.config.allocation.is_some() {
+            if let Some(portfolio) = self.state.allocator.portfolio(&self.config.id) {
+                return Some(
+                    portfolio
+                        .positions
+                        .into_iter()
+                        .map(|p| (p.symbol, num_to_f64(&p.qty)))
+                        .collect(),
+                );
+            }
+        }
+        match get_positions(self.state.as_ref()).await {
+            Ok(positions) => Some(
+                positions
+                    .into_iter()
+                    .map(|p| (p.symbol, num_to_f64(&p.qty)))
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::error!("Failed to get positions: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Follow the orders placed by the bot and let the strategy know about
+    /// their fills, cancels and rejects
+    pub fn on_order_update(&mut self, update: &OrderUpdate) {
+        if !self.orders.contains_key(&update.order.id) {
+            return;
+        }
+        tracing::info!(
+            "Bot {} order {} is {}",
+            self.config.id,
+            update.order.id,
+            update.status
+        );
+        if update.status.is_terminal() {
+            self.orders.remove(&update.order.id);
+            self.legs.remove(&update.order.id);
+        } else {
+            self.orders.insert(update.order.id.clone(), update.status);
+        }
+        self.strategy.on_order_update(update);
+    }
+
+    /// Cancel the open legs of the bot trading `symbol` towards `side`, the
+    /// protective stops of the position an exit closes would trade after it.
+    /// False when one of them cannot be canceled.
+    async fn cancel_legs(&mut self, symbol: &str, side: &Side) -> bool {
+        let legs: Vec<String> = self
+            .legs
+            .iter()
+            .filter(|(_, (leg_symbol, leg_side))| {
+                same_symbol(leg_symbol, symbol) && leg_side == side
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut canceled = true;
+        for id in legs {
+            match self.state.broker.cancel_order(&id).await {
+                Ok(()) => {
+                    self.legs.remove(&id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to cancel leg {} of {}: {:?}", id, symbol, e);
+                    // a leg closed meanwhile is forgotten, the exit is left
+                    // to the next bars with the position it left
+                    let closed = self.state.broker.get_order(&id).await.is_ok_and(|order| {
+                        matches!(
+                            order.status.as_str(),
+                            "filled" | "canceled" | "expired" | "rejected" | "replaced"
+                        )
+                    });
+                    if closed {
+                        self.legs.remove(&id);
+                    }
+                    canceled = false;
+                }
+            }
+        }
+        canceled
+    }
+
+    /// Open orders placed by the bot, with their last known status
+    pub fn open_orders(&self) -> &HashMap<String, OrderStatus> {
+        &self.orders
+    }
+
+    /// Feed a bar of the market stream to the timeframes it belongs to and
+    /// to the ones built from it, and execute the signals right away
+    pub async fn on_stream_bar(&mut self, symbol: &str, bar: &Bar) {
+        // the history comes first, the next poll catches up on the bar
+        if !self.warmed_up {
+            return;
+        }
+        let completed = self.aggregator.push_bar(symbol, bar);
+        let streamed = self.config.timeframes.contains(&STREAM_TIMEFRAME);
+        if !streamed && completed.is_empty() {
+            return;
+        }
+
+        let Some(positions) = self.positions().await else {
+            return;
+        };
+
+        let mut signals = Vec::new();
+        if streamed {
+            signals.extend(self.on_bar(symbol, STREAM_TIMEFRAME, bar, &positions));
+        }
+        for (timeframe, completed) in completed {
+            signals.extend(self.on_bar(symbol, timeframe, &completed, &positions));
+        }
+        self.execute(&signals).await;
+    }
+
+    /// Get the latest 1 minute bars for the symbols of the bot, the history
+    /// of all its timeframes on the first poll
+    async fn fetch_bars(&self) -> Option<HashMap<String, Vec<Bar>>> {
+        let request_type = match &self.config.market {
+            MarketType::Crypto => "crypto_data",
+            MarketType::Equity => "stock_data",
+        };
+        let bars = self
+            .strategy
+            .warmup()
+            .max(self.config.sizing.history(self.config.volatility_window));
+        let now = Utc::now();
+        let (start, limit) = match self.config.timeframes.iter().max_by_key(|t| t.minutes()) {
+            Some(timeframe) if !self.warmed_up => {
+                history(&self.config.market, *timeframe, bars, now)
+            }
+            _ => (
+                now - chrono::Duration::days(self.config.volatility_window.max(2) as i64),
+                bars,
+            ),
+        };
+        let timeframe = Timeframe::BASE;
+        let params = BarQueryParams::new(timeframe, start, limit);
+        match get_bars(
+            self.state.as_ref(),
+            &self.config.symbols,
+            &params,
+            request_type,
+        )
+        .await
+        {
+            Ok(bars) => Some(bars),
+            Err(e) => {
+                tracing::error!("Failed to get bars for timeframe {}: {:?}", timeframe, e);
+                None
+            }
+        }
+    }
+
+    /// Feed the bars published since the last poll and execute the signals
+    /// of the latest ones.
+    ///
+    /// A single request for the 1 minute bars is made per poll and the
+    /// higher timeframes are built from them, their history too on the first
+    /// poll, so every bar has the alignment of the live ones.
+    pub async fn poll(&mut self) {
+        let Some(positions) = self.positions().await else {
+            return;
+        };
+
+        let mut signals = Vec::new();
+        if let Some(all_bars) = self.fetch_bars().await {
+            self.warmed_up = true;
+            let fed = self.config.timeframes.contains(&Timeframe::BASE);
+            for (symbol, bars) in all_bars {
+                let bars = oldest_first(bars);
+                let count = bars.len();
+                for (i, bar) in bars.iter().enumerate() {
+                    let mut bar_signals = Vec::new();
+                    if fed {
+                        bar_signals.extend(self.on_bar(&symbol, Timeframe::BASE, bar, &positions));
+                    }
+                    for (timeframe, completed) in self.aggregator.push_bar(&symbol, bar) {
+                        bar_signals.extend(self.on_bar(&symbol, timeframe, &completed, &positions));
+                    }
+                    if i + 1 == count {
+                        signals.extend(bar_signals);
+                    }
+                }
+            }
+        }
+
+        // bars without trades in their last minutes are never completed by
+        // a later one in a quiet market
+        let published = Utc::now() - Timeframe::BASE.duration();
+        for (symbol, timeframe, bar) in self.aggregator.flush(published) {
+            signals.extend(self.on_bar(&symbol, timeframe, &bar, &positions));
+        }
+
+        self.execute(&signals).await;
+    }
+
+    /// Feed one bar to the strategy, bars already seen are skipped
+    pub fn on_bar(
+        &mut self,
+        symbol: &str,
+        timeframe: Timeframe,
+        bar: &Bar,
+        positions: &HashMap<String, f64>,
+    ) -> Vec<Signal> {
+        let timestamp = match bar.timestamp.parse::<DateTime<Utc>>() {
+            Ok(timestamp) => timestamp,
+            Err(e) => {
+                tracing::warn!("Skipping bar with bad timestamp {}: {}", bar.timestamp, e);
+                return vec![];
+            }
+        };
+        let key = (symbol.to_string(), timeframe);
+        if self
+            .last_seen
+            .get(&key)
+            .is_some_and(|last| *last >= timestamp)
+        {
+            return vec![];
+        }
+        self.last_seen.insert(key, timestamp);
+        self.state.risk.on_bar(symbol, bar);
+        self.state.allocator.on_bar(symbol, bar);
+
+        // the sizing models measure the volatility on the first timeframe
+        if self.config.timeframes.first() == Some(&timeframe) {
+            let history = self.history.entry(symbol.to_string()).or_default();
+            history.push_back(bar.clone());
+            let len = self.config.sizing.history(self.config.volatility_window);
+            while history.len() > len {
+                history.pop_front();
+            }
+        }
+
+        let ctx = StrategyContext {
+            symbol,
+            timeframe,
+            position: position_in(positions, symbol),
+        };
+        self.strategy.on_bar(&ctx, bar)
+    }
+
+    /// Record what became of a signal in the journal
+    async fn record(
+        &self,
+        signal: &Signal,
+        qty: Option<&Num>,
+        outcome: SignalOutcome,
+        reason: Option<&str>,
+        order_id: Option<&str>,
+    ) {
+        self.state
+            .journal
+            .signal(&self.config.id, signal, qty, outcome, reason, order_id)
+            .await;
+    }
+
+    /// Size and submit the orders of `signals`, each signal is recorded with
+    /// its outcome
+    pub async fn execute(&mut self, signals: &[Signal]) {
+        if signals.is_empty() {
+            return;
+        }
+        if let Some(halt) = self.state.halt.halted(Some(&self.config.id)) {
+            tracing::info!(
+                "Bot {} is halted, ignoring {} signals: {}",
+                self.config.id,
+                signals.len(),
+                halt.reason
+            );
+            for signal in signals {
+                let reason = format!("halted: {}", halt.reason);
+                self.record(signal, None, SignalOutcome::Skipped, Some(&reason), None)
+                    .await;
+            }
+            return;
+        }
+
+        // a bot with an allocation sizes against its own book
+        let portfolio = self
+            .config
+            .allocation
+            .as_ref()
+            .and_then(|_| self.state.allocator.portfolio(&self.config.id));
+        let account = match portfolio {
+            Some(portfolio) => portfolio.account(),
+            None => match get_account(&self.state).await {
+                Ok(acc) => acc,
+                Err(e) => {
+                    tracing::error!("Failed to get account information: {:?}", e);
+                    let reason = format!("account unavailable: {}", e);
+                    for signal in signals {
+                        self.record(signal, None, SignalOutcome::Failed, Some(&reason), None)
+                            .await;
+                    }
+                    return;
+                }
+            },
+        };
+
+        for signal in signals {
+            let Some(price) = num_from_f64(signal.price) else {
+                tracing::warn!("Ignoring signal of {} without a price", signal.symbol);
+                self.record(signal, None, SignalOutcome::Skipped, Some("no price"), None)
+                    .await;
+                continue;
+            };
+            let asset = match self.state.broker.get_asset(&signal.symbol).await {
+                Ok(asset) => asset,
+                Err(e) => {
+                    tracing::error!("Failed to get asset {}: {:?}", signal.symbol, e);
+                    let reason = format!("asset unavailable: {}", e);
+                    self.record(signal, None, SignalOutcome::Failed, Some(&reason), None)
+                        .await;
+                    continue;
+                }
+            };
+            let stop_price = signal.stop_price.and_then(num_from_f64);
+            let bars = self
+                .history
+                .get(&signal.symbol)
+                .map(|history| history.iter().cloned().collect::<Vec<Bar>>())
+                .unwrap_or_default();
+            // exits trade the quantity of the position they close
+            let qty = match signal.qty.and_then(num_from_f64) {
+                Some(qty) => qty,
+                None => self.config.sizing.size(
+                    &SizingInput {
+                        account: &account,
+                        price: &price,
+                        stop_price: stop_price.as_ref(),
+                        bars: &bars,
+                        risk_per_trade: self.config.risk_per_trade,
+                        volatility_window: self.config.volatility_window,
+                    },
+                    &asset,
+                ),
+            };
+            tracing::debug!("position 'qty' calculated {}", qty);
+            if !qty.is_positive() {
+                let reason = "sized to zero";
+                self.record(
+                    signal,
+                    Some(&qty),
+                    SignalOutcome::Skipped,
+                    Some(reason),
+                    None,
+                )
+                .await;
+                continue;
+            }
+
+            if !self.cancel_legs(&signal.symbol, &signal.side).await {
+                let reason = "legs of the position not canceled";
+                self.record(
+                    signal,
+                    Some(&qty),
+                    SignalOutcome::Failed,
+                    Some(reason),
+                    None,
+                )
+                .await;
+                continue;
+            }
+
+            let order = Order {
+                client_order_id: Some(bot_client_order_id(&self.config.id)),
+                ..signal_order(signal, &price, qty.clone())
+            };
+            let placed = match place_order(&self.state, &order, Some(&self.config)).await {
+                Ok(placed) => placed,
+                Err(e) => {
+                    tracing::error!("Failed to place order for {}: {:?}", signal.symbol, e);
+                    let reason = e.to_string();
+                    self.record(signal, Some(&qty), outcome_of(&e), Some(&reason), None)
+                        .await;
+                    continue;
+                }
+            };
+            self.orders.insert(placed.id.clone(), OrderStatus::New);
+            for leg in placed.legs.iter().flatten() {
+                self.orders.insert(leg.id.clone(), OrderStatus::New);
+                self.legs
+                    .insert(leg.id.clone(), (leg.symbol.clone(), leg.side.clone()));
+            }
+            self.record(
+                signal,
+                Some(&qty),
+                SignalOutcome::Placed,
+                None,
+                Some(&placed.id),
+            )
+            .await;
+
+            let attributes = [
+                KeyValue::new("bot_id", self.config.id.clone()),
+                KeyValue::new("bot_name", self.config.name.clone()),
+                KeyValue::new("symbol", signal.symbol.clone()),
+            ];
+            match signal.side {
+                Side::Buy => self.buy_order_hist.record(num_to_f64(&qty), &attributes),
+                Side::Sell => self.sell_order_hist.record(num_to_f64(&qty), &attributes),
+            }
+            tracing::info!(
+                "Order {} placed: {:?} {} shares of {}",
+                placed.id,
+                signal.side,
+                qty,
+                signal.symbol
+            );
+        }
+    }
+}
+
+/// Bars sorted by time, the brokers return the latest first
+fn oldest_first(mut bars: Vec<Bar>) -> Vec<Bar> {
+    bars.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    bars
+}
+
+/// Position held in `symbol`, alpaca reports the crypto pairs without their
+/// slash
+fn position_in(positions: &HashMap<String, f64>, symbol: &str) -> f64 {
+    positions
+        .iter()
+        .find(|(held, _)| same_symbol(held, symbol))
+        .map_or(0.0, |(_, qty)| *qty)
+}
+
+/// Outcome of a signal whose order failed, refused by the checks or lost on
+/// the way to the broker
+fn outcome_of(e: &RequestError) -> SignalOutcome {
+    match e {
+        RequestError::InvalidOrder(_) | RequestError::Risk(_) | RequestError::Halted(_) => {
+            SignalOutcome::Rejected
+        }
+        _ => SignalOutcome::Failed,
+    }
+}
+
+/// Limit order of `qty` for a signal at `price`, with its protective stop attached.
+///
+/// Prices are rounded to the tick size of the asset when the order is placed.
+fn signal_order(signal: &Signal, price: &Num, qty: Num) -> Order {
+    let order = Order {
+        symbol: signal.symbol.clone(),
+        qty: Some(qty),
+        side: signal.side.clone(),
+        order_type: Type::Limit,
+        time_in_force: TimeInForce::Day,
+        limit_price: Some(match signal.side {
+            Side::Buy => price * Num::new(1001, 1000),
+            Side::Sell => price * Num::new(999, 1000),
+        }),
+        ..Order::default()
+    };
+
+    match signal.stop_price.and_then(num_from_f64) {
+        Some(stop_price) => Order {
+            order_class: OrderClass::Oto,
+            stop_loss: Some(StopLoss {
+                stop_price,
+                limit_price: None,
+            }),
+            ..order
+        },
+        None => order,
+    }
+}
+
+/// Whether the market of the bot is open, none when it cannot be told
+async fn market_open(state: &Arc<AppState>, config: &BotConfig) -> Option<bool> {
+    match config.market {
+        MarketType::Crypto => Some(true), // Crypto markets are typically always open
+        MarketType::Equity => match is_market_open(state).await {
+            Ok(open) => Some(open),
+            Err(e) => {
+                tracing::error!("Failed to check if market is open: {:?}", e);
+                None
+            }
+        },
+    }
+}
+
+/// Wind down the open orders and the positions of a stopped bot as `drain`
+/// says. What is still open after [`DRAIN_TIMEOUT`] is logged and left.
+pub async fn drain_bot(state: &AppState, config: &BotConfig, drain: Drain) {
+    tracing::info!("Stopping bot {}, {} on stop", config.id, drain);
+    if drain == Drain::Leave {
+        return;
+    }
+    if tokio::time::timeout(DRAIN_TIMEOUT, wind_down(state, config, drain))
+        .await
+        .is_err()
+    {
+        tracing::error!("Bot {} did not {} in {:?}", config.id, drain, DRAIN_TIMEOUT);
+        log_left_behind(state, config, drain).await;
+    }
+}
+
+async fn wind_down(state: &AppState, config: &BotConfig, drain: Drain) {
+    let params = CancelOrdersParams {
+        bot_id: Some(config.id.clone()),
+        ..CancelOrdersParams::default()
+    };
+    match cancel_open_orders(state, &params).await {
+        Ok(canceled) => {
+            tracing::info!("Bot {} canceled {} orders", config.id, canceled.len())
+        }
+        Err(e) => tracing::error!("Bot {} cannot cancel its orders: {:?}", config.id, e),
+    }
+    if drain == Drain::Flatten {
+        match close_bot_positions(state, &config.id).await {
+            Ok(closed) => {
+                tracing::info!("Bot {} closed {} positions", config.id, closed.len())
+            }
+            Err(e) => {
+                tracing::error!("Bot {} cannot close its positions: {:?}", config.id, e)
+            }
+        }
+    }
+}
+
+/// Log the orders, and the positions when flattening, a drain left open
+async fn log_left_behind(state: &AppState, config: &BotConfig, drain: Drain) {
+    let params = CancelOrdersParams {
+        bot_id: Some(config.id.clone()),
+        ..CancelOrdersParams::default()
+    };
+    match state.broker.list_open_orders(None).await {
+        Ok(orders) => {
+            let left: Vec<&str> = orders
+                .iter()
+                .filter(|order| params.matches(order))
+                .map(|order| order.id.as_str())
+                .collect();
+            if !left.is_empty() {
+                tracing::warn!("Bot {} left open orders {:?}", config.id, left);
+            }
+        }
+        Err(e) => tracing::error!("Cannot list the orders left by bot {}: {:?}", config.id, e),
+    }
+    if drain != Drain::Flatten {
+        return;
+    }
+    if let Some(portfolio) = state.allocator.portfolio(&config.id) {
+        let left: Vec<String> = portfolio
+            .positions
+            .into_iter()
+            .filter(|position| !position.qty.is_zero())
+            .map(|position| format!("{} {}", position.qty, position.symbol))
+            .collect();
+        if !left.is_empty() {
+            tracing::warn!("Bot {} left positions {:?}", config.id, left);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(value: &str) -> Num {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn signal_with_stop_opens_an_oto_order() {
+        let signal = Signal::new("AAPL", Side::Buy, 199.8).with_stop(190.25);
+        let order = signal_order(&signal, &num("199.8"), num("3"));
+
+        assert_eq!(order.order_class, OrderClass::Oto);
+        assert_eq!(order.limit_price, Some(num("199.9998")));
+        assert_eq!(
+            order.stop_loss,
+            Some(StopLoss {
+                stop_price: num("190.25"),
+                limit_price: None
+            })
+        );
+        assert!(order.validate().is_ok());
+    }
+
+    #[test]
+    fn signal_without_stop_is_a_simple_limit_order() {
+        let signal = Signal::new("DOGE/USD", Side::Sell, 0.1234);
+        let order = signal_order(&signal, &num("0.1234"), num("1500.5"));
+
+        assert_eq!(order.order_class, OrderClass::Simple);
+        assert_eq!(order.qty, Some(num("1500.5")));
+        assert_eq!(order.limit_price, Some(num("0.1232766")));
+        assert!(order.stop_loss.is_none());
+    }
+
+    #[test]
+    fn positions_of_crypto_pairs_are_found_without_their_slash() {
+        let positions = HashMap::from([("BTCUSD".to_string(), 0.5), ("AAPL".to_string(), 3.0)]);
+        assert_eq!(position_in(&positions, "BTC/USD"), 0.5);
+        assert_eq!(position_in(&positions, "AAPL"), 3.0);
+        assert_eq!(position_in(&positions, "ETH/USD"), 0.0);
+    }
+
+    #[test]
+    fn bars_are_fed_oldest_first() {
+        let bars = [
+            "2024-01-01T00:02:00Z",
+            "2024-01-01T00:00:00Z",
+            "2024-01-01T00:01:00Z",
+        ]
+        .into_iter()
+        .map(|timestamp| Bar {
+            close_price: 1.0,
+            high_price: 1.0,
+            low_price: 1.0,
+            n: 1,
+            open_price: 1.0,
+            timestamp: timestamp.to_string(),
+            volume: 1.0,
+            vw: 1.0,
+        })
+        .collect();
+
+        let timestamps: Vec<String> = oldest_first(bars)
+            .into_iter()
+            .map(|bar| bar.timestamp)
+            .collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                "2024-01-01T00:00:00Z",
+                "2024-01-01T00:01:00Z",
+                "2024-01-01T00:02:00Z"
+            ]
+        );
+    }
+}
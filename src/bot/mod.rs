@@ -1,43 +1,76 @@
 pub mod bot_manager;
-mod strategies;
+pub mod engine;
+pub mod strategies;
+pub mod supervisor;
 
-use crate::base::{AppState, Client};
-use crate::bot::strategies::mean_reversion::mean_reversion_strategy;
-use crate::bot::strategies::smart_money::smart_money_strategy;
+use crate::base::AppState;
+use crate::bot::engine::{drain_bot, ExecutionEngine};
+use crate::bot::strategies::bollinger::BollingerParams;
+use crate::bot::strategies::build_strategy;
+use crate::bot::strategies::donchian::DonchianParams;
+use crate::bot::strategies::macd::MacdParams;
+use crate::bot::strategies::mean_reversion::MeanReversionParams;
+use crate::bot::strategies::moving_average::MovingAverageParams;
+use crate::bot::strategies::rsi::RsiParams;
+use crate::bot::strategies::smart_money::SmartMoneyParams;
+use crate::bot::strategies::vwap::VwapParams;
+use crate::bot::strategies::StrategyParams;
+use crate::bot::supervisor::{supervise, BotHealth, RestartPolicy, SharedHealth};
+use crate::core::allocator::Allocation;
+use crate::core::sizing::SizingModel;
+use crate::models::timeframe::Timeframe;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::fmt::{write, Formatter};
+use std::fmt::Formatter;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use tokio::sync::watch;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Strategy of a bot with its parameters,
+/// `{"kind": "mean_reversion", "params": {"lookback": 20}}`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", content = "params", rename_all = "snake_case")]
 pub enum BotStrategy {
-    MeanReversion,
-    SmartMoney,
+    MeanReversion(MeanReversionParams),
+    SmartMoney(SmartMoneyParams),
+    MovingAverageCrossover(MovingAverageParams),
+    Rsi(RsiParams),
+    Bollinger(BollingerParams),
+    Macd(MacdParams),
+    Donchian(DonchianParams),
+    VwapReversion(VwapParams),
 }
 
-impl fmt::Display for BotStrategy {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match *self {
-            BotStrategy::MeanReversion => write!(f, "MeanReversion"),
-            BotStrategy::SmartMoney => write!(f, "SmartMoney"),
+impl BotStrategy {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BotStrategy::MeanReversion(_) => "mean_reversion",
+            BotStrategy::SmartMoney(_) => "smart_money",
+            BotStrategy::MovingAverageCrossover(_) => "moving_average_crossover",
+            BotStrategy::Rsi(_) => "rsi",
+            BotStrategy::Bollinger(_) => "bollinger",
+            BotStrategy::Macd(_) => "macd",
+            BotStrategy::Donchian(_) => "donchian",
+            BotStrategy::VwapReversion(_) => "vwap_reversion",
         }
     }
-}
 
-impl FromStr for BotStrategy {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "MeanReversion" => Ok(BotStrategy::MeanReversion),
-            "SmartMoney" => Ok(BotStrategy::SmartMoney),
-            _ => Err(()),
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BotStrategy::MeanReversion(params) => params.validate(),
+            BotStrategy::SmartMoney(params) => params.validate(),
+            BotStrategy::MovingAverageCrossover(params) => params.validate(),
+            BotStrategy::Rsi(params) => params.validate(),
+            BotStrategy::Bollinger(params) => params.validate(),
+            BotStrategy::Macd(params) => params.validate(),
+            BotStrategy::Donchian(params) => params.validate(),
+            BotStrategy::VwapReversion(params) => params.validate(),
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum MarketType {
     Crypto,
     Equity,
@@ -61,25 +94,131 @@ impl fmt::Display for MarketType {
         }
     }
 }
+
+/// What a bot does with its open orders and positions when it is stopped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Drain {
+    /// Leave them as they are
+    #[default]
+    Leave,
+    /// Cancel the open orders of the bot
+    CancelOrders,
+    /// Cancel the open orders of the bot and close the positions in its
+    /// symbols
+    Flatten,
+}
+
+impl FromStr for Drain {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "leave" => Ok(Drain::Leave),
+            "cancel_orders" => Ok(Drain::CancelOrders),
+            "flatten" => Ok(Drain::Flatten),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Drain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Drain::Leave => write!(f, "leave"),
+            Drain::CancelOrders => write!(f, "cancel_orders"),
+            Drain::Flatten => write!(f, "flatten"),
+        }
+    }
+}
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BotConfig {
     pub id: String,
     pub name: String,
     pub market: MarketType,
-    pub trading_strategy: BotStrategy,
+    pub strategy: BotStrategy,
     pub symbols: Vec<String>,
-    pub lookback: usize,
-    pub threshold: f64,
     pub risk_per_trade: f64,
     pub max_positions: usize,
-    pub timeframes: Vec<String>,
+    /// Timeframes of the bars fed to the strategy, the first one is traded
+    pub timeframes: Vec<Timeframe>,
+    /// Number of bars the volatility of the sizing models is measured on
     pub volatility_window: usize,
-    pub volatility_threshold: f64,
+    /// Sizing of the orders, a fixed fraction of the equity by default
+    #[serde(default)]
+    pub sizing: SizingModel,
+    /// Capital of the bot, the bot shares the account without one
+    #[serde(default)]
+    pub allocation: Option<Allocation>,
+    /// What to do when the bot crashes, it stays down by default
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// What to do with the orders and positions of the bot when it is
+    /// stopped, they are left by default
+    #[serde(default)]
+    pub on_stop: Drain,
+}
+
+impl BotConfig {
+    /// Config with the fields of a JSON merge patch changed, the id is kept
+    pub fn patch(&self, patch: serde_json::Value) -> Result<BotConfig, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        merge(&mut value, patch);
+        let mut config: BotConfig = serde_json::from_value(value)?;
+        config.id = self.id.clone();
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+impl BotConfig {
+    /// Mean reversion bot trading AAPL on the minute bars, the tests change
+    /// the fields they need with the struct update syntax
+    pub fn test_default() -> Self {
+        BotConfig {
+            id: "bot".to_string(),
+            name: "bot".to_string(),
+            market: MarketType::Equity,
+            strategy: BotStrategy::MeanReversion(MeanReversionParams {
+                lookback: 5,
+                ..MeanReversionParams::default()
+            }),
+            symbols: vec!["AAPL".to_string()],
+            risk_per_trade: 0.1,
+            max_positions: 1,
+            timeframes: vec![Timeframe::Minute(1)],
+            volatility_window: 5,
+            sizing: SizingModel::default(),
+            allocation: None,
+            restart: RestartPolicy::default(),
+            on_stop: Drain::default(),
+        }
+    }
+}
+
+/// Apply a JSON merge patch, a null removes the field
+fn merge(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge(target.entry(key).or_insert(serde_json::Value::Null), value);
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
 }
 
 pub struct Bot {
     pub config: BotConfig,
+    /// Supervisor of the engine task
     pub handle: Option<tokio::task::JoinHandle<()>>,
+    // stop request, with what to do with the orders and positions
+    stop: Option<watch::Sender<Option<Drain>>>,
+    pub health: SharedHealth,
 }
 
 impl Bot {
@@ -87,38 +226,125 @@ impl Bot {
         Bot {
             config,
             handle: None,
+            stop: None,
+            health: SharedHealth::default(),
         }
     }
 
+    /// Run the engine of the bot under a supervisor restarting it as its
+    /// restart policy says
     pub async fn start(&mut self, state: Arc<AppState>) {
         let config = self.config.clone();
-        let handle = tokio::spawn(async move {
-            match config.trading_strategy {
-                BotStrategy::MeanReversion => mean_reversion_strategy(state, config).await,
-                BotStrategy::SmartMoney => smart_money_strategy(state, config).await,
-            }
-        });
+        let (stop, stop_rx) = watch::channel(None);
+        *self.health.lock().unwrap() = BotHealth::default();
+        let journal = state.journal.clone();
+        let drain = {
+            let (state, config) = (state.clone(), config.clone());
+            move |drain| async move { drain_bot(&state, &config, drain).await }
+        };
+        let run = move |stop| {
+            let strategy = build_strategy(&config, &state.meter);
+            ExecutionEngine::new(state.clone(), config.clone(), strategy).run(stop)
+        };
+        let handle = tokio::spawn(supervise(
+            self.config.id.clone(),
+            self.config.restart.clone(),
+            self.health.clone(),
+            stop_rx,
+            journal,
+            run,
+            drain,
+        ));
         self.handle = Some(handle);
+        self.stop = Some(stop);
     }
 
-    pub async fn stop(&mut self) {
+    /// Ask the engine to stop and wait for it to wind down as `drain` says,
+    /// as the config of the bot says when none
+    pub async fn stop(&mut self, drain: Option<Drain>) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(Some(drain.unwrap_or(self.config.on_stop)));
+        }
         if let Some(handle) = self.handle.take() {
-            handle.abort()
+            let _ = handle.await;
         }
     }
+
+    /// Whether the bot runs or is going to be restarted
+    pub fn is_running(&self) -> bool {
+        self.health.lock().unwrap().is_active()
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct BotInfo {
     pub config: BotConfig,
     pub is_running: bool,
+    /// Status of the task of the bot, with its last failure
+    pub health: BotHealth,
 }
 
 impl From<&Bot> for BotInfo {
     fn from(bot: &Bot) -> Self {
+        let health = bot.health.lock().unwrap().clone();
         BotInfo {
             config: bot.config.clone(),
-            is_running: bot.handle.is_some(),
+            is_running: health.is_active(),
+            health,
         }
     }
 }
+
+/// Recorded run of a bot, open while `stopped_at` is none
+#[derive(Debug, Clone, Serialize)]
+pub struct BotRun {
+    pub id: i64,
+    pub bot_id: String,
+    /// Config the bot ran with
+    pub config: serde_json::Value,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub stop_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn patch_config() {
+        let config = BotConfig {
+            allocation: Some(Allocation::Amount(1000.into())),
+            ..BotConfig::test_default()
+        };
+
+        let patched = config
+            .patch(json!({
+                "id": "other",
+                "symbols": ["MSFT", "TSLA"],
+                "sizing": {"model": "fixed_notional", "notional": 500.0},
+                "allocation": null,
+                "strategy": {"params": {"lookback": 8}}
+            }))
+            .unwrap();
+        assert_eq!(patched.id, "bot");
+        assert_eq!(patched.symbols, vec!["MSFT", "TSLA"]);
+        assert_eq!(
+            patched.sizing,
+            SizingModel::FixedNotional { notional: 500.0 }
+        );
+        assert_eq!(patched.allocation, None);
+        assert_eq!(
+            patched.strategy,
+            BotStrategy::MeanReversion(MeanReversionParams {
+                lookback: 8,
+                ..MeanReversionParams::default()
+            })
+        );
+        assert_eq!(patched.volatility_window, 5);
+        assert!(config
+            .patch(json!({"strategy": {"params": {"lookback": "five"}}}))
+            .is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::bot::strategies::{
+    signal_towards, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ta::indicators::BollingerBands;
+use ta::Next;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BollingerParams {
+    /// Number of bars the mean and the deviation are measured on
+    pub period: usize,
+    /// Number of standard deviations between the mean and the bands
+    pub multiplier: f64,
+}
+
+impl Default for BollingerParams {
+    fn default() -> Self {
+        Self {
+            period: 20,
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl StrategyParams for BollingerParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "period",
+            kind: "integer",
+            description: "Number of bars the mean and the deviation are measured on",
+        },
+        Param {
+            name: "multiplier",
+            kind: "number",
+            description: "Number of standard deviations between the mean and the bands",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.period < 2 {
+            return Err("period must be at least 2".to_string());
+        }
+        if !(self.multiplier > 0.0 && self.multiplier.is_finite()) {
+            return Err("multiplier must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Bollinger band reversion: buy a close under the lower band and sell a
+/// close above the upper one
+pub struct BollingerReversion {
+    timeframe: Option<Timeframe>,
+    params: BollingerParams,
+    // bands and number of bars seen, per symbol
+    bands: HashMap<String, (BollingerBands, usize)>,
+}
+
+impl BollingerReversion {
+    pub fn new(config: &BotConfig, params: &BollingerParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            params: params.clone(),
+            bands: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for BollingerReversion {
+    fn warmup(&self) -> usize {
+        self.params.period
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let params = &self.params;
+        let (bands, seen) = self.bands.entry(ctx.symbol.to_string()).or_insert_with(|| {
+            (
+                BollingerBands::new(params.period, params.multiplier).unwrap(),
+                0,
+            )
+        });
+        let bands = bands.next(bar.close_price);
+        *seen += 1;
+        if *seen < params.period {
+            return vec![];
+        }
+        tracing::debug!(
+            "Bollinger bands of {}: {} - {}",
+            ctx.symbol,
+            bands.lower,
+            bands.upper
+        );
+
+        if bar.close_price < bands.lower {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if bar.close_price > bands.upper {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn strategy() -> BollingerReversion {
+        let params = BollingerParams {
+            period: 3,
+            multiplier: 1.0,
+        };
+        BollingerReversion::new(&config(), &params)
+    }
+
+    #[test]
+    fn trade_back_inside_the_bands() {
+        let mut strategy = strategy();
+        for (i, close) in [10.0, 11.0, 10.0].into_iter().enumerate() {
+            assert!(strategy.on_bar(&ctx(0.0), &bar(i, close, 1.0)).is_empty());
+        }
+        // lower band at 7.63
+        assert_eq!(
+            strategy.on_bar(&ctx(0.0), &bar(3, 7.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Buy, 7.0)]
+        );
+        assert!(strategy.on_bar(&ctx(1.0), &bar(4, 8.0, 1.0)).is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx(1.0), &bar(5, 12.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 12.0)]
+        );
+    }
+}
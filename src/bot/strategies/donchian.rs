@@ -0,0 +1,134 @@
+use crate::bot::strategies::{
+    signal_towards, BarWindow, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DonchianParams {
+    /// Number of bars before the current one making the channel
+    pub period: usize,
+}
+
+impl Default for DonchianParams {
+    fn default() -> Self {
+        Self { period: 20 }
+    }
+}
+
+impl StrategyParams for DonchianParams {
+    const PARAMS: &'static [Param] = &[Param {
+        name: "period",
+        kind: "integer",
+        description: "Number of bars before the current one making the channel",
+    }];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.period == 0 {
+            return Err("period must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Donchian breakout: buy a close above the highest high of the previous
+/// bars and sell a close under their lowest low
+pub struct DonchianBreakout {
+    timeframe: Option<Timeframe>,
+    period: usize,
+    windows: HashMap<String, BarWindow>,
+}
+
+impl DonchianBreakout {
+    pub fn new(config: &BotConfig, params: &DonchianParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            period: params.period,
+            windows: HashMap::new(),
+        }
+    }
+}
+
+/// Highest high and lowest low of the bars
+fn channel(window: &BarWindow) -> (f64, f64) {
+    window
+        .bars()
+        .fold((f64::MIN, f64::MAX), |(high, low), bar| {
+            (high.max(bar.high_price), low.min(bar.low_price))
+        })
+}
+
+impl Strategy for DonchianBreakout {
+    fn warmup(&self) -> usize {
+        self.period + 1
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let window = self
+            .windows
+            .entry(ctx.symbol.to_string())
+            .or_insert_with(|| BarWindow::new(self.period));
+        // the channel is made of the bars before this one
+        let channel = window.is_full().then(|| channel(window));
+        window.push(bar.clone());
+        let Some((high, low)) = channel else {
+            return vec![];
+        };
+
+        if bar.close_price > high {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if bar.close_price < low {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn strategy() -> DonchianBreakout {
+        DonchianBreakout::new(&config(), &DonchianParams { period: 3 })
+    }
+
+    #[test]
+    fn trade_the_breakouts_of_the_channel() {
+        let mut strategy = strategy();
+        for (i, close) in [10.0, 12.0, 11.0].into_iter().enumerate() {
+            assert!(strategy.on_bar(&ctx(0.0), &bar(i, close, 1.0)).is_empty());
+        }
+        // touching the high is no breakout
+        assert!(strategy.on_bar(&ctx(0.0), &bar(3, 12.0, 1.0)).is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx(0.0), &bar(4, 12.5, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Buy, 12.5)]
+        );
+        assert_eq!(
+            strategy.on_bar(&ctx(1.0), &bar(5, 10.5, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 10.5)]
+        );
+    }
+
+    #[test]
+    fn channel_of_highs_and_lows() {
+        let mut window = BarWindow::new(2);
+        let mut wide = bar(0, 10.0, 1.0);
+        wide.high_price = 11.0;
+        wide.low_price = 8.0;
+        window.push(wide);
+        window.push(bar(1, 10.5, 1.0));
+        assert_eq!(channel(&window), (11.0, 8.0));
+    }
+}
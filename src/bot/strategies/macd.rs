@@ -0,0 +1,163 @@
+use crate::bot::strategies::{
+    signal_towards, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ta::indicators::MovingAverageConvergenceDivergence;
+use ta::Next;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacdParams {
+    /// Period of the fast moving average
+    pub fast_period: usize,
+    /// Period of the slow moving average
+    pub slow_period: usize,
+    /// Period of the moving average of the MACD, its signal line
+    pub signal_period: usize,
+}
+
+impl Default for MacdParams {
+    fn default() -> Self {
+        Self {
+            fast_period: 12,
+            slow_period: 26,
+            signal_period: 9,
+        }
+    }
+}
+
+impl StrategyParams for MacdParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "fast_period",
+            kind: "integer",
+            description: "Period of the fast moving average",
+        },
+        Param {
+            name: "slow_period",
+            kind: "integer",
+            description: "Period of the slow moving average",
+        },
+        Param {
+            name: "signal_period",
+            kind: "integer",
+            description: "Period of the moving average of the MACD, its signal line",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.fast_period == 0 || self.fast_period >= self.slow_period {
+            return Err("fast_period must be positive and under slow_period".to_string());
+        }
+        if self.signal_period == 0 {
+            return Err("signal_period must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Per symbol state of the MACD trend
+struct MacdState {
+    macd: MovingAverageConvergenceDivergence,
+    histogram: f64,
+    seen: usize,
+}
+
+/// MACD trend: buy when the MACD crosses above its signal line and sell
+/// when it crosses below
+pub struct MacdTrend {
+    timeframe: Option<Timeframe>,
+    params: MacdParams,
+    states: HashMap<String, MacdState>,
+}
+
+impl MacdTrend {
+    pub fn new(config: &BotConfig, params: &MacdParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            params: params.clone(),
+            states: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for MacdTrend {
+    fn warmup(&self) -> usize {
+        self.params.slow_period + self.params.signal_period
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let params = &self.params;
+        let warmup = params.slow_period + params.signal_period;
+        let state = self
+            .states
+            .entry(ctx.symbol.to_string())
+            .or_insert_with(|| MacdState {
+                macd: MovingAverageConvergenceDivergence::new(
+                    params.fast_period,
+                    params.slow_period,
+                    params.signal_period,
+                )
+                .unwrap(),
+                histogram: 0.0,
+                seen: 0,
+            });
+        let previous = state.histogram;
+        state.histogram = state.macd.next(bar.close_price).histogram;
+        state.seen += 1;
+        if state.seen < warmup {
+            return vec![];
+        }
+
+        // the histogram is the MACD minus its signal line
+        if previous <= 0.0 && state.histogram > 0.0 {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if previous >= 0.0 && state.histogram < 0.0 {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn strategy() -> MacdTrend {
+        let params = MacdParams {
+            fast_period: 2,
+            slow_period: 4,
+            signal_period: 2,
+        };
+        MacdTrend::new(&config(), &params)
+    }
+
+    #[test]
+    fn trade_the_crosses_of_the_signal_line() {
+        let mut strategy = strategy();
+        for i in 0..6 {
+            assert!(strategy.on_bar(&ctx(0.0), &bar(i, 10.0, 1.0)).is_empty());
+        }
+        assert_eq!(
+            strategy.on_bar(&ctx(0.0), &bar(6, 12.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Buy, 12.0)]
+        );
+        // still above the signal line, no new cross
+        assert!(strategy.on_bar(&ctx(1.0), &bar(7, 13.0, 1.0)).is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx(1.0), &bar(8, 8.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 8.0)]
+        );
+    }
+}
@@ -1,160 +1,642 @@
-use crate::base::AppState;
-use crate::bot::strategies::should_execute;
-use crate::bot::{BotConfig, MarketType};
-use crate::core::functions::calculate_position_size;
-use crate::handlers::account::get_account;
-use crate::handlers::bar::get_bars;
-use crate::handlers::market::{get_positions, is_market_open};
-use crate::handlers::order::create_order;
-use crate::models::order::{Order, Qty};
-use crate::models::trade::{Side, TimeInForce, Type};
-use axum::extract::State;
-use axum::Json;
+use crate::bot::strategies::{BarWindow, Param, Signal, Strategy, StrategyContext, StrategyParams};
+use crate::bot::BotConfig;
+use crate::core::order_tracker::OrderUpdate;
+use crate::core::sizing::volatility;
+use crate::models::bar::Bar;
+use crate::models::num_to_f64;
+use crate::models::order::OrderStatus;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::interval;
 
-/// This is a mean reversion bot for both crypto and equity markets
-pub async fn mean_reversion_strategy(state: Arc<AppState>, config: BotConfig) {
-    // checking interval in sec (every 1min)
-    let mut interval = interval(Duration::from_secs(100));
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeanReversionParams {
+    /// Number of closes the mean and the deviation are measured on
+    pub lookback: usize,
+    /// Z-score of the close past which a position is opened
+    pub threshold: f64,
+    /// Highest volatility of the returns, over the `volatility_window` of the
+    /// bot, at which positions are opened, no limit when none
+    pub volatility_threshold: Option<f64>,
+    /// Bars of the first timeframe after which a position is closed, no time
+    /// stop when none
+    pub max_holding_bars: Option<usize>,
+    /// Loss, as a fraction of the entry price, at which a position is
+    /// closed, no stop when none
+    pub stop_loss: Option<f64>,
+}
 
-    loop {
-        interval.tick().await;
+impl Default for MeanReversionParams {
+    fn default() -> Self {
+        Self {
+            lookback: 20,
+            threshold: 2.0,
+            volatility_threshold: None,
+            max_holding_bars: None,
+            stop_loss: None,
+        }
+    }
+}
 
-        let should_execute = match should_execute(&state, &config).await {
-            Some(value) => value,
-            None => continue,
-        };
+impl StrategyParams for MeanReversionParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "lookback",
+            kind: "integer",
+            description: "Number of closes the mean and the deviation are measured on",
+        },
+        Param {
+            name: "threshold",
+            kind: "number",
+            description: "Z-score of the close past which a position is opened",
+        },
+        Param {
+            name: "volatility_threshold",
+            kind: "number",
+            description: "Highest volatility of the returns, over the volatility_window of the bot, at which positions are opened",
+        },
+        Param {
+            name: "max_holding_bars",
+            kind: "integer",
+            description: "Bars of the first timeframe after which a position is closed",
+        },
+        Param {
+            name: "stop_loss",
+            kind: "number",
+            description: "Loss, as a fraction of the entry price, at which a position is closed",
+        },
+    ];
 
-        if should_execute {
-            let mut all_signals = HashMap::new();
+    fn validate(&self) -> Result<(), String> {
+        if self.lookback < 2 {
+            return Err("lookback must be at least 2".to_string());
+        }
+        if !(self.threshold > 0.0 && self.threshold.is_finite()) {
+            return Err("threshold must be positive".to_string());
+        }
+        if self
+            .volatility_threshold
+            .is_some_and(|threshold| !(threshold > 0.0 && threshold.is_finite()))
+        {
+            return Err("volatility_threshold must be positive".to_string());
+        }
+        if self.max_holding_bars == Some(0) {
+            return Err("max_holding_bars must be positive".to_string());
+        }
+        if self
+            .stop_loss
+            .is_some_and(|stop_loss| !(stop_loss > 0.0 && stop_loss < 1.0))
+        {
+            return Err("stop_loss must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
 
-            let request_type = match &config.market {
-                MarketType::Crypto => "crypto_data",
-                MarketType::Equity => "stock_data",
-            };
+/// Position opened by the strategy
+#[derive(Debug, Clone)]
+struct Entry {
+    /// Close the entry was signaled at, then its average fill price
+    price: f64,
+    side: Side,
+    /// Bars of the first timeframe seen since the entry
+    bars: usize,
+    /// Whether the entry order filled, until then it is kept while flat
+    filled: bool,
+}
 
-            for timeframe in &config.timeframes {
-                match get_bars(
-                    state.as_ref(),
-                    &config.symbols,
-                    timeframe,
-                    config.lookback.max(config.volatility_window),
-                    config.volatility_window,
-                    request_type,
-                )
-                .await
-                {
-                    Ok(all_bars) => {
-                        for (symbol, bars) in all_bars {
-                            if bars.len() < config.lookback.max(config.volatility_window) {
-                                tracing::warn!(
-                                    "Not enough data for {} on timeframe {}",
-                                    symbol,
-                                    timeframe
-                                );
-                                continue;
-                            }
-
-                            let prices: Vec<f64> = bars.iter().map(|bar| bar.close_price).collect();
-                            let mean = prices.iter().take(config.lookback).sum::<f64>()
-                                / config.lookback as f64;
-                            let last_price = *prices.last().unwrap();
-
-                            let deviation = (last_price - mean).abs() / mean;
-                            let signal = if last_price > mean { -1 } else { 1 };
-                            all_signals
-                                .entry(symbol.clone())
-                                .and_modify(|e: &mut i32| *e += signal)
-                                .or_insert(signal);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to get bars for timeframe {}: {:?}", timeframe, e)
-                    }
-                }
+/// This is a mean reversion bot for both crypto and equity markets.
+///
+/// Each timeframe votes to buy when the z-score of its latest close over the
+/// last `lookback` closes is under `-threshold` and to sell when it is above
+/// `threshold`. A position is opened when the bot is flat and all the
+/// timeframes agree, unless the volatility is above `volatility_threshold`.
+/// It is closed on the first timeframe when the close reverts to the mean,
+/// after `max_holding_bars` bars or when the loss reaches `stop_loss`.
+pub struct MeanReversion {
+    params: MeanReversionParams,
+    timeframes: Vec<Timeframe>,
+    volatility_window: usize,
+    windows: HashMap<(String, Timeframe), BarWindow>,
+    votes: HashMap<(String, Timeframe), i32>,
+    entries: HashMap<String, Entry>,
+}
+
+impl MeanReversion {
+    pub fn new(config: &BotConfig, params: &MeanReversionParams) -> Self {
+        Self {
+            params: params.clone(),
+            timeframes: config.timeframes.clone(),
+            volatility_window: config.volatility_window.max(2),
+            windows: HashMap::new(),
+            votes: HashMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Number of bars kept per symbol and timeframe
+    fn window_size(&self) -> usize {
+        match self.params.volatility_threshold {
+            Some(_) => self.params.lookback.max(self.volatility_window + 1),
+            None => self.params.lookback,
+        }
+    }
+
+    /// Why the position held in the symbol of `ctx` must be closed at
+    /// `close`, none while it is kept
+    fn exit_reason(
+        &self,
+        ctx: &StrategyContext,
+        entry: &Entry,
+        close: f64,
+    ) -> Option<&'static str> {
+        let long = ctx.position > 0.0;
+        if let Some(stop_loss) = self.params.stop_loss {
+            let stopped = if long {
+                close <= entry.price * (1.0 - stop_loss)
+            } else {
+                close >= entry.price * (1.0 + stop_loss)
+            };
+            if stopped {
+                return Some("stop loss");
             }
+        }
+        if self
+            .params
+            .max_holding_bars
+            .is_some_and(|max| entry.bars >= max)
+        {
+            return Some("time stop");
+        }
+        let window = self.windows.get(&(ctx.symbol.to_string(), ctx.timeframe))?;
+        let mean = mean(&last(&window.closes(), self.params.lookback));
+        let reverted = if long { close >= mean } else { close <= mean };
+        reverted.then_some("reverted to the mean")
+    }
+}
+
+/// Last `n` values of `values`
+fn last(values: &[f64], n: usize) -> Vec<f64> {
+    values[values.len().saturating_sub(n)..].to_vec()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Z-score of the latest of `prices`, oldest first, against the mean and the
+/// standard deviation of all of them. None when they do not move.
+pub fn z_score(prices: &[f64]) -> Option<f64> {
+    let last_price = *prices.last()?;
+    let mean = mean(prices);
+    let variance = prices
+        .iter()
+        .map(|price| (price - mean).powi(2))
+        .sum::<f64>()
+        / prices.len() as f64;
+    let deviation = variance.sqrt();
+    (deviation > 0.0).then(|| (last_price - mean) / deviation)
+}
+
+/// Vote of one timeframe for a z-score: 1 to buy under `-threshold`, -1 to
+/// sell above `threshold`, 0 otherwise
+pub fn mean_reversion_vote(z_score: Option<f64>, threshold: f64) -> i32 {
+    match z_score {
+        Some(z) if z <= -threshold => 1,
+        Some(z) if z >= threshold => -1,
+        _ => 0,
+    }
+}
 
-            // get account
-            let account = match get_account(&state).await {
-                Ok(acc) => acc,
-                Err(e) => {
-                    tracing::error!("Failed to get account information: {:?}", e);
-                    continue;
+impl Strategy for MeanReversion {
+    fn warmup(&self) -> usize {
+        self.window_size()
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(100)
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        let key = (ctx.symbol.to_string(), ctx.timeframe);
+        let window_size = self.window_size();
+        let window = self
+            .windows
+            .entry(key.clone())
+            .or_insert_with(|| BarWindow::new(window_size));
+        window.push(bar.clone());
+        let closes = window.closes();
+        let full = closes.len() >= self.params.lookback;
+        let is_first = self.timeframes.first() == Some(&key.1);
+
+        // positions are closed on the first timeframe only
+        if ctx.position != 0.0 {
+            if !is_first {
+                return vec![];
+            }
+            let mut entry = self.entries.remove(ctx.symbol).unwrap_or(Entry {
+                // held before the strategy started, measured from here
+                price: bar.close_price,
+                side: if ctx.position > 0.0 {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                },
+                bars: 0,
+                filled: true,
+            });
+            entry.bars += 1;
+            entry.filled = true;
+            if !full {
+                self.entries.insert(ctx.symbol.to_string(), entry);
+                return vec![];
+            }
+            return match self.exit_reason(ctx, &entry, bar.close_price) {
+                Some(reason) => {
+                    tracing::debug!("Closing the position in {}: {}", ctx.symbol, reason);
+                    let side = if ctx.position > 0.0 {
+                        Side::Sell
+                    } else {
+                        Side::Buy
+                    };
+                    vec![Signal::new(ctx.symbol, side, bar.close_price).with_qty(ctx.position.abs())]
+                }
+                None => {
+                    self.entries.insert(ctx.symbol.to_string(), entry);
+                    vec![]
                 }
             };
+        }
+        if is_first
+            && self
+                .entries
+                .get(ctx.symbol)
+                .is_some_and(|entry| entry.filled)
+        {
+            // the position was closed
+            self.entries.remove(ctx.symbol);
+        }
+
+        if !full {
+            tracing::trace!(
+                "Not enough data for {} on timeframe {}",
+                ctx.symbol,
+                ctx.timeframe
+            );
+            self.votes.remove(&key);
+            return vec![];
+        }
+        let z = z_score(&last(&closes, self.params.lookback));
+        self.votes
+            .insert(key, mean_reversion_vote(z, self.params.threshold));
+
+        let mut signal = 0;
+        for timeframe in &self.timeframes {
+            match self.votes.get(&(ctx.symbol.to_string(), *timeframe)) {
+                Some(vote) => signal += vote,
+                None => return vec![],
+            }
+        }
+        if signal == 0 || signal.abs() != self.timeframes.len() as i32 {
+            return vec![];
+        }
+        if self
+            .entries
+            .get(ctx.symbol)
+            .is_some_and(|entry| !entry.filled)
+        {
+            tracing::debug!("Not entering {}, the last entry is pending", ctx.symbol);
+            return vec![];
+        }
 
-            // get positions
-            let positions = match get_positions(state.as_ref()).await {
-                Ok(pos) => pos,
-                Err(e) => {
-                    tracing::error!("Failed to get positions: {:?}", e);
-                    continue;
+        if let Some(threshold) = self.params.volatility_threshold {
+            let first = (ctx.symbol.to_string(), self.timeframes[0]);
+            let bars: Vec<Bar> = self
+                .windows
+                .get(&first)
+                .map(|window| window.bars().cloned().collect())
+                .unwrap_or_default();
+            match volatility(&bars, self.volatility_window) {
+                Some(volatility) if volatility <= threshold => {}
+                volatility => {
+                    tracing::debug!(
+                        "Not entering {}, volatility {:?} above {}",
+                        ctx.symbol,
+                        volatility,
+                        threshold
+                    );
+                    return vec![];
                 }
-            };
+            }
+        }
+
+        let side = if signal > 0 { Side::Buy } else { Side::Sell };
+        let mut entry = Signal::new(ctx.symbol, side.clone(), bar.close_price);
+        if let Some(stop_loss) = self.params.stop_loss {
+            entry = entry.with_stop(bar.close_price * (1.0 - stop_loss * signal.signum() as f64));
+        }
+        self.entries.insert(
+            ctx.symbol.to_string(),
+            Entry {
+                price: bar.close_price,
+                side,
+                bars: 0,
+                filled: false,
+            },
+        );
+        vec![entry]
+    }
 
-            let current_positions: HashMap<String, f64> =
-                positions.into_iter().map(|p| (p.symbol, p.qty)).collect();
-
-            for (symbol, signal) in all_signals {
-                if signal.abs() == config.timeframes.len() as i32 {
-                    let side = if signal > 0 { Side::Buy } else { Side::Sell };
-                    let current_position = *current_positions.get(&symbol).unwrap_or(&0.0);
-
-                    if (side == Side::Buy && current_position <= 0.0)
-                        || (side == Side::Sell && current_position >= 0.0)
-                    {
-                        let request_type = match &config.market {
-                            MarketType::Crypto => "crypto_data",
-                            MarketType::Equity => "stock_data",
-                        };
-                        let last_price = match get_bars(
-                            &state,
-                            &[symbol.clone()],
-                            "1Min",
-                            1,
-                            config.volatility_window,
-                            request_type,
-                        )
-                        .await
-                        {
-                            Ok(bars) => bars[&symbol][0].close_price,
-                            Err(e) => {
-                                tracing::error!(
-                                    "Failed to get current price for {}: {:?}",
-                                    symbol,
-                                    e
-                                );
-                                continue;
-                            }
-                        };
-
-                        let qty =
-                            calculate_position_size(&account, last_price, config.risk_per_trade);
-
-                        if qty > 0.0 {
-                            let order = Order {
-                                symbol: symbol.clone(),
-                                qty: Some(Qty::Int(qty as i32)),
-                                side: side.clone(),
-                                order_type: Type::Limit,
-                                time_in_force: TimeInForce::Day,
-                                limit_price: Some(if side == Side::Buy {
-                                    (last_price * 1.001) as i32
-                                } else {
-                                    (last_price * 0.999) as i32
-                                }),
-                                ..Order::default()
-                            };
-
-                            create_order(State(state.clone()), Json(order)).await;
-                            tracing::info!("Order placed: {:?} {} shares of {}", side, qty, symbol);
-                        }
-                    }
+    fn on_order_update(&mut self, update: &OrderUpdate) {
+        let order = &update.order;
+        let Some(entry) = self.entries.get_mut(&order.symbol) else {
+            return;
+        };
+        // the orders closing the position are not the entry
+        if order.side != entry.side {
+            return;
+        }
+        match update.status {
+            OrderStatus::PartiallyFilled | OrderStatus::Filled => {
+                if let Some(price) = order.filled_avg_price.as_ref().or(update.price.as_ref()) {
+                    entry.price = num_to_f64(price);
                 }
+                entry.filled = true;
             }
+            OrderStatus::Canceled | OrderStatus::Rejected | OrderStatus::Expired
+                if !entry.filled =>
+            {
+                self.entries.remove(&order.symbol);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{self, bar};
+    use crate::bot::BotStrategy;
+    use crate::models::order::{BrokerOrder, TradeEvent};
+    use num_decimal::Num;
+
+    fn params() -> MeanReversionParams {
+        MeanReversionParams {
+            lookback: 3,
+            threshold: 1.2,
+            ..MeanReversionParams::default()
+        }
+    }
+
+    fn config(timeframes: &[&str]) -> BotConfig {
+        BotConfig {
+            strategy: BotStrategy::MeanReversion(params()),
+            timeframes: timeframes.iter().map(|tf| tf.parse().unwrap()).collect(),
+            ..tests::config()
+        }
+    }
+
+    fn strategy(timeframes: &[&str], params: MeanReversionParams) -> MeanReversion {
+        MeanReversion::new(&config(timeframes), &params)
+    }
+
+    fn ctx(timeframe: &str, position: f64) -> StrategyContext<'static> {
+        StrategyContext {
+            symbol: "BTC/USD",
+            timeframe: timeframe.parse().unwrap(),
+            position,
+        }
+    }
+
+    /// Strategy on the minute bars that just bought at 7
+    fn bought(params: MeanReversionParams) -> MeanReversion {
+        let mut strategy = strategy(&["1Min"], params);
+        for (i, close) in [10.0, 10.0].into_iter().enumerate() {
+            assert!(strategy
+                .on_bar(&ctx("1Min", 0.0), &bar(i, close, 1.0))
+                .is_empty());
+        }
+        let signals = strategy.on_bar(&ctx("1Min", 0.0), &bar(2, 7.0, 1.0));
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].side, Side::Buy);
+        strategy
+    }
+
+    #[test]
+    fn vote_on_the_z_score_of_the_latest_close() {
+        // mean 9, deviation 1.41
+        let z = z_score(&[10.0, 10.0, 7.0]).unwrap();
+        assert!((z + 1.414).abs() < 1e-3);
+        assert_eq!(mean_reversion_vote(Some(z), 1.2), 1);
+        assert_eq!(mean_reversion_vote(Some(z), 1.5), 0);
+        assert_eq!(mean_reversion_vote(Some(-z), 1.2), -1);
+        // flat prices have no z-score
+        assert_eq!(z_score(&[10.0, 10.0, 10.0]), None);
+        assert_eq!(mean_reversion_vote(None, 1.2), 0);
+    }
+
+    #[test]
+    fn no_entry_within_the_threshold() {
+        let mut strategy = strategy(
+            &["1Min"],
+            MeanReversionParams {
+                threshold: 1.5,
+                ..params()
+            },
+        );
+        for (i, close) in [10.0, 10.0, 7.0].into_iter().enumerate() {
+            assert!(strategy
+                .on_bar(&ctx("1Min", 0.0), &bar(i, close, 1.0))
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn exit_when_reverted_to_the_mean() {
+        let mut strategy = bought(params());
+        // mean 8.5
+        assert!(strategy
+            .on_bar(&ctx("1Min", 2.0), &bar(3, 8.0, 1.0))
+            .is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx("1Min", 2.0), &bar(4, 9.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 9.0).with_qty(2.0)]
+        );
+    }
+
+    #[test]
+    fn exit_on_the_time_stop() {
+        let mut strategy = bought(MeanReversionParams {
+            max_holding_bars: Some(2),
+            ..params()
+        });
+        assert!(strategy
+            .on_bar(&ctx("1Min", 1.0), &bar(3, 6.0, 1.0))
+            .is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx("1Min", 1.0), &bar(4, 5.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 5.0).with_qty(1.0)]
+        );
+    }
+
+    #[test]
+    fn exit_on_the_stop_loss() {
+        let mut strategy = strategy(
+            &["1Min"],
+            MeanReversionParams {
+                stop_loss: Some(0.1),
+                ..params()
+            },
+        );
+        strategy.on_bar(&ctx("1Min", 0.0), &bar(0, 10.0, 1.0));
+        strategy.on_bar(&ctx("1Min", 0.0), &bar(1, 10.0, 1.0));
+        let entry = strategy.on_bar(&ctx("1Min", 0.0), &bar(2, 7.0, 1.0));
+        assert_eq!(entry[0].stop_price, Some(7.0 * 0.9));
+
+        assert!(strategy
+            .on_bar(&ctx("1Min", 1.0), &bar(3, 6.5, 1.0))
+            .is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx("1Min", 1.0), &bar(4, 6.2, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 6.2).with_qty(1.0)]
+        );
+    }
+
+    fn update(status: OrderStatus, filled_avg_price: Option<&str>) -> OrderUpdate {
+        OrderUpdate {
+            event: TradeEvent::Fill,
+            status,
+            order: BrokerOrder {
+                symbol: "BTC/USD".to_string(),
+                side: Side::Buy,
+                filled_avg_price: filled_avg_price.map(|price| price.parse().unwrap()),
+                ..BrokerOrder::default()
+            },
+            price: None,
+            qty: None,
         }
     }
+
+    #[test]
+    fn entry_kept_while_its_order_is_pending() {
+        let mut strategy = bought(MeanReversionParams {
+            stop_loss: Some(0.1),
+            ..params()
+        });
+        // not filled yet
+        assert!(strategy
+            .on_bar(&ctx("1Min", 0.0), &bar(3, 7.5, 1.0))
+            .is_empty());
+        strategy.on_order_update(&update(OrderStatus::Filled, Some("6.5")));
+
+        // the stop is measured from the fill at 6.5, not from the close at 7.5
+        assert!(strategy
+            .on_bar(&ctx("1Min", 1.0), &bar(4, 6.2, 1.0))
+            .is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx("1Min", 1.0), &bar(5, 5.8, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 5.8).with_qty(1.0)]
+        );
+    }
+
+    #[test]
+    fn no_entry_while_the_last_one_is_pending() {
+        let mut strategy = bought(params());
+        // crosses the threshold again before the entry is filled, mean 7
+        assert!(strategy
+            .on_bar(&ctx("1Min", 0.0), &bar(3, 4.0, 1.0))
+            .is_empty());
+        assert_eq!(strategy.entries["BTC/USD"].price, 7.0);
+
+        // entered again once the order is canceled
+        strategy.on_order_update(&update(OrderStatus::Canceled, None));
+        assert_eq!(
+            strategy.on_bar(&ctx("1Min", 0.0), &bar(4, 1.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Buy, 1.0)]
+        );
+    }
+
+    #[test]
+    fn entry_dropped_when_its_order_is_canceled() {
+        let mut strategy = bought(MeanReversionParams {
+            stop_loss: Some(0.1),
+            ..params()
+        });
+        strategy.on_order_update(&update(OrderStatus::Canceled, None));
+        assert!(strategy.entries.is_empty());
+
+        // a partial fill keeps the entry when the rest is canceled
+        let mut strategy = bought(params());
+        let mut partial = update(OrderStatus::PartiallyFilled, Some("7"));
+        partial.price = Some(Num::from(7));
+        strategy.on_order_update(&partial);
+        strategy.on_order_update(&update(OrderStatus::Canceled, Some("7")));
+        assert_eq!(strategy.entries["BTC/USD"].price, 7.0);
+    }
+
+    #[test]
+    fn no_entry_when_too_volatile() {
+        let closes = [10.0, 10.0, 10.0, 7.0];
+        let mut calm = strategy(
+            &["1Min"],
+            MeanReversionParams {
+                volatility_threshold: Some(0.2),
+                ..params()
+            },
+        );
+        let mut volatile = strategy(
+            &["1Min"],
+            MeanReversionParams {
+                volatility_threshold: Some(0.1),
+                ..params()
+            },
+        );
+        assert_eq!(volatile.warmup(), 4);
+
+        // the volatility of the returns is 0.17
+        let mut signals = vec![];
+        for (i, close) in closes.into_iter().enumerate() {
+            signals = calm.on_bar(&ctx("1Min", 0.0), &bar(i, close, 1.0));
+            assert!(volatile
+                .on_bar(&ctx("1Min", 0.0), &bar(i, close, 1.0))
+                .is_empty());
+        }
+        assert_eq!(signals, vec![Signal::new("BTC/USD", Side::Buy, 7.0)]);
+    }
+
+    #[test]
+    fn no_entry_when_already_long() {
+        let mut strategy = strategy(&["1Min"], params());
+        for (i, close) in [10.0, 10.0, 7.0].into_iter().enumerate() {
+            assert!(strategy
+                .on_bar(&ctx("1Min", 1.0), &bar(i, close, 1.0))
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn timeframes_must_agree() {
+        let mut strategy = strategy(&["1Min", "5Min"], params());
+        for (i, close) in [10.0, 10.0, 7.0].into_iter().enumerate() {
+            assert!(strategy
+                .on_bar(&ctx("1Min", 0.0), &bar(i, close, 1.0))
+                .is_empty());
+        }
+
+        // 5Min is above its mean, votes cancel out
+        for (i, close) in [10.0, 10.0].into_iter().enumerate() {
+            strategy.on_bar(&ctx("5Min", 0.0), &bar(i, close, 1.0));
+        }
+        assert!(strategy
+            .on_bar(&ctx("5Min", 0.0), &bar(2, 13.0, 1.0))
+            .is_empty());
+
+        let signals = strategy.on_bar(&ctx("5Min", 0.0), &bar(3, 5.0, 1.0));
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].side, Side::Buy);
+    }
 }
@@ -1,29 +1,353 @@
-use crate::base::AppState;
-use crate::bot::{BotConfig, MarketType};
-use crate::handlers::market::is_market_open;
-use std::sync::Arc;
+use crate::bot::{BotConfig, BotStrategy};
+use crate::core::order_tracker::OrderUpdate;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use bollinger::{BollingerParams, BollingerReversion};
+use donchian::{DonchianBreakout, DonchianParams};
+use macd::{MacdParams, MacdTrend};
+use mean_reversion::{MeanReversion, MeanReversionParams};
+use moving_average::{MovingAverageCrossover, MovingAverageParams};
+use opentelemetry::metrics::Meter;
+use rsi::{RsiParams, RsiReversion};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use smart_money::{SmartMoney, SmartMoneyInstruments, SmartMoneyParams};
+use std::collections::VecDeque;
 use std::time::Duration;
+use vwap::{VwapParams, VwapReversion};
 
+pub mod bollinger;
+pub mod donchian;
+pub mod macd;
 pub mod mean_reversion;
-mod moving_avarage;
-mod oth;
+pub mod moving_average;
+pub mod rsi;
 pub mod smart_money;
+pub mod vwap;
 
-async fn should_execute(state: &Arc<AppState>, config: &BotConfig) -> Option<bool> {
-    let should_execute = match config.market {
-        MarketType::Crypto => true, // Crypto markets are typically always open
-        MarketType::Equity => match is_market_open(&state).await {
-            Ok(true) => true,
-            Ok(false) => {
-                tracing::info!("Equity market is closed. Waiting for next check.");
-                tokio::time::sleep(Duration::from_secs(3600)).await;
-                return None;
-            }
-            Err(e) => {
-                tracing::error!("Failed to check if market is open: {:?}", e);
-                return None;
-            }
-        },
+/// Trading decision emitted by a strategy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub symbol: String,
+    pub side: Side,
+    /// Price the signal was computed on
+    pub price: f64,
+    /// Protective stop placed with the order, in one request
+    pub stop_price: Option<f64>,
+    /// Quantity to trade, sized by the bot when none
+    pub qty: Option<f64>,
+}
+
+impl Signal {
+    pub fn new(symbol: &str, side: Side, price: f64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            side,
+            price,
+            stop_price: None,
+            qty: None,
+        }
+    }
+
+    /// Attach a protective stop to the position opened by the signal
+    pub fn with_stop(mut self, stop_price: f64) -> Self {
+        self.stop_price = Some(stop_price);
+        self
+    }
+
+    /// Trade `qty` instead of sizing the signal, to close a position
+    pub fn with_qty(mut self, qty: f64) -> Self {
+        self.qty = Some(qty);
+        self
+    }
+}
+
+/// Signal moving the position towards `side`, none when it already leans
+/// that way
+pub fn signal_towards(ctx: &StrategyContext, side: Side, price: f64) -> Vec<Signal> {
+    let leans = match side {
+        Side::Buy => ctx.position > 0.0,
+        Side::Sell => ctx.position < 0.0,
     };
-    Some(should_execute)
+    if leans {
+        vec![]
+    } else {
+        vec![Signal::new(ctx.symbol, side, price)]
+    }
+}
+
+/// What a strategy knows about the bot when a bar arrives
+#[derive(Debug, Clone)]
+pub struct StrategyContext<'a> {
+    pub symbol: &'a str,
+    pub timeframe: Timeframe,
+    /// Quantity held in `symbol`, negative when short
+    pub position: f64,
+}
+
+/// Signal logic of a bot.
+///
+/// Strategies do no I/O: the execution engine fetches the bars, feeds them
+/// oldest first and takes care of sizing and submitting the orders, so the
+/// same strategy runs live, on the simulated broker and in backtests.
+pub trait Strategy: Send + Sync {
+    /// Number of bars needed before the strategy emits signals
+    fn warmup(&self) -> usize;
+
+    /// How often the live engine checks for new bars
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// Update the strategy with a new bar and return the resulting signals
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal>;
+
+    /// Called when an order placed on a signal of the strategy moves
+    fn on_order_update(&mut self, _update: &OrderUpdate) {}
+}
+
+/// Build the strategy of a bot
+pub fn build_strategy(config: &BotConfig, meter: &Meter) -> Box<dyn Strategy> {
+    match &config.strategy {
+        BotStrategy::MeanReversion(params) => Box::new(MeanReversion::new(config, params)),
+        BotStrategy::SmartMoney(params) => Box::new(
+            SmartMoney::new(config, params).with_instruments(SmartMoneyInstruments::new(meter)),
+        ),
+        BotStrategy::MovingAverageCrossover(params) => {
+            Box::new(MovingAverageCrossover::new(config, params))
+        }
+        BotStrategy::Rsi(params) => Box::new(RsiReversion::new(config, params)),
+        BotStrategy::Bollinger(params) => Box::new(BollingerReversion::new(config, params)),
+        BotStrategy::Macd(params) => Box::new(MacdTrend::new(config, params)),
+        BotStrategy::Donchian(params) => Box::new(DonchianBreakout::new(config, params)),
+        BotStrategy::VwapReversion(params) => Box::new(VwapReversion::new(config, params)),
+    }
+}
+
+/// Parameter of a strategy
+#[derive(Debug, Clone, Copy)]
+pub struct Param {
+    pub name: &'static str,
+    /// JSON schema type, `integer`, `number`, `boolean` or `string`
+    pub kind: &'static str,
+    pub description: &'static str,
+}
+
+/// Typed parameters of a strategy, missing ones take their default
+pub trait StrategyParams: Default + Serialize + DeserializeOwned {
+    /// Every parameter, described for the clients
+    const PARAMS: &'static [Param];
+
+    /// Check the parts of the parameters their types do not
+    fn validate(&self) -> Result<(), String>;
+}
+
+/// Strategy a bot can run, with the JSON schema of its parameters
+#[derive(Debug, Clone, Serialize)]
+pub struct StrategySchema {
+    pub kind: &'static str,
+    pub description: &'static str,
+    pub params: serde_json::Value,
+}
+
+impl StrategySchema {
+    fn of<P: StrategyParams>(kind: &'static str, description: &'static str) -> Self {
+        let defaults = serde_json::to_value(P::default()).unwrap_or_default();
+        let properties: serde_json::Map<String, serde_json::Value> = P::PARAMS
+            .iter()
+            .map(|param| {
+                let schema = serde_json::json!({
+                    "type": param.kind,
+                    "description": param.description,
+                    "default": defaults[param.name],
+                });
+                (param.name.to_string(), schema)
+            })
+            .collect();
+        Self {
+            kind,
+            description,
+            params: serde_json::json!({
+                "type": "object",
+                "properties": properties,
+            }),
+        }
+    }
+}
+
+/// The strategies a bot can run
+pub fn strategy_schemas() -> Vec<StrategySchema> {
+    vec![
+        StrategySchema::of::<MeanReversionParams>(
+            "mean_reversion",
+            "Buys under the mean of the latest closes and sells above it, when all the timeframes agree",
+        ),
+        StrategySchema::of::<SmartMoneyParams>(
+            "smart_money",
+            "Trades the breaks of support and resistance that come with a volume anomaly and a matching order flow",
+        ),
+        StrategySchema::of::<MovingAverageParams>(
+            "moving_average_crossover",
+            "Buys when the short moving average crosses above the long one and sells when it crosses below",
+        ),
+        StrategySchema::of::<RsiParams>(
+            "rsi",
+            "Buys when the RSI is oversold and sells when it is overbought",
+        ),
+        StrategySchema::of::<BollingerParams>(
+            "bollinger",
+            "Buys under the lower Bollinger band and sells above the upper one",
+        ),
+        StrategySchema::of::<MacdParams>(
+            "macd",
+            "Buys when the MACD crosses above its signal line and sells when it crosses below",
+        ),
+        StrategySchema::of::<DonchianParams>(
+            "donchian",
+            "Buys a close above the highest high of the channel and sells a close under its lowest low",
+        ),
+        StrategySchema::of::<VwapParams>(
+            "vwap_reversion",
+            "Buys under the rolling VWAP and sells above it, past a threshold",
+        ),
+    ]
+}
+
+/// Rolling window of the latest bars, oldest first
+#[derive(Debug, Clone)]
+pub struct BarWindow {
+    bars: VecDeque<Bar>,
+    capacity: usize,
+}
+
+impl BarWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            bars: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, bar: Bar) {
+        if self.bars.len() == self.capacity {
+            self.bars.pop_front();
+        }
+        self.bars.push_back(bar);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.bars.len() >= self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.bars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bars.is_empty()
+    }
+
+    /// The bars, oldest first
+    pub fn bars(&self) -> impl Iterator<Item = &Bar> {
+        self.bars.iter()
+    }
+
+    pub fn closes(&self) -> Vec<f64> {
+        self.bars.iter().map(|bar| bar.close_price).collect()
+    }
+
+    pub fn volumes(&self) -> Vec<f64> {
+        self.bars.iter().map(|bar| bar.volume).collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::bot::MarketType;
+
+    /// Bot trading BTC/USD on the minute bars
+    pub(crate) fn config() -> BotConfig {
+        BotConfig {
+            market: MarketType::Crypto,
+            strategy: BotStrategy::MeanReversion(MeanReversionParams::default()),
+            symbols: vec!["BTC/USD".to_string()],
+            risk_per_trade: 0.01,
+            volatility_window: 3,
+            ..BotConfig::test_default()
+        }
+    }
+
+    /// Context of the minute bars of BTC/USD
+    pub(crate) fn ctx(position: f64) -> StrategyContext<'static> {
+        StrategyContext {
+            symbol: "BTC/USD",
+            timeframe: Timeframe::Minute(1),
+            position,
+        }
+    }
+
+    /// Bar closing at `close`, `i` minutes after the first one
+    pub(crate) fn bar(i: usize, close: f64, volume: f64) -> Bar {
+        Bar {
+            close_price: close,
+            high_price: close,
+            low_price: close,
+            n: 1,
+            open_price: close,
+            timestamp: format!("2024-01-01T{:02}:{:02}:00Z", i / 60, i % 60),
+            volume,
+            vw: close,
+        }
+    }
+
+    /// Check that the schema of `P` describes all its parameters
+    pub(crate) fn params_are_described<P: StrategyParams>() {
+        let defaults = serde_json::to_value(P::default()).unwrap();
+        let mut names: Vec<&str> = defaults
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut described: Vec<&str> = P::PARAMS.iter().map(|param| param.name).collect();
+        names.sort();
+        described.sort();
+        assert_eq!(names, described);
+        assert!(P::default().validate().is_ok());
+    }
+
+    #[test]
+    fn schemas_describe_the_params() {
+        params_are_described::<MeanReversionParams>();
+        params_are_described::<SmartMoneyParams>();
+        params_are_described::<MovingAverageParams>();
+        params_are_described::<RsiParams>();
+        params_are_described::<BollingerParams>();
+        params_are_described::<MacdParams>();
+        params_are_described::<DonchianParams>();
+        params_are_described::<VwapParams>();
+
+        for schema in strategy_schemas() {
+            let strategy: BotStrategy =
+                serde_json::from_value(serde_json::json!({"kind": schema.kind, "params": {}}))
+                    .unwrap();
+            assert_eq!(strategy.kind(), schema.kind);
+        }
+        let schemas = strategy_schemas();
+        assert_eq!(schemas[0].params["properties"]["lookback"]["default"], 20);
+        assert_eq!(schemas[2].params["properties"]["average"]["default"], "ema");
+    }
+
+    #[test]
+    fn window_keeps_latest_bars() {
+        let mut window = BarWindow::new(3);
+        for i in 0..5 {
+            window.push(bar(i, i as f64, 1.0));
+        }
+
+        assert!(window.is_full());
+        assert_eq!(window.closes(), vec![2.0, 3.0, 4.0]);
+    }
 }
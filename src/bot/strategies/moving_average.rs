@@ -0,0 +1,197 @@
+use crate::bot::strategies::{
+    signal_towards, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ta::indicators::{ExponentialMovingAverage, SimpleMovingAverage};
+use ta::Next;
+
+/// Kind of the moving averages of a crossover
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AverageKind {
+    #[default]
+    Ema,
+    Sma,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovingAverageParams {
+    /// Period of the short moving average
+    pub short_period: usize,
+    /// Period of the long moving average
+    pub long_period: usize,
+    /// Kind of both moving averages
+    pub average: AverageKind,
+}
+
+impl Default for MovingAverageParams {
+    fn default() -> Self {
+        Self {
+            short_period: 10,
+            long_period: 30,
+            average: AverageKind::Ema,
+        }
+    }
+}
+
+impl StrategyParams for MovingAverageParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "short_period",
+            kind: "integer",
+            description: "Period of the short moving average",
+        },
+        Param {
+            name: "long_period",
+            kind: "integer",
+            description: "Period of the long moving average",
+        },
+        Param {
+            name: "average",
+            kind: "string",
+            description: "Kind of the moving averages, `ema` or `sma`",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.short_period == 0 || self.short_period >= self.long_period {
+            return Err("short_period must be positive and under long_period".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Moving average of either kind
+enum Average {
+    Ema(ExponentialMovingAverage),
+    Sma(SimpleMovingAverage),
+}
+
+impl Average {
+    fn new(kind: AverageKind, period: usize) -> Self {
+        match kind {
+            AverageKind::Ema => Average::Ema(ExponentialMovingAverage::new(period).unwrap()),
+            AverageKind::Sma => Average::Sma(SimpleMovingAverage::new(period).unwrap()),
+        }
+    }
+
+    fn next(&mut self, value: f64) -> f64 {
+        match self {
+            Average::Ema(average) => average.next(value),
+            Average::Sma(average) => average.next(value),
+        }
+    }
+}
+
+/// Moving average crossover: long while the short average is above the long
+/// one, short otherwise
+pub struct MovingAverageCrossover {
+    timeframe: Option<Timeframe>,
+    params: MovingAverageParams,
+    // short average, long average and number of bars seen, per symbol
+    averages: HashMap<String, (Average, Average, usize)>,
+}
+
+impl MovingAverageCrossover {
+    pub fn new(config: &BotConfig, params: &MovingAverageParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            params: params.clone(),
+            averages: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for MovingAverageCrossover {
+    fn warmup(&self) -> usize {
+        self.params.long_period
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let params = &self.params;
+        let (short, long, seen) =
+            self.averages
+                .entry(ctx.symbol.to_string())
+                .or_insert_with(|| {
+                    (
+                        Average::new(params.average, params.short_period),
+                        Average::new(params.average, params.long_period),
+                        0,
+                    )
+                });
+        let short = short.next(bar.close_price);
+        let long = long.next(bar.close_price);
+        *seen += 1;
+        if *seen < params.long_period {
+            return vec![];
+        }
+
+        if short > long {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if short < long {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn crossover(average: AverageKind) -> MovingAverageCrossover {
+        let params = MovingAverageParams {
+            short_period: 2,
+            long_period: 4,
+            average,
+        };
+        MovingAverageCrossover::new(&config(), &params)
+    }
+
+    #[test]
+    fn buy_when_the_short_average_crosses_above() {
+        for average in [AverageKind::Ema, AverageKind::Sma] {
+            let mut strategy = crossover(average);
+            for i in 0..3 {
+                assert!(strategy.on_bar(&ctx(0.0), &bar(i, 10.0, 1.0)).is_empty());
+            }
+            // flat averages do not cross
+            assert!(strategy.on_bar(&ctx(0.0), &bar(3, 10.0, 1.0)).is_empty());
+            assert_eq!(
+                strategy.on_bar(&ctx(0.0), &bar(4, 12.0, 1.0)),
+                vec![Signal::new("BTC/USD", Side::Buy, 12.0)]
+            );
+            assert!(strategy.on_bar(&ctx(1.0), &bar(5, 13.0, 1.0)).is_empty());
+            assert_eq!(
+                strategy.on_bar(&ctx(1.0), &bar(6, 6.0, 1.0)),
+                vec![Signal::new("BTC/USD", Side::Sell, 6.0)]
+            );
+        }
+    }
+
+    #[test]
+    fn average_kind_is_a_param() {
+        let params: MovingAverageParams =
+            serde_json::from_str(r#"{"average": "sma", "long_period": 50}"#).unwrap();
+        assert_eq!(params.average, AverageKind::Sma);
+        assert_eq!(params.short_period, 10);
+        assert!(MovingAverageParams {
+            short_period: 30,
+            ..MovingAverageParams::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
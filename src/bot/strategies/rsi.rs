@@ -0,0 +1,154 @@
+use crate::bot::strategies::{
+    signal_towards, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ta::indicators::RelativeStrengthIndex;
+use ta::Next;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RsiParams {
+    /// Number of bars the RSI is measured on
+    pub period: usize,
+    /// RSI under which the symbol is bought
+    pub oversold: f64,
+    /// RSI above which the symbol is sold
+    pub overbought: f64,
+}
+
+impl Default for RsiParams {
+    fn default() -> Self {
+        Self {
+            period: 14,
+            oversold: 30.0,
+            overbought: 70.0,
+        }
+    }
+}
+
+impl StrategyParams for RsiParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "period",
+            kind: "integer",
+            description: "Number of bars the RSI is measured on",
+        },
+        Param {
+            name: "oversold",
+            kind: "number",
+            description: "RSI under which the symbol is bought",
+        },
+        Param {
+            name: "overbought",
+            kind: "number",
+            description: "RSI above which the symbol is sold",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.period == 0 {
+            return Err("period must be positive".to_string());
+        }
+        if !(0.0 < self.oversold && self.oversold < self.overbought && self.overbought < 100.0) {
+            return Err("oversold and overbought must be ordered between 0 and 100".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// RSI reversion: buy an oversold symbol and sell an overbought one
+pub struct RsiReversion {
+    timeframe: Option<Timeframe>,
+    params: RsiParams,
+    // RSI and number of bars seen, per symbol
+    indicators: HashMap<String, (RelativeStrengthIndex, usize)>,
+}
+
+impl RsiReversion {
+    pub fn new(config: &BotConfig, params: &RsiParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            params: params.clone(),
+            indicators: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for RsiReversion {
+    fn warmup(&self) -> usize {
+        // the first bar only seeds the RSI
+        self.params.period + 1
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let period = self.params.period;
+        let (rsi, seen) = self
+            .indicators
+            .entry(ctx.symbol.to_string())
+            .or_insert_with(|| (RelativeStrengthIndex::new(period).unwrap(), 0));
+        let rsi = rsi.next(bar.close_price);
+        *seen += 1;
+        if *seen <= period {
+            return vec![];
+        }
+        tracing::debug!("RSI of {}: {}", ctx.symbol, rsi);
+
+        if rsi < self.params.oversold {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if rsi > self.params.overbought {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn strategy() -> RsiReversion {
+        let params = RsiParams {
+            period: 3,
+            ..RsiParams::default()
+        };
+        RsiReversion::new(&config(), &params)
+    }
+
+    #[test]
+    fn buy_oversold_and_sell_overbought() {
+        let mut strategy = strategy();
+        // overbought during the warmup
+        for (i, close) in [10.0, 10.5, 10.0].into_iter().enumerate() {
+            assert!(strategy.on_bar(&ctx(0.0), &bar(i, close, 1.0)).is_empty());
+        }
+        assert_eq!(
+            strategy.on_bar(&ctx(0.0), &bar(3, 9.5, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Buy, 9.5)]
+        );
+        assert!(strategy.on_bar(&ctx(1.0), &bar(4, 10.0, 1.0)).is_empty());
+        assert_eq!(
+            strategy.on_bar(&ctx(1.0), &bar(5, 11.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 11.0)]
+        );
+    }
+
+    #[test]
+    fn thresholds_are_ordered() {
+        let params = RsiParams {
+            oversold: 80.0,
+            ..RsiParams::default()
+        };
+        assert!(params.validate().is_err());
+    }
+}
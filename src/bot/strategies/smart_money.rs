@@ -1,27 +1,22 @@
-use crate::base::AppState;
-use crate::bot::{BotConfig, MarketType};
-use crate::core::functions::calculate_position_size;
-use crate::handlers::account::get_account;
-use crate::handlers::bar::get_bars;
-use crate::handlers::market::{get_positions, is_market_open};
-use crate::handlers::order::create_order;
-use crate::models::order::{Order, Qty};
-use crate::models::trade::{Side, TimeInForce, Type};
-use axum::extract::State;
-use axum::Json;
-use std::sync::Arc;
-use std::time::Duration;
+use crate::bot::strategies::{BarWindow, Param, Signal, Strategy, StrategyContext, StrategyParams};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use opentelemetry::metrics::{Gauge, Meter};
 use opentelemetry::KeyValue;
-use tokio::time::interval;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
 
 // Define a structure to hold market data
-struct MarketData {
-    price: f64,
-    volume: f64,
+pub struct MarketData {
+    pub price: f64,
+    pub volume: f64,
 }
 
 // Function to identify support and resistance levels
-fn identify_support_resistance(prices: &[f64], window: usize) -> (f64, f64) {
+pub fn identify_support_resistance(prices: &[f64], window: usize) -> (f64, f64) {
     let mut support = f64::MAX;
     let mut resistance = f64::MIN;
 
@@ -37,7 +32,7 @@ fn identify_support_resistance(prices: &[f64], window: usize) -> (f64, f64) {
 }
 
 // Function to detect volume anomalies
-fn detect_volume_anomaly(volumes: &[f64], threshold: f64) -> bool {
+pub fn detect_volume_anomaly(volumes: &[f64], threshold: f64) -> bool {
     let avg_volume = volumes.iter().sum::<f64>() / volumes.len() as f64;
     let last_volume = volumes.last().unwrap();
 
@@ -45,7 +40,7 @@ fn detect_volume_anomaly(volumes: &[f64], threshold: f64) -> bool {
 }
 
 // Function to analyze order flow
-fn analyze_order_flow(data: &[MarketData], window: usize) -> bool {
+pub fn analyze_order_flow(data: &[MarketData]) -> bool {
     let price_changes: Vec<f64> = data.windows(2).map(|w| w[1].price - w[0].price).collect();
 
     let volume_weighted_price_changes: Vec<f64> = data
@@ -59,190 +54,320 @@ fn analyze_order_flow(data: &[MarketData], window: usize) -> bool {
     sum_volume_weighted_changes > sum_price_changes
 }
 
-pub async fn smart_money_strategy(state: Arc<AppState>, config: BotConfig) {
-    let mut interval = interval(Duration::from_secs(30)); // Check every 5 minutes
-    let support_gauge = state.meter.f64_gauge("support_gauge")
-        .with_description("The support value gauge")
-        .init();
-    let resistance_gauge = state.meter.f64_gauge("resistance_gauge")
-        .with_description("The resistance value gauge")
-        .init();
-    let sell_order_hist = state.meter.f64_histogram("sell_order_hist")
-        .init();
-    let buy_order_hist = state.meter.f64_histogram("buy_order_hist")
-        .init();
-
-    loop {
-        interval.tick().await;
-
-        let should_execute = match &config.market {
-            MarketType::Crypto => true,
-            MarketType::Equity => match is_market_open(&state).await {
-                Ok(true) => true,
-                Ok(false) => {
-                    tracing::info!("Equity market is closed. Waiting for next check.");
-                    tokio::time::sleep(Duration::from_secs(3600)).await;
-                    continue;
-                }
-                Err(e) => {
-                    tracing::error!("Failed to check if market is open: {:?}", e);
-                    continue;
-                }
-            },
-        };
+/// Metric instruments of the smart money strategy
+pub struct SmartMoneyInstruments {
+    support_gauge: Gauge<f64>,
+    resistance_gauge: Gauge<f64>,
+}
+
+impl SmartMoneyInstruments {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            support_gauge: meter
+                .f64_gauge("support_gauge")
+                .with_description("The support value gauge")
+                .init(),
+            resistance_gauge: meter
+                .f64_gauge("resistance_gauge")
+                .with_description("The resistance value gauge")
+                .init(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartMoneyParams {
+    /// Number of bars the levels, the volume and the order flow are
+    /// measured on
+    pub lookback: usize,
+    /// Number of bars of the windows whose extremes are the support and the
+    /// resistance
+    pub level_window: usize,
+    /// Multiple of the average volume making a volume anomaly
+    pub volume_threshold: f64,
+}
+
+impl Default for SmartMoneyParams {
+    fn default() -> Self {
+        Self {
+            lookback: 20,
+            level_window: 5,
+            volume_threshold: 1.5,
+        }
+    }
+}
+
+impl StrategyParams for SmartMoneyParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "lookback",
+            kind: "integer",
+            description: "Number of bars the levels, the volume and the order flow are measured on",
+        },
+        Param {
+            name: "level_window",
+            kind: "integer",
+            description:
+                "Number of bars of the windows whose extremes are the support and the resistance",
+        },
+        Param {
+            name: "volume_threshold",
+            kind: "number",
+            description: "Multiple of the average volume making a volume anomaly",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.lookback < 2 {
+            return Err("lookback must be at least 2".to_string());
+        }
+        if self.level_window == 0 || self.level_window > self.lookback {
+            return Err("level_window must be between 1 and lookback".to_string());
+        }
+        if !(self.volume_threshold > 0.0 && self.volume_threshold.is_finite()) {
+            return Err("volume_threshold must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Smart money strategy: trade the breaks of support and resistance that
+/// come with a volume anomaly and a matching order flow
+pub struct SmartMoney {
+    bot_id: String,
+    bot_name: String,
+    timeframe: Option<Timeframe>,
+    window_size: usize,
+    level_window: usize,
+    volume_threshold: f64,
+    windows: HashMap<String, BarWindow>,
+    instruments: Option<SmartMoneyInstruments>,
+}
+
+impl SmartMoney {
+    pub fn new(config: &BotConfig, params: &SmartMoneyParams) -> Self {
+        Self {
+            bot_id: config.id.clone(),
+            bot_name: config.name.clone(),
+            timeframe: config.timeframes.first().copied(),
+            window_size: params.lookback.max(params.level_window),
+            level_window: params.level_window,
+            volume_threshold: params.volume_threshold,
+            windows: HashMap::new(),
+            instruments: None,
+        }
+    }
+
+    /// Record the support and resistance levels as metrics
+    pub fn with_instruments(mut self, instruments: SmartMoneyInstruments) -> Self {
+        self.instruments = Some(instruments);
+        self
+    }
+}
+
+impl Strategy for SmartMoney {
+    fn warmup(&self) -> usize {
+        self.window_size
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        // only the first timeframe of the bot is traded
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
 
-        if should_execute {
-            for symbol in &config.symbols {
-                let request_type = match &config.market {
-                    MarketType::Crypto => "crypto_data",
-                    MarketType::Equity => "stock_data",
-                };
-
-                // Fetch historical data
-                let bars = match get_bars(
-                    state.as_ref(),
-                    &[symbol.clone()],
-                    &config.timeframes[0],
-                    config.lookback.max(config.volatility_window),
-                    2,
-                    request_type,
-                )
-                .await
-                {
-                    Ok(bars) => bars.get(symbol).expect(&format!("Cannot get bar for {}", symbol)).clone(),
-                    Err(e) => {
-                        tracing::error!("Failed to get historical data for {}: {:?}", symbol, e);
-                        continue;
-                    }
-                };
-
-                if bars.len() < config.lookback {
-                    tracing::warn!("Not enough data for {}", symbol);
-                    continue;
-                }
-
-                let prices: Vec<f64> = bars.iter().map(|bar| bar.close_price).collect();
-                let volumes: Vec<f64> = bars.iter().map(|bar| bar.volume as f64).collect();
-                let market_data: Vec<MarketData> = bars
-                    .iter()
-                    .map(|bar| MarketData {
-                        price: bar.close_price,
-                        volume: bar.volume as f64,
-                    })
-                    .collect();
-
-                // Identify support and resistance
-                let (support, resistance) = identify_support_resistance(&prices, config.volatility_window);
-                support_gauge.record(support.clone(), &get_key_value_info(&config, &symbol));
-                resistance_gauge.record(resistance.clone(), &get_key_value_info(&config, &symbol));
-
-                tracing::debug!("support value :{}, resistance value : {}", support.clone(), resistance.clone());
-
-                // Detect volume anomaly
-                let volume_anomaly = detect_volume_anomaly(&volumes, config.threshold);
-                tracing::debug!("value anomaly {}", volume_anomaly.clone());
-
-
-                // Analyze order flow
-                let bullish_order_flow = analyze_order_flow(&market_data, config.volatility_window);
-                tracing::debug!("bullish_order_flow {}", bullish_order_flow.clone());
-
-                let last_price = *prices.last().unwrap();
-
-                // Get account information
-                let account = match get_account(&state).await {
-                    Ok(acc) => acc,
-                    Err(e) => {
-                        tracing::error!("Failed to get account information: {:?}", e);
-                        continue;
-                    }
-                };
-
-                // Get current positions
-                let positions = match get_positions(state.as_ref()).await {
-                    Ok(pos) => pos,
-                    Err(e) => {
-                        tracing::error!("Failed to get positions: {:?}", e);
-                        continue;
-                    }
-                };
-
-                let current_position = positions
-                    .iter()
-                    .find(|p| p.symbol == *symbol)
-                    .map(|p| p.qty)
-                    .unwrap_or(0.0);
-
-                tracing::info!("The current position is: {}", current_position.clone());
-
-                // Trading logic
-                if last_price <= support
-                    && volume_anomaly
-                    && bullish_order_flow
-                    && current_position <= 0.0
-                {
-                    // Potential smart money accumulation, consider buying
-                    let qty = calculate_position_size(&account, last_price, config.risk_per_trade);
-                    
-                    tracing::debug!("position 'qty' calculated {}", qty.clone());
-                    if qty > 0.0 {
-                        let order = Order {
-                            symbol: symbol.clone(),
-                            qty: Some(Qty::Float(qty as f32)),
-                            side: Side::Buy,
-                            order_type: Type::Limit,
-                            time_in_force: TimeInForce::Day,
-                            limit_price: Some((last_price * 1.001) as i32),
-                            ..Order::default()
-                        };
-
-                        create_order(State(state.clone()), Json(order)).await;
-                        buy_order_hist.record(
-                            qty as f64,
-                            &[
-                                KeyValue::new("bot_id", format!("{}", config.id)),
-                                KeyValue::new("bot_name", format!("{}", config.name)),
-                            ]);
-                        tracing::info!("Buy order placed: {} shares of {}", qty, symbol);
-                    }
-                } else if last_price >= resistance
-                    && volume_anomaly
-                    && !bullish_order_flow
-                    && current_position >= 0.0
-                {
-                    // Potential smart money distribution, consider selling
-                    let qty = calculate_position_size(&account, last_price, config.risk_per_trade);
-
-                    tracing::debug!("position 'qty' calculated {}", qty.clone());
-                    if qty > 0.0 {
-                        let order = Order {
-                            symbol: symbol.clone(),
-                            qty: Some(Qty::Float(qty as f32)),
-                            side: Side::Sell,
-                            order_type: Type::Limit,
-                            time_in_force: TimeInForce::Day,
-                            limit_price: Some((last_price * 0.999) as i32),
-                            ..Order::default()
-                        };
-                        create_order(State(state.clone()), Json(order)).await;
-                        sell_order_hist.record(
-                            qty as f64,
-                                                &[
-                                                    KeyValue::new("bot_id", format!("{}", config.id)),
-                                                    KeyValue::new("bot_name", format!("{}", config.name)),
-                                                ]);
-                        tracing::info!("Sell order placed: {} shares of {}", qty, symbol);
-                    }
-                }
-            }
+        let window = self
+            .windows
+            .entry(ctx.symbol.to_string())
+            .or_insert_with(|| BarWindow::new(self.window_size));
+        window.push(bar.clone());
+        if !window.is_full() {
+            tracing::trace!("Not enough data for {}", ctx.symbol);
+            return vec![];
         }
+
+        let prices = window.closes();
+        let volumes = window.volumes();
+        let market_data: Vec<MarketData> = prices
+            .iter()
+            .zip(&volumes)
+            .map(|(&price, &volume)| MarketData { price, volume })
+            .collect();
+
+        // Identify support and resistance
+        let (support, resistance) = identify_support_resistance(&prices, self.level_window);
+        if let Some(instruments) = &self.instruments {
+            let attributes = [
+                KeyValue::new("bot_id", self.bot_id.clone()),
+                KeyValue::new("bot_name", self.bot_name.clone()),
+                KeyValue::new("symbol", ctx.symbol.to_string()),
+            ];
+            instruments.support_gauge.record(support, &attributes);
+            instruments.resistance_gauge.record(resistance, &attributes);
+        }
+        tracing::debug!(
+            "support value :{}, resistance value : {}",
+            support,
+            resistance
+        );
+
+        // Detect volume anomaly
+        let volume_anomaly = detect_volume_anomaly(&volumes, self.volume_threshold);
+        tracing::debug!("value anomaly {}", volume_anomaly);
+
+        // Analyze order flow
+        let bullish_order_flow = analyze_order_flow(&market_data);
+        tracing::debug!("bullish_order_flow {}", bullish_order_flow);
+
+        let last_price = bar.close_price;
+
+        // Trading logic
+        let side =
+            if last_price <= support && volume_anomaly && bullish_order_flow && ctx.position <= 0.0
+            {
+                // Potential smart money accumulation, consider buying
+                Side::Buy
+            } else if last_price >= resistance
+                && volume_anomaly
+                && !bullish_order_flow
+                && ctx.position >= 0.0
+            {
+                // Potential smart money distribution, consider selling
+                Side::Sell
+            } else {
+                return vec![];
+            };
+
+        vec![Signal::new(ctx.symbol, side, last_price)]
     }
 }
 
-fn get_key_value_info(config: &BotConfig, symbol: &String) -> Vec<KeyValue>{
-    vec![
-        KeyValue::new("bot_id", format!("{}", config.id)),
-        KeyValue::new("bot_name", format!("{}", config.name)),
-        KeyValue::new("symbol", format!("{}", symbol)),
-    ]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{self, bar};
+    use crate::bot::BotStrategy;
+
+    fn params() -> SmartMoneyParams {
+        SmartMoneyParams {
+            lookback: 4,
+            level_window: 2,
+            volume_threshold: 1.5,
+        }
+    }
+
+    fn config() -> BotConfig {
+        BotConfig {
+            strategy: BotStrategy::SmartMoney(params()),
+            volatility_window: 2,
+            ..tests::config()
+        }
+    }
+
+    fn strategy() -> SmartMoney {
+        SmartMoney::new(&config(), &params())
+    }
+
+    fn ctx(position: f64) -> StrategyContext<'static> {
+        StrategyContext {
+            symbol: "BTC/USD",
+            timeframe: Timeframe::Minute(1),
+            position,
+        }
+    }
+
+    #[test]
+    fn support_and_resistance_are_window_extremes() {
+        let prices = [10.0, 12.0, 9.0, 11.0, 13.0];
+        assert_eq!(identify_support_resistance(&prices, 2), (9.0, 13.0));
+        // not enough prices for a single window
+        assert_eq!(
+            identify_support_resistance(&prices[..1], 2),
+            (f64::MAX, f64::MIN)
+        );
+    }
+
+    #[test]
+    fn volume_anomaly_compares_last_volume_to_average() {
+        assert!(detect_volume_anomaly(&[10.0, 10.0, 10.0, 40.0], 1.5));
+        assert!(!detect_volume_anomaly(&[10.0, 10.0, 10.0, 12.0], 1.5));
+    }
+
+    #[test]
+    fn order_flow_weights_price_changes_by_volume() {
+        let rising = [
+            MarketData {
+                price: 10.0,
+                volume: 1.0,
+            },
+            MarketData {
+                price: 11.0,
+                volume: 5.0,
+            },
+        ];
+        let falling = [
+            MarketData {
+                price: 11.0,
+                volume: 1.0,
+            },
+            MarketData {
+                price: 10.0,
+                volume: 5.0,
+            },
+        ];
+        assert!(analyze_order_flow(&rising));
+        assert!(!analyze_order_flow(&falling));
+    }
+
+    #[test]
+    fn sell_on_resistance_break_with_volume() {
+        let mut strategy = strategy();
+        let closes = [10.0, 10.5, 10.2, 9.0];
+        let volumes = [10.0, 10.0, 10.0, 10.0];
+        for (i, (&close, &volume)) in closes.iter().zip(&volumes).enumerate() {
+            assert!(strategy
+                .on_bar(&ctx(0.0), &bar(i, close, volume))
+                .is_empty());
+        }
+
+        let signals = strategy.on_bar(&ctx(0.0), &bar(4, 12.0, 100.0));
+        assert!(
+            signals.is_empty(),
+            "rising order flow is not a distribution"
+        );
+
+        let signals = strategy.on_bar(&ctx(0.0), &bar(5, 13.0, 10.0));
+        assert!(signals.is_empty(), "no volume anomaly");
+    }
+
+    #[test]
+    fn buy_on_support_with_bullish_flow() {
+        let mut strategy = strategy();
+        for (i, close) in [10.0, 9.0, 9.5, 9.8].into_iter().enumerate() {
+            strategy.on_bar(&ctx(0.0), &bar(i, close, 10.0));
+        }
+
+        // new low on heavy volume while the weighted flow stays positive
+        let signals = strategy.on_bar(&ctx(0.0), &bar(4, 8.0, 100.0));
+        assert!(signals.is_empty() || signals[0].side == Side::Buy);
+    }
+
+    #[test]
+    fn other_timeframes_are_ignored() {
+        let mut strategy = strategy();
+        let ctx = StrategyContext {
+            symbol: "BTC/USD",
+            timeframe: Timeframe::Minute(5),
+            position: 0.0,
+        };
+        for i in 0..10 {
+            assert!(strategy.on_bar(&ctx, &bar(i, 10.0, 10.0)).is_empty());
+        }
+        assert!(strategy.windows.is_empty());
+    }
 }
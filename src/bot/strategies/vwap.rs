@@ -0,0 +1,169 @@
+use crate::bot::strategies::{
+    signal_towards, BarWindow, Param, Signal, Strategy, StrategyContext, StrategyParams,
+};
+use crate::bot::BotConfig;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::models::trade::Side;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VwapParams {
+    /// Number of bars the VWAP is measured on
+    pub lookback: usize,
+    /// Distance to the VWAP, as a fraction of it, past which the symbol is
+    /// traded
+    pub threshold: f64,
+}
+
+impl Default for VwapParams {
+    fn default() -> Self {
+        Self {
+            lookback: 20,
+            threshold: 0.01,
+        }
+    }
+}
+
+impl StrategyParams for VwapParams {
+    const PARAMS: &'static [Param] = &[
+        Param {
+            name: "lookback",
+            kind: "integer",
+            description: "Number of bars the VWAP is measured on",
+        },
+        Param {
+            name: "threshold",
+            kind: "number",
+            description:
+                "Distance to the VWAP, as a fraction of it, past which the symbol is traded",
+        },
+    ];
+
+    fn validate(&self) -> Result<(), String> {
+        if self.lookback == 0 {
+            return Err("lookback must be positive".to_string());
+        }
+        if !(0.0..1.0).contains(&self.threshold) {
+            return Err("threshold must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Volume weighted average price of the bars, from the VWAP of each bar or
+/// its close when the feed has none. None without volume.
+pub fn vwap(window: &BarWindow) -> Option<f64> {
+    let (value, volume) = window.bars().fold((0.0, 0.0), |(value, volume), bar| {
+        let price = if bar.vw > 0.0 {
+            bar.vw
+        } else {
+            bar.close_price
+        };
+        (value + price * bar.volume, volume + bar.volume)
+    });
+    (volume > 0.0).then(|| value / volume)
+}
+
+/// VWAP reversion: buy under the rolling VWAP and sell above it, past the
+/// threshold
+pub struct VwapReversion {
+    timeframe: Option<Timeframe>,
+    params: VwapParams,
+    windows: HashMap<String, BarWindow>,
+}
+
+impl VwapReversion {
+    pub fn new(config: &BotConfig, params: &VwapParams) -> Self {
+        Self {
+            timeframe: config.timeframes.first().copied(),
+            params: params.clone(),
+            windows: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for VwapReversion {
+    fn warmup(&self) -> usize {
+        self.params.lookback
+    }
+
+    fn on_bar(&mut self, ctx: &StrategyContext, bar: &Bar) -> Vec<Signal> {
+        if self.timeframe != Some(ctx.timeframe) {
+            return vec![];
+        }
+
+        let window = self
+            .windows
+            .entry(ctx.symbol.to_string())
+            .or_insert_with(|| BarWindow::new(self.params.lookback));
+        window.push(bar.clone());
+        if !window.is_full() {
+            return vec![];
+        }
+        let Some(vwap) = vwap(window) else {
+            return vec![];
+        };
+        tracing::debug!("VWAP of {}: {}", ctx.symbol, vwap);
+
+        if bar.close_price < vwap * (1.0 - self.params.threshold) {
+            signal_towards(ctx, Side::Buy, bar.close_price)
+        } else if bar.close_price > vwap * (1.0 + self.params.threshold) {
+            signal_towards(ctx, Side::Sell, bar.close_price)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::{bar, config, ctx};
+
+    fn strategy() -> VwapReversion {
+        let params = VwapParams {
+            lookback: 2,
+            threshold: 0.1,
+        };
+        VwapReversion::new(&config(), &params)
+    }
+
+    #[test]
+    fn vwap_weighs_the_bars_by_volume() {
+        let mut window = BarWindow::new(2);
+        window.push(bar(0, 10.0, 3.0));
+        let mut no_vw = bar(1, 20.0, 1.0);
+        no_vw.vw = 0.0;
+        window.push(no_vw);
+        assert_eq!(vwap(&window), Some(12.5));
+
+        let mut empty = BarWindow::new(1);
+        empty.push(bar(0, 10.0, 0.0));
+        assert_eq!(vwap(&empty), None);
+    }
+
+    #[test]
+    fn trade_past_the_threshold() {
+        let mut strategy = strategy();
+        assert!(strategy.on_bar(&ctx(0.0), &bar(0, 10.0, 9.0)).is_empty());
+        // VWAP at 9.9, within the threshold
+        assert!(strategy.on_bar(&ctx(0.0), &bar(1, 9.0, 1.0)).is_empty());
+        // VWAP at 10
+        let mut dip = bar(2, 8.5, 1.0);
+        dip.vw = 10.1;
+        assert_eq!(
+            strategy.on_bar(&ctx(0.0), &dip),
+            vec![Signal::new("BTC/USD", Side::Buy, 8.5)]
+        );
+        // VWAP at 9.11
+        assert!(strategy.on_bar(&ctx(1.0), &bar(3, 9.0, 9.0)).is_empty());
+        // VWAP at 9.2
+        assert_eq!(
+            strategy.on_bar(&ctx(1.0), &bar(4, 11.0, 1.0)),
+            vec![Signal::new("BTC/USD", Side::Sell, 11.0)]
+        );
+    }
+}
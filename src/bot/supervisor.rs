@@ -0,0 +1,378 @@
+use crate::bot::Drain;
+use crate::core::journal::Journal;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinError;
+
+/// Run time after which a restarted bot is healthy again, at least
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// Time given to a bot to leave its loop once asked to stop, it is aborted
+/// after
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delays between the restarts of a bot, doubling from `initial_secs` up to
+/// `max_secs`. A bot running for `max_secs`, and at least a minute, without
+/// failing starts over from `initial_secs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Backoff {
+    pub initial_secs: u64,
+    pub max_secs: u64,
+    /// Restarts in a row before giving up, unlimited when none
+    pub max_restarts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial_secs: 1,
+            max_secs: 60,
+            max_restarts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the restart following `restarts` restarts in a row
+    pub fn delay(&self, restarts: u32) -> Duration {
+        let secs = self
+            .initial_secs
+            .saturating_mul(2u64.saturating_pow(restarts))
+            .min(self.max_secs);
+        Duration::from_secs(secs)
+    }
+}
+
+/// What to do when the task of a bot ends without being stopped
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Leave the bot down
+    #[default]
+    Never,
+    /// Restart the bot when it crashes
+    OnFailure(Backoff),
+    /// Restart the bot whenever it ends
+    Always(Backoff),
+}
+
+impl RestartPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RestartPolicy::Never => Ok(()),
+            RestartPolicy::OnFailure(backoff) | RestartPolicy::Always(backoff) => {
+                if backoff.initial_secs > backoff.max_secs {
+                    return Err("initial_secs must not exceed max_secs".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Delay before restarting a bot ended after `restarts` restarts in a row,
+    /// none when it stays down
+    pub fn restart_delay(&self, crashed: bool, restarts: u32) -> Option<Duration> {
+        let backoff = match self {
+            RestartPolicy::Never => return None,
+            RestartPolicy::OnFailure(_) if !crashed => return None,
+            RestartPolicy::OnFailure(backoff) | RestartPolicy::Always(backoff) => backoff,
+        };
+        if backoff.max_restarts.is_some_and(|max| restarts >= max) {
+            return None;
+        }
+        Some(backoff.delay(restarts))
+    }
+
+    /// Run time after which a bot is healthy again
+    fn reset_after(&self) -> Duration {
+        match self {
+            RestartPolicy::Never => Duration::MAX,
+            RestartPolicy::OnFailure(backoff) | RestartPolicy::Always(backoff) => {
+                Duration::from_secs(backoff.max_secs).max(HEALTHY_RUN)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BotStatus {
+    Running,
+    /// Waiting to be restarted
+    BackingOff,
+    /// Ended on a panic and not restarted
+    Crashed,
+    #[default]
+    Stopped,
+}
+
+/// Status of the task of a bot, with its last failure
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BotHealth {
+    pub status: BotStatus,
+    /// Panic message, or reason the task ended, of the last failure
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Restarts since the bot was started
+    pub restarts: u32,
+    pub next_restart_at: Option<DateTime<Utc>>,
+}
+
+impl BotHealth {
+    /// Whether the bot runs or is going to be restarted
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, BotStatus::Running | BotStatus::BackingOff)
+    }
+
+    fn failed(&mut self, error: String) {
+        self.last_error = Some(error);
+        self.last_error_at = Some(Utc::now());
+    }
+}
+
+/// Health of a bot shared with its supervisor
+pub type SharedHealth = Arc<Mutex<BotHealth>>;
+
+/// Run the task made by `run` until a stop is requested on `stop`,
+/// restarting it as the policy says when it ends on its own. The task gets
+/// the stop requests too and is given [`STOP_TIMEOUT`] to leave its loop
+/// before it is aborted, then `drain` winds down its orders and positions
+/// outside of it so a slow drain is never cut halfway. The health is kept up
+/// to date and each run ended on its own is recorded in the journal.
+pub async fn supervise<F, Fut, D, DrainFut>(
+    bot_id: String,
+    policy: RestartPolicy,
+    health: SharedHealth,
+    mut stop: watch::Receiver<Option<Drain>>,
+    journal: Journal,
+    mut run: F,
+    drain: D,
+) where
+    F: FnMut(watch::Receiver<Option<Drain>>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+    D: FnOnce(Drain) -> DrainFut,
+    DrainFut: Future<Output = ()>,
+{
+    let mut restarts = 0;
+    loop {
+        {
+            let mut health = health.lock().unwrap();
+            health.status = BotStatus::Running;
+            health.next_restart_at = None;
+        }
+        let started = Instant::now();
+        let mut task = tokio::spawn(run(stop.clone()));
+        let result = tokio::select! {
+            result = &mut task => result,
+            _ = stop.changed() => {
+                if tokio::time::timeout(STOP_TIMEOUT, &mut task).await.is_err() {
+                    tracing::warn!("Bot {} did not stop in {:?}, aborting it", bot_id, STOP_TIMEOUT);
+                    task.abort();
+                    let _ = task.await;
+                }
+                let requested = stop.borrow().unwrap_or_default();
+                drain(requested).await;
+                health.lock().unwrap().status = BotStatus::Stopped;
+                return;
+            }
+        };
+        // the task may have wound down before the stop was seen here
+        let requested = *stop.borrow();
+        if let (Some(requested), Ok(())) = (requested, &result) {
+            drain(requested).await;
+            health.lock().unwrap().status = BotStatus::Stopped;
+            return;
+        }
+
+        let (crashed, error) = match result {
+            Ok(()) => (false, "ended".to_string()),
+            Err(e) => (true, failure_message(e)),
+        };
+        if crashed {
+            tracing::error!("Bot {} crashed: {}", bot_id, error);
+        } else {
+            tracing::warn!("Bot {} ended", bot_id);
+        }
+        journal
+            .run_stopped(Some(&bot_id), if crashed { "crashed" } else { "ended" })
+            .await;
+
+        if started.elapsed() >= policy.reset_after() {
+            restarts = 0;
+        }
+        let delay = policy.restart_delay(crashed, restarts);
+        {
+            let mut health = health.lock().unwrap();
+            health.failed(error);
+            match delay {
+                Some(delay) => {
+                    health.status = BotStatus::BackingOff;
+                    health.next_restart_at = chrono::Duration::from_std(delay)
+                        .ok()
+                        .map(|delay| Utc::now() + delay);
+                }
+                None => {
+                    health.status = if crashed {
+                        BotStatus::Crashed
+                    } else {
+                        BotStatus::Stopped
+                    };
+                }
+            }
+        }
+        let Some(delay) = delay else {
+            return;
+        };
+
+        tracing::info!("Restarting bot {} in {:?}", bot_id, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stop.changed() => {
+                let requested = stop.borrow().unwrap_or_default();
+                drain(requested).await;
+                let mut health = health.lock().unwrap();
+                health.status = BotStatus::Stopped;
+                health.next_restart_at = None;
+                return;
+            }
+        }
+        restarts += 1;
+        health.lock().unwrap().restarts += 1;
+    }
+}
+
+/// Panic message of a crashed task
+fn failure_message(e: JoinError) -> String {
+    if !e.is_panic() {
+        return e.to_string();
+    }
+    let panic: Box<dyn Any + Send> = e.into_panic();
+    match panic.downcast::<String>() {
+        Ok(message) => format!("panicked: {}", message),
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => format!("panicked: {}", message),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn restart_delays() {
+        let backoff = Backoff {
+            initial_secs: 2,
+            max_secs: 30,
+            max_restarts: Some(5),
+        };
+        let on_failure = RestartPolicy::OnFailure(backoff.clone());
+        assert_eq!(
+            on_failure.restart_delay(true, 0),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            on_failure.restart_delay(true, 3),
+            Some(Duration::from_secs(16))
+        );
+        assert_eq!(
+            on_failure.restart_delay(true, 4),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(on_failure.restart_delay(true, 5), None);
+        assert_eq!(on_failure.restart_delay(false, 0), None);
+        assert_eq!(
+            RestartPolicy::Always(backoff).restart_delay(false, 0),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(RestartPolicy::Never.restart_delay(true, 0), None);
+
+        let parsed: RestartPolicy =
+            serde_json::from_str(r#"{"policy": "on_failure", "max_restarts": 3}"#).unwrap();
+        assert_eq!(
+            parsed,
+            RestartPolicy::OnFailure(Backoff {
+                max_restarts: Some(3),
+                ..Backoff::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn crashed_bots_are_restarted() {
+        let health = SharedHealth::default();
+        let (_stop, stop_rx) = watch::channel(None);
+        let runs = Arc::new(AtomicU32::new(0));
+        let policy = RestartPolicy::OnFailure(Backoff {
+            initial_secs: 0,
+            max_secs: 0,
+            max_restarts: Some(2),
+        });
+        let counter = runs.clone();
+        supervise(
+            "bot".to_string(),
+            policy,
+            health.clone(),
+            stop_rx,
+            Journal::disabled(),
+            move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { panic!("no bars") }
+            },
+            |_| async {},
+        )
+        .await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let health = health.lock().unwrap();
+        assert_eq!(health.status, BotStatus::Crashed);
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("panicked: no bars"));
+    }
+
+    #[tokio::test]
+    async fn stopped_bots_wind_down() {
+        let health = SharedHealth::default();
+        let (stop, stop_rx) = watch::channel(None);
+        let seen = Arc::new(Mutex::new(None));
+        let drained = Arc::new(Mutex::new(None));
+        let (seen_by_task, drained_by_supervisor) = (seen.clone(), drained.clone());
+        let supervisor = tokio::spawn(supervise(
+            "bot".to_string(),
+            RestartPolicy::Always(Backoff::default()),
+            health.clone(),
+            stop_rx,
+            Journal::disabled(),
+            move |mut stop: watch::Receiver<Option<Drain>>| {
+                let seen = seen_by_task.clone();
+                // leaves its loop once asked to
+                async move {
+                    let _ = stop.changed().await;
+                    *seen.lock().unwrap() = *stop.borrow();
+                }
+            },
+            move |drain| async move {
+                *drained_by_supervisor.lock().unwrap() = Some(drain);
+            },
+        ));
+        tokio::task::yield_now().await;
+        assert_eq!(health.lock().unwrap().status, BotStatus::Running);
+
+        stop.send(Some(Drain::Flatten)).unwrap();
+        supervisor.await.unwrap();
+        assert_eq!(*seen.lock().unwrap(), Some(Drain::Flatten));
+        assert_eq!(*drained.lock().unwrap(), Some(Drain::Flatten));
+        let health = health.lock().unwrap();
+        assert_eq!(health.status, BotStatus::Stopped);
+        assert_eq!(health.restarts, 0);
+        assert!(health.last_error.is_none());
+    }
+}
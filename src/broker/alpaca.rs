@@ -0,0 +1,881 @@
+use crate::base::ApiConfig;
+use crate::broker::Broker;
+use crate::core::rate_limiter::RateLimiter;
+use crate::error::Error;
+use crate::error::RequestError;
+use crate::models::account::Account;
+use crate::models::asset::Asset;
+use crate::models::bar::{Bar, BarQueryParams};
+use crate::models::order::{BrokerOrder, Order, OrderCancellation, OrderParams, ReplaceOrder};
+use crate::models::position::{ClosePosition, Position, PositionClosure};
+use crate::models::Clock;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body_util::BodyExt;
+use hyper::{Method, Request};
+use hyper_tls::HttpsConnector;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use traidano::RequestType;
+
+#[derive(Debug)]
+pub struct ClientBuildError;
+
+/// Http client of the alpaca api
+pub struct Client {
+    pub api_config: ApiConfig,
+}
+
+#[derive(Default)]
+pub struct ClientBuilder {
+    config: Option<ApiConfig>,
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self { config: None }
+    }
+
+    pub fn config(mut self, config: ApiConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn build(self) -> Result<Client, ClientBuildError> {
+        let config = match self.config {
+            Some(conf) => conf,
+            None => {
+                tracing::error!("Base url required");
+                return Err(ClientBuildError);
+            }
+        };
+
+        Ok(Client { api_config: config })
+    }
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    pub async fn send<T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+        request_type: RequestType,
+    ) -> Result<T, RequestError>
+    where
+        T: DeserializeOwned,
+    {
+        let body_bytes = self.request(method, path, body, request_type).await?;
+
+        let response: T =
+            serde_json::from_slice(&body_bytes).map_err(|e| RequestError::Json(Error::Json(e)))?;
+        Ok(response)
+    }
+
+    /// Send a request whose response has no body (e.g. `204 No Content`)
+    pub async fn send_empty(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+        request_type: RequestType,
+    ) -> Result<(), RequestError> {
+        self.request(method, path, body, request_type).await?;
+        Ok(())
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+        request_type: RequestType,
+    ) -> Result<Bytes, RequestError> {
+        use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+        let https = HttpsConnector::new();
+        let client = Client::builder(TokioExecutor::new()).build(https);
+
+        // get the right url
+
+        let mut full_url = self.url_match(request_type);
+        full_url.push_str(path);
+
+        let req = Request::builder()
+            .method(method)
+            .uri(&full_url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .header("APCA-API-KEY-ID", &self.api_config.api_key)
+            .header("APCA-API-SECRET-KEY", &self.api_config.secret_key)
+            .body(body)
+            .map_err(RequestError::HttpBuild)?;
+
+        tracing::debug!("request  send : {:?}", req);
+        let res = client
+            .request(req)
+            .await
+            .map_err(RequestError::LegacyHyper)?;
+
+        tracing::debug!("Response status: {}", res.status());
+        tracing::debug!("Response: {:#?}\n", res);
+
+        if res.status().is_success() {
+            let body_bytes = res
+                .into_body()
+                .collect()
+                .await
+                .map_err(RequestError::Hyper)?
+                .to_bytes();
+            Ok(body_bytes)
+        } else {
+            Err(RequestError::ApiError(res.status()))
+        }
+    }
+
+    fn url_match(&self, request_type: RequestType) -> String {
+        match request_type {
+            RequestType::CryptoData => self.api_config.crypto_data_url.clone(),
+            RequestType::StockData => self.api_config.stock_data_url.clone(),
+            RequestType::Order => self.api_config.base_url.clone(),
+        }
+    }
+}
+
+/// Alpaca implementation of the [`Broker`] trait.
+///
+/// All calls share the same rate limiter, alpaca allows 200 requests per minute.
+/// Assets are cached once fetched, their increments rarely change.
+pub struct AlpacaBroker {
+    client: Client,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    assets: Mutex<HashMap<String, Asset>>,
+}
+
+impl AlpacaBroker {
+    pub fn new(client: Client, rate_limiter: RateLimiter) -> Self {
+        Self {
+            client,
+            rate_limiter: Arc::new(Mutex::new(rate_limiter)),
+            assets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn acquire(&self) {
+        // Acquire the rate limiter lock
+        let mut guard = self.rate_limiter.lock().await;
+        tracing::trace!("rate limit: {}", guard.rate);
+        guard.acquire().await;
+    }
+
+    /// Get a page of bars, a response without them is malformed
+    async fn bars_page(
+        &self,
+        symbols: &[String],
+        params: &BarQueryParams,
+        request_type: RequestType,
+        page_token: Option<&str>,
+    ) -> Result<BarsPage, RequestError> {
+        let path = bars_path(symbols, params, request_type, page_token)?;
+        let page = self
+            .rate_limited_request::<serde_json::Value>(
+                Method::GET,
+                &path,
+                Body::empty(),
+                request_type,
+            )
+            .await?;
+        serde_json::from_value(page).map_err(RequestError::MalformedResponse)
+    }
+
+    async fn rate_limited_request<T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+        request_type: RequestType,
+    ) -> Result<T, RequestError>
+    where
+        T: DeserializeOwned,
+    {
+        self.acquire().await;
+        self.client
+            .send::<T>(method, path, body, request_type)
+            .await
+    }
+}
+
+/// Largest page of the historical bars endpoints
+const MAX_BARS_PAGE: usize = 10_000;
+
+/// Page of the historical bars endpoints
+#[derive(Deserialize)]
+struct BarsPage {
+    bars: HashMap<String, Vec<Bar>>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// Path of a page of bars, latest first
+fn bars_path(
+    symbols: &[String],
+    params: &BarQueryParams,
+    request_type: RequestType,
+    page_token: Option<&str>,
+) -> Result<String, RequestError> {
+    let format = |at: DateTime<Utc>| at.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let page_limit = params
+        .limit
+        .saturating_mul(symbols.len())
+        .clamp(1, MAX_BARS_PAGE);
+    let mut query = format!(
+        "symbols={}&limit={}&start={}&sort=desc",
+        symbols.join(","),
+        page_limit,
+        format(params.start)
+    );
+    if let Some(end) = params.end {
+        query.push_str(&format!("&end={}", format(end)));
+    }
+    if let Some(token) = page_token {
+        // tokens are base64
+        let token = token
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+        query.push_str(&format!("&page_token={}", token));
+    }
+
+    match request_type {
+        RequestType::StockData => {
+            if let Some(feed) = params.feed {
+                query.push_str(&format!("&feed={}", feed.as_str()));
+            }
+            if let Some(adjustment) = params.adjustment {
+                query.push_str(&format!("&adjustment={}", adjustment.as_str()));
+            }
+            Ok(format!("bars/{}?{}", params.timeframe, query))
+        }
+        RequestType::CryptoData => Ok(format!("us/bars?timeframe={}&{}", params.timeframe, query)),
+        RequestType::Order => {
+            tracing::error!("Cannot get bar of historical data from order query type");
+            Err(RequestError::ApiError(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+/// Positions of crypto pairs are addressed without the slash, `BTC/USD` is
+/// `BTCUSD`
+fn position_path(symbol: &str) -> String {
+    symbol.replace('/', "")
+}
+
+#[async_trait]
+impl Broker for AlpacaBroker {
+    async fn get_account(&self) -> Result<Account, RequestError> {
+        self.rate_limited_request::<Account>(
+            Method::GET,
+            "account",
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, RequestError> {
+        self.rate_limited_request::<Vec<Position>>(
+            Method::GET,
+            "positions",
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn get_position(&self, symbol: &str) -> Result<Position, RequestError> {
+        self.rate_limited_request::<Position>(
+            Method::GET,
+            &format!("positions/{}", position_path(symbol)),
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn close_position(
+        &self,
+        symbol: &str,
+        close: &ClosePosition,
+    ) -> Result<BrokerOrder, RequestError> {
+        let mut path = format!("positions/{}", position_path(symbol));
+        if let Some(qty) = &close.qty {
+            path.push_str(&format!("?qty={}", qty));
+        } else if let Some(percentage) = &close.percentage {
+            path.push_str(&format!("?percentage={}", percentage));
+        }
+        self.rate_limited_request::<BrokerOrder>(
+            Method::DELETE,
+            &path,
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn close_all_positions(
+        &self,
+        cancel_orders: bool,
+    ) -> Result<Vec<PositionClosure>, RequestError> {
+        // answered with a 207 multi-status, one status per position
+        self.rate_limited_request::<Vec<PositionClosure>>(
+            Method::DELETE,
+            &format!("positions?cancel_orders={}", cancel_orders),
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn get_asset(&self, symbol: &str) -> Result<Asset, RequestError> {
+        if let Some(asset) = self.assets.lock().await.get(symbol) {
+            return Ok(asset.clone());
+        }
+
+        // crypto pairs contain a slash
+        let path = format!("assets/{}", symbol.replace('/', "%2F"));
+        let asset = self
+            .rate_limited_request::<Asset>(Method::GET, &path, Body::empty(), RequestType::Order)
+            .await?;
+        self.assets
+            .lock()
+            .await
+            .insert(symbol.to_string(), asset.clone());
+        Ok(asset)
+    }
+
+    async fn get_clock(&self) -> Result<Clock, RequestError> {
+        self.rate_limited_request::<Clock>(Method::GET, "clock", Body::empty(), RequestType::Order)
+            .await
+    }
+
+    async fn get_bars(
+        &self,
+        symbols: &[String],
+        params: &BarQueryParams,
+        request_type: RequestType,
+    ) -> Result<HashMap<String, Vec<Bar>>, RequestError> {
+        let page = self.bars_page(symbols, params, request_type, None).await?;
+        let mut bars = page.bars;
+        if let Some(next_page_token) = page.next_page_token {
+            // pages hold the bars of one symbol after the other, the symbols
+            // left short are paged on their own
+            for symbol in symbols {
+                if bars.get(symbol).map_or(0, Vec::len) >= params.limit {
+                    continue;
+                }
+                // the pages of a single symbol go on from the first one
+                let (mut symbol_bars, mut page_token) = match symbols.len() {
+                    1 => (
+                        bars.remove(symbol).unwrap_or_default(),
+                        Some(next_page_token.clone()),
+                    ),
+                    _ => (Vec::new(), None),
+                };
+                loop {
+                    let page = self
+                        .bars_page(
+                            std::slice::from_ref(symbol),
+                            params,
+                            request_type,
+                            page_token.as_deref(),
+                        )
+                        .await?;
+                    symbol_bars.extend(page.bars.into_values().flatten());
+                    match page.next_page_token {
+                        Some(token) if symbol_bars.len() < params.limit => page_token = Some(token),
+                        _ => break,
+                    }
+                }
+                bars.insert(symbol.clone(), symbol_bars);
+            }
+        }
+        for symbol_bars in bars.values_mut() {
+            symbol_bars.truncate(params.limit);
+        }
+        Ok(bars)
+    }
+
+    async fn submit_order(&self, order: &Order) -> Result<BrokerOrder, RequestError> {
+        let body = serde_json::to_string(order).map_err(|e| RequestError::Json(Error::Json(e)))?;
+        self.rate_limited_request::<BrokerOrder>(
+            Method::POST,
+            "orders",
+            Body::from(body),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn get_order(&self, order_id: &str) -> Result<BrokerOrder, RequestError> {
+        self.rate_limited_request::<BrokerOrder>(
+            Method::GET,
+            &format!("orders/{}", order_id),
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn get_order_by_client_id(
+        &self,
+        client_order_id: &str,
+    ) -> Result<BrokerOrder, RequestError> {
+        self.rate_limited_request::<BrokerOrder>(
+            Method::GET,
+            &format!(
+                "orders:by_client_order_id?client_order_id={}",
+                client_order_id
+            ),
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn replace_order(
+        &self,
+        order_id: &str,
+        replace: &ReplaceOrder,
+    ) -> Result<BrokerOrder, RequestError> {
+        let body =
+            serde_json::to_string(replace).map_err(|e| RequestError::Json(Error::Json(e)))?;
+        self.rate_limited_request::<BrokerOrder>(
+            Method::PATCH,
+            &format!("orders/{}", order_id),
+            Body::from(body),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn cancel_order(&self, order_id: &str) -> Result<(), RequestError> {
+        self.acquire().await;
+        self.client
+            .send_empty(
+                Method::DELETE,
+                &format!("orders/{}", order_id),
+                Body::empty(),
+                RequestType::Order,
+            )
+            .await
+    }
+
+    async fn cancel_all_orders(&self) -> Result<Vec<OrderCancellation>, RequestError> {
+        // answered with a 207 multi-status, one status per order
+        self.rate_limited_request::<Vec<OrderCancellation>>(
+            Method::DELETE,
+            "orders",
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+
+    async fn list_orders(&self, params: &OrderParams) -> Result<Vec<BrokerOrder>, RequestError> {
+        let mut url_query: String = "orders?".to_string();
+        url_query.push_str(&params.query());
+
+        self.rate_limited_request::<Vec<BrokerOrder>>(
+            Method::GET,
+            url_query.as_str(),
+            Body::empty(),
+            RequestType::Order,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bar::{Adjustment, DataFeed};
+    use crate::models::timeframe::Timeframe;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Debug, Serialize, PartialEq, Eq)]
+    struct TestResponse {
+        message: String,
+    }
+
+    #[test]
+    fn create_client() {
+        let api_config = ApiConfig {
+            base_url: "base".to_string(),
+            stream_url: "".to_string(),
+            stock_data_url: "".to_string(),
+            crypto_data_url: "".to_string(),
+            api_key: "key".to_string(),
+            secret_key: "secret".to_string(),
+        };
+
+        let client = Client::builder().config(api_config.clone()).build();
+
+        assert_eq!(client.unwrap().api_config, api_config)
+    }
+
+    #[tokio::test]
+    async fn test_send_success() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let mock_url = mock_server.url();
+
+        let api_config = ApiConfig {
+            base_url: mock_url,
+            ..ApiConfig::default()
+        };
+
+        // mock successful response
+        let _m = mock_server
+            .mock("GET", "/test-endpoint")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"message": "Success"}"#)
+            .create_async()
+            .await;
+
+        let client = Client { api_config };
+        let res: Result<TestResponse, RequestError> = client
+            .send(
+                Method::GET,
+                "/test-endpoint",
+                Body::empty(),
+                RequestType::Order,
+            )
+            .await;
+
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            TestResponse {
+                message: "Success".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_order_no_content() {
+        let mut mock_server = mockito::Server::new_async().await;
+
+        let api_config = ApiConfig {
+            base_url: format!("{}/", mock_server.url()),
+            ..ApiConfig::default()
+        };
+
+        let _m = mock_server
+            .mock("DELETE", "/orders/order-id")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let broker = AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(1.0, 1.0),
+        );
+
+        assert!(broker.cancel_order("order-id").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn assets_are_fetched_once() {
+        let mut mock_server = mockito::Server::new_async().await;
+
+        let api_config = ApiConfig {
+            base_url: format!("{}/", mock_server.url()),
+            ..ApiConfig::default()
+        };
+
+        let mock = mock_server
+            .mock("GET", "/assets/BTC%2FUSD")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id":"a1","class":"crypto","exchange":"CRYPTO","symbol":"BTC/USD","tradable":true,"fractionable":true,"min_order_size":"0.0001","min_trade_increment":"0.000000001","price_increment":"1"}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let broker = AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(10.0, 10.0),
+        );
+
+        let asset = broker.get_asset("BTC/USD").await.unwrap();
+        assert_eq!(asset.price_increment, Some(num_decimal::Num::from(1)));
+        broker.get_asset("BTC/USD").await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn replace_order_patches_the_order() {
+        let mut mock_server = mockito::Server::new_async().await;
+
+        let api_config = ApiConfig {
+            base_url: format!("{}/", mock_server.url()),
+            ..ApiConfig::default()
+        };
+
+        let _m = mock_server
+            .mock("PATCH", "/orders/order-id")
+            .match_body(mockito::Matcher::Json(
+                serde_json::json!({"limit_price": "101.5"}),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id":"new-id","symbol":"AAPL","side":"buy","type":"limit","time_in_force":"day","limit_price":"101.5","status":"accepted","replaces":"order-id"}"#,
+            )
+            .create_async()
+            .await;
+
+        let broker = AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(1.0, 1.0),
+        );
+        let replace = ReplaceOrder {
+            limit_price: Some("101.5".parse().unwrap()),
+            ..ReplaceOrder::default()
+        };
+
+        let order = broker.replace_order("order-id", &replace).await.unwrap();
+        assert_eq!(order.id, "new-id");
+        assert_eq!(order.replaces.as_deref(), Some("order-id"));
+    }
+
+    #[tokio::test]
+    async fn close_all_positions_reads_each_closure() {
+        let mut mock_server = mockito::Server::new_async().await;
+
+        let api_config = ApiConfig {
+            base_url: format!("{}/", mock_server.url()),
+            ..ApiConfig::default()
+        };
+
+        let _m = mock_server
+            .mock("DELETE", "/positions?cancel_orders=true")
+            .with_status(207)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"[{"symbol":"AAPL","status":200,"body":{"id":"o1","symbol":"AAPL","side":"sell","type":"market","time_in_force":"day","qty":"10","status":"accepted"}},{"symbol":"BTCUSD","status":500,"body":{"code":50010000,"message":"internal server error"}}]"#,
+            )
+            .create_async()
+            .await;
+
+        let broker = AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(1.0, 1.0),
+        );
+
+        let closures = broker.close_all_positions(true).await.unwrap();
+        assert_eq!(closures.len(), 2);
+        assert_eq!(closures[0].order.as_ref().unwrap().id, "o1");
+        assert_eq!(closures[1].status, 500);
+    }
+
+    fn crypto_broker(url: String) -> AlpacaBroker {
+        let api_config = ApiConfig {
+            crypto_data_url: format!("{}/", url),
+            ..ApiConfig::default()
+        };
+        AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(10.0, 10.0),
+        )
+    }
+
+    fn bars_json(closes: &[u32]) -> String {
+        let bars: Vec<String> = closes
+            .iter()
+            .map(|close| {
+                format!(
+                    r#"{{"t":"2024-01-01T00:{:02}:00Z","o":1,"h":1,"l":1,"c":{},"v":1}}"#,
+                    close, close
+                )
+            })
+            .collect();
+        format!("[{}]", bars.join(","))
+    }
+
+    #[tokio::test]
+    async fn bars_follow_the_page_tokens() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let first = mock_server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(
+                    r"^/us/bars\?timeframe=1Min&symbols=BTC/USD&limit=3&start=[^&]+&sort=desc$"
+                        .to_string(),
+                ),
+            )
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"bars":{{"BTC/USD":{}}},"next_page_token":"p/2="}}"#,
+                bars_json(&[9, 8])
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        let second = mock_server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"&page_token=p%2F2%3D$".to_string()),
+            )
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"bars":{{"BTC/USD":{}}},"next_page_token":"p3"}}"#,
+                bars_json(&[7, 6])
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let broker = crypto_broker(mock_server.url());
+        let params = BarQueryParams::new(Timeframe::Minute(1), Utc::now(), 3);
+        let bars = broker
+            .get_bars(&["BTC/USD".to_string()], &params, RequestType::CryptoData)
+            .await
+            .unwrap();
+
+        let closes: Vec<f64> = bars["BTC/USD"].iter().map(|bar| bar.close_price).collect();
+        assert_eq!(closes, vec![9.0, 8.0, 7.0]);
+        first.assert_async().await;
+        second.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn symbols_left_short_are_paged_on_their_own() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let both = mock_server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"symbols=BTC/USD,ETH/USD&limit=4&".to_string()),
+            )
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"bars":{{"BTC/USD":{},"ETH/USD":{}}},"next_page_token":"p2"}}"#,
+                bars_json(&[9, 8]),
+                bars_json(&[5])
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        let short = mock_server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"symbols=ETH/USD&limit=2&[^&]+&sort=desc$".to_string()),
+            )
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"bars":{{"ETH/USD":{}}},"next_page_token":"p3"}}"#,
+                bars_json(&[5, 4])
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        let satisfied = mock_server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"symbols=BTC/USD&".to_string()),
+            )
+            .expect(0)
+            .create_async()
+            .await;
+
+        let broker = crypto_broker(mock_server.url());
+        let params = BarQueryParams::new(Timeframe::Minute(1), Utc::now(), 2);
+        let symbols = ["BTC/USD".to_string(), "ETH/USD".to_string()];
+        let bars = broker
+            .get_bars(&symbols, &params, RequestType::CryptoData)
+            .await
+            .unwrap();
+
+        let closes =
+            |symbol: &str| -> Vec<f64> { bars[symbol].iter().map(|bar| bar.close_price).collect() };
+        assert_eq!(closes("BTC/USD"), vec![9.0, 8.0]);
+        assert_eq!(closes("ETH/USD"), vec![5.0, 4.0]);
+        both.assert_async().await;
+        short.assert_async().await;
+        satisfied.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn malformed_bars_are_an_error() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = mock_server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(r#"{"bar":{}}"#)
+            .create_async()
+            .await;
+
+        let broker = crypto_broker(mock_server.url());
+        let params = BarQueryParams::new(Timeframe::Minute(1), Utc::now(), 3);
+        let result = broker
+            .get_bars(&["BTC/USD".to_string()], &params, RequestType::CryptoData)
+            .await;
+        assert!(matches!(result, Err(RequestError::MalformedResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn other_responses_keep_their_json_error() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let _m = mock_server
+            .mock("GET", "/clock")
+            .with_status(200)
+            .with_body(r#"{"open":"yes"}"#)
+            .create_async()
+            .await;
+
+        let api_config = ApiConfig {
+            base_url: format!("{}/", mock_server.url()),
+            ..ApiConfig::default()
+        };
+        let broker = AlpacaBroker::new(
+            Client::builder().config(api_config).build().unwrap(),
+            RateLimiter::new(10.0, 10.0),
+        );
+        let result = broker.get_clock().await;
+        assert!(matches!(result, Err(RequestError::Json(_))));
+    }
+
+    #[test]
+    fn equity_bars_take_feed_and_adjustment() {
+        let start = "2024-01-01T00:00:00Z".parse().unwrap();
+        let params = BarQueryParams {
+            end: Some("2024-01-02T00:00:00Z".parse().unwrap()),
+            feed: Some(DataFeed::Iex),
+            adjustment: Some(Adjustment::Split),
+            ..BarQueryParams::new(Timeframe::Hour(1), start, 50)
+        };
+        let symbols = ["AAPL".to_string(), "MSFT".to_string()];
+        assert_eq!(
+            bars_path(&symbols, &params, RequestType::StockData, None).unwrap(),
+            "bars/1Hour?symbols=AAPL,MSFT&limit=100&start=2024-01-01T00:00:00Z&sort=desc\
+             &end=2024-01-02T00:00:00Z&feed=iex&adjustment=split"
+        );
+        // crypto bars have neither
+        assert_eq!(
+            bars_path(&symbols[..1], &params, RequestType::CryptoData, Some("t")).unwrap(),
+            "us/bars?timeframe=1Hour&symbols=AAPL&limit=50&start=2024-01-01T00:00:00Z&sort=desc\
+             &end=2024-01-02T00:00:00Z&page_token=t"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::RequestError;
+use crate::models::account::Account;
+use crate::models::asset::Asset;
+use crate::models::bar::{Bar, BarQueryParams};
+use crate::models::order::{BrokerOrder, Order, OrderCancellation, OrderParams, ReplaceOrder};
+use crate::models::position::{ClosePosition, Position, PositionClosure};
+use crate::models::Clock;
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{HashMap, HashSet};
+use traidano::RequestType;
+
+pub mod alpaca;
+pub mod sim;
+
+/// Most orders listed in one request
+const ORDERS_PAGE: u32 = 500;
+
+/// Trading venue used by the handlers and the bots.
+///
+/// Every call that reaches an exchange goes through this trait, so a bot
+/// does not know which venue (or simulator) is behind it.
+#[async_trait]
+pub trait Broker: Send + Sync {
+    /// Get account information
+    async fn get_account(&self) -> Result<Account, RequestError>;
+
+    /// Get all open positions
+    async fn get_positions(&self) -> Result<Vec<Position>, RequestError>;
+
+    /// Get the open position in `symbol`
+    async fn get_position(&self, symbol: &str) -> Result<Position, RequestError>;
+
+    /// Close all or part of the position in `symbol` with a market order
+    async fn close_position(
+        &self,
+        symbol: &str,
+        close: &ClosePosition,
+    ) -> Result<BrokerOrder, RequestError>;
+
+    /// Close every open position, cancelling the open orders first when
+    /// `cancel_orders` is set
+    async fn close_all_positions(
+        &self,
+        cancel_orders: bool,
+    ) -> Result<Vec<PositionClosure>, RequestError>;
+
+    /// Get the metadata of the asset traded as `symbol`
+    async fn get_asset(&self, symbol: &str) -> Result<Asset, RequestError>;
+
+    /// Get the market clock
+    async fn get_clock(&self) -> Result<Clock, RequestError>;
+
+    /// Get historical bars for `symbols`, latest bar first
+    async fn get_bars(
+        &self,
+        symbols: &[String],
+        params: &BarQueryParams,
+        request_type: RequestType,
+    ) -> Result<HashMap<String, Vec<Bar>>, RequestError>;
+
+    /// Submit a new order
+    async fn submit_order(&self, order: &Order) -> Result<BrokerOrder, RequestError>;
+
+    /// Get an order by its broker id
+    async fn get_order(&self, order_id: &str) -> Result<BrokerOrder, RequestError>;
+
+    /// Get an order by the id given by the client when placing it
+    async fn get_order_by_client_id(
+        &self,
+        client_order_id: &str,
+    ) -> Result<BrokerOrder, RequestError>;
+
+    /// Replace an open order, the new order is returned
+    async fn replace_order(
+        &self,
+        order_id: &str,
+        replace: &ReplaceOrder,
+    ) -> Result<BrokerOrder, RequestError>;
+
+    /// Cancel an open order
+    async fn cancel_order(&self, order_id: &str) -> Result<(), RequestError>;
+
+    /// Cancel every open order
+    async fn cancel_all_orders(&self) -> Result<Vec<OrderCancellation>, RequestError>;
+
+    /// List orders matching `params`
+    async fn list_orders(&self, params: &OrderParams) -> Result<Vec<BrokerOrder>, RequestError>;
+
+    /// List every open order, of the comma separated `symbols` when given,
+    /// page by page from the oldest
+    async fn list_open_orders(
+        &self,
+        symbols: Option<String>,
+    ) -> Result<Vec<BrokerOrder>, RequestError> {
+        let mut orders = vec![];
+        let mut seen = HashSet::new();
+        let mut after = None;
+        loop {
+            let params = OrderParams {
+                status: Some("open".to_string()),
+                limit: Some(ORDERS_PAGE),
+                after: after.clone(),
+                direction: Some("asc".to_string()),
+                symbols: symbols.clone(),
+                ..OrderParams::default()
+            };
+            let page = self.list_orders(&params).await?;
+            let full = page.len() >= ORDERS_PAGE as usize;
+            // the next page starts just before the last order, the orders
+            // submitted at the same time are listed again
+            after = page
+                .last()
+                .and_then(|order| order.submitted_at.as_ref().or(order.created_at.as_ref()))
+                .and_then(|at| at.parse::<DateTime<Utc>>().ok())
+                .map(|at| {
+                    (at - chrono::Duration::microseconds(1))
+                        .to_rfc3339_opts(SecondsFormat::Micros, true)
+                });
+            let listed = orders.len();
+            orders.extend(
+                page.into_iter()
+                    .filter(|order| seen.insert(order.id.clone())),
+            );
+            if !full || after.is_none() || orders.len() == listed {
+                return Ok(orders);
+            }
+        }
+    }
+}
@@ -0,0 +1,1482 @@
+use crate::broker::Broker;
+use crate::error::RequestError;
+use crate::models::account::Account;
+use crate::models::asset::Asset;
+use crate::models::bar::{Bar, BarQueryParams};
+use crate::models::order::{
+    bot_client_order_id, client_order_bot_id, BrokerOrder, Order, OrderCancellation, OrderParams,
+    ReplaceOrder, TradeEvent, TradeUpdate,
+};
+use crate::models::position::{ClosePosition, Position, PositionClosure, PositionSide};
+use crate::models::trade::{OrderClass, TimeInForce};
+use crate::models::trade::{Side, Type};
+use crate::models::{num_from_f64, Clock};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use traidano::RequestType;
+use uuid::Uuid;
+
+/// Execution settings of the simulated broker
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub initial_cash: f64,
+    /// Slippage applied to market orders, in basis points
+    pub slippage_bps: f64,
+    /// Fee charged on the notional of every fill, in basis points
+    pub fee_bps: f64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            initial_cash: 100_000.0,
+            slippage_bps: 5.0,
+            fee_bps: 0.0,
+        }
+    }
+}
+
+impl SimConfig {
+    /// Read `SIM_INITIAL_CASH`, `SIM_SLIPPAGE_BPS` and `SIM_FEE_BPS`, falling back to the defaults
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            initial_cash: env_or("SIM_INITIAL_CASH", default.initial_cash),
+            slippage_bps: env_or("SIM_SLIPPAGE_BPS", default.slippage_bps),
+            fee_bps: env_or("SIM_FEE_BPS", default.fee_bps),
+        }
+    }
+}
+
+fn env_or(key: &str, default: f64) -> f64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Rate of a number of basis points
+fn bps(value: f64) -> Num {
+    num_from_f64(value).unwrap_or_default() / 10_000
+}
+
+fn abs(value: &Num) -> Num {
+    if value.is_negative() {
+        -value
+    } else {
+        value.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SimPosition {
+    qty: Num,
+    avg_entry_price: Num,
+}
+
+#[derive(Debug, Clone)]
+struct SimOrder {
+    request: Order,
+    info: BrokerOrder,
+    /// Order whose fill activates this leg
+    parent: Option<String>,
+    /// Filling one order of the group cancels the others
+    oco_group: Option<String>,
+    /// Best price seen by a trailing stop
+    hwm: Option<Num>,
+}
+
+impl SimOrder {
+    /// Whether the order can fill, held legs wait for their parent
+    fn is_active(&self) -> bool {
+        matches!(self.info.status.as_str(), "new" | "accepted")
+    }
+
+    fn is_closed(&self) -> bool {
+        matches!(
+            self.info.status.as_str(),
+            "filled" | "canceled" | "rejected" | "expired" | "replaced"
+        )
+    }
+
+    /// Stop price of the order, following the best price for trailing stops
+    fn stop(&self) -> Option<Num> {
+        if self.request.order_type != Type::TrailingStop {
+            return self.request.stop_price.clone();
+        }
+        let hwm = self.hwm.as_ref()?;
+        let trail = match (&self.request.trail_price, &self.request.trail_percent) {
+            (Some(price), _) => price.clone(),
+            (None, Some(percent)) => hwm * percent / 100,
+            (None, None) => return None,
+        };
+        match self.request.side {
+            Side::Buy => Some(hwm + trail),
+            Side::Sell => Some(hwm - trail),
+        }
+    }
+
+    /// Move the best price of a trailing stop
+    fn track(&mut self, price: &Num) {
+        if self.request.order_type != Type::TrailingStop {
+            return;
+        }
+        let hwm = match (self.hwm.take(), &self.request.side) {
+            (None, _) => price.clone(),
+            (Some(hwm), Side::Buy) => hwm.min(price.clone()),
+            (Some(hwm), Side::Sell) => hwm.max(price.clone()),
+        };
+        self.info.hwm = Some(hwm.clone());
+        self.hwm = Some(hwm);
+    }
+}
+
+/// Execution of an order by the simulated broker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimFill {
+    pub order_id: String,
+    pub symbol: String,
+    pub side: Side,
+    pub qty: Num,
+    pub price: Num,
+    pub fee: Num,
+    /// Profit realized by the part of the fill that reduced a position
+    pub realized_pnl: Option<Num>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Default)]
+struct SimBook {
+    cash: Num,
+    positions: HashMap<String, SimPosition>,
+    orders: Vec<SimOrder>,
+    fills: Vec<SimFill>,
+    last_prices: HashMap<String, Num>,
+    last_bars: HashMap<String, String>,
+    /// Bars replayed without a data source, oldest first
+    history: HashMap<String, Vec<Bar>>,
+    updates: Option<mpsc::UnboundedSender<TradeUpdate>>,
+}
+
+/// In-process paper trading broker.
+///
+/// Keeps its own cash, positions and open orders and fills them against the
+/// bars it sees. Market data and the clock come from an optional `data`
+/// broker, orders never leave the process. Without a data source the bars
+/// given to [`SimBroker::on_bar`] are replayed as market data, which is how
+/// backtests drive it.
+///
+/// Legs of bracket, oco and oto orders are simulated, `opg` and `cls`
+/// orders are handled like day orders since there is no auction.
+pub struct SimBroker {
+    config: SimConfig,
+    data: Option<Arc<dyn Broker>>,
+    book: Mutex<SimBook>,
+}
+
+impl SimBroker {
+    pub fn new(config: SimConfig, data: Option<Arc<dyn Broker>>) -> Self {
+        let book = SimBook {
+            cash: num_from_f64(config.initial_cash).unwrap_or_default(),
+            ..SimBook::default()
+        };
+        Self {
+            config,
+            data,
+            book: Mutex::new(book),
+        }
+    }
+
+    /// Feed a new bar of `symbol` and fill the open orders it crosses
+    pub async fn on_bar(&self, symbol: &str, bar: &Bar) {
+        let prices = [
+            bar.open_price,
+            bar.low_price,
+            bar.high_price,
+            bar.close_price,
+        ];
+        let Some([open, low, high, close]) = prices
+            .iter()
+            .map(|&price| num_from_f64(price))
+            .collect::<Option<Vec<Num>>>()
+            .and_then(|prices| <[Num; 4]>::try_from(prices).ok())
+        else {
+            tracing::warn!("sim: ignoring bar of {} without prices", symbol);
+            return;
+        };
+        let mut book = self.book.lock().await;
+
+        // the same bar can be seen several times when polling
+        if let Some(last) = book.last_bars.get(symbol) {
+            if bar.timestamp.as_str() <= last.as_str() {
+                return;
+            }
+        }
+        book.last_bars
+            .insert(symbol.to_string(), bar.timestamp.clone());
+        if self.data.is_none() {
+            book.history
+                .entry(symbol.to_string())
+                .or_default()
+                .push(bar.clone());
+        }
+
+        // legs activated by a fill wait for the next bar
+        let working: Vec<usize> = book
+            .orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| order.is_active() && order.request.symbol == symbol)
+            .map(|(index, _)| index)
+            .collect();
+        for index in working {
+            let order = &mut book.orders[index];
+            // an earlier fill of the bar may have canceled it
+            if !order.is_active() {
+                continue;
+            }
+            order.track(&open);
+            if let Some(price) = match_price(
+                &order.request,
+                order.stop(),
+                &open,
+                &low,
+                &high,
+                &bps(self.config.slippage_bps),
+            ) {
+                book.fill(index, price, &bps(self.config.fee_bps), &bar.timestamp);
+            } else {
+                match order.request.side {
+                    Side::Buy => order.track(&low),
+                    Side::Sell => order.track(&high),
+                }
+            }
+        }
+        book.last_prices.insert(symbol.to_string(), close);
+    }
+
+    /// Publish the order transitions to `updates`, the way the trade updates
+    /// stream of a real broker does
+    pub fn with_trade_updates(mut self, updates: mpsc::UnboundedSender<TradeUpdate>) -> Self {
+        self.book.get_mut().updates = Some(updates);
+        self
+    }
+
+    /// All the fills executed so far, oldest first
+    pub async fn fills(&self) -> Vec<SimFill> {
+        self.book.lock().await.fills.clone()
+    }
+}
+
+/// Price at which `order` fills on a bar, `None` when the bar does not cross it.
+///
+/// `stop` is the current stop price of stop and trailing stop orders.
+/// `slippage` is the rate applied to market and stop fills.
+fn match_price(
+    order: &Order,
+    stop: Option<Num>,
+    open: &Num,
+    low: &Num,
+    high: &Num,
+    slippage: &Num,
+) -> Option<Num> {
+    let with_slippage = |price: Num| match order.side {
+        Side::Buy => price * (Num::from(1) + slippage),
+        Side::Sell => price * (Num::from(1) - slippage),
+    };
+    // price at which a stop triggers, gaps trigger at the open
+    let triggered = |stop: Num| match order.side {
+        Side::Buy if *open >= stop => Some(open.clone()),
+        Side::Buy if *high >= stop => Some(stop),
+        Side::Sell if *open <= stop => Some(open.clone()),
+        Side::Sell if *low <= stop => Some(stop),
+        _ => None,
+    };
+    // fill of a limit order working from `price`
+    let limit_fill = |price: Num, limit: &Num| match order.side {
+        Side::Buy if price <= *limit => Some(price),
+        Side::Buy if low <= limit => Some(limit.clone()),
+        Side::Sell if price >= *limit => Some(price),
+        Side::Sell if high >= limit => Some(limit.clone()),
+        _ => None,
+    };
+    let limit = order.limit_price.as_ref();
+
+    match order.order_type {
+        Type::Market => Some(with_slippage(open.clone())),
+        Type::Limit => limit_fill(open.clone(), limit?),
+        Type::Stop | Type::TrailingStop => triggered(stop?).map(with_slippage),
+        Type::StopLimit => limit_fill(triggered(stop?)?, limit?),
+    }
+}
+
+/// Broker view of a new order
+fn order_info(order: &Order, status: &str, now: &str) -> BrokerOrder {
+    BrokerOrder {
+        id: Uuid::new_v4().to_string(),
+        client_order_id: order.client_order_id.clone(),
+        created_at: Some(now.to_string()),
+        updated_at: Some(now.to_string()),
+        submitted_at: Some(now.to_string()),
+        filled_at: None,
+        symbol: order.symbol.clone(),
+        qty: order.qty.clone(),
+        notional: order.national.clone(),
+        filled_qty: Some(Num::from(0)),
+        filled_avg_price: None,
+        side: order.side.clone(),
+        order_type: order.order_type.clone(),
+        time_in_force: order.time_in_force.clone(),
+        order_class: order.order_class.clone(),
+        limit_price: order.limit_price.clone(),
+        stop_price: order.stop_price.clone(),
+        trail_price: order.trail_price.clone(),
+        trail_percent: order.trail_percent.clone(),
+        hwm: None,
+        status: status.to_string(),
+        extended_hours: order.extended_hours,
+        legs: None,
+        replaced_by: None,
+        replaces: None,
+    }
+}
+
+/// Take profit and stop loss orders of an order with legs
+fn leg_orders(order: &Order) -> Vec<Order> {
+    let leg = |order_type: Type| Order {
+        symbol: order.symbol.clone(),
+        qty: order.qty.clone(),
+        side: order.exit_side(),
+        order_type,
+        time_in_force: order.time_in_force.clone(),
+        // the fills of the legs are booked to the bot of the order
+        client_order_id: order
+            .client_order_id
+            .as_deref()
+            .and_then(client_order_bot_id)
+            .map(bot_client_order_id),
+        ..Order::default()
+    };
+
+    let mut legs = vec![];
+    // the order itself is the take profit of an oco order
+    if order.order_class != OrderClass::Oco {
+        if let Some(take_profit) = &order.take_profit {
+            legs.push(Order {
+                limit_price: Some(take_profit.limit_price.clone()),
+                ..leg(Type::Limit)
+            });
+        }
+    }
+    if let Some(stop_loss) = &order.stop_loss {
+        let order_type = match stop_loss.limit_price {
+            Some(_) => Type::StopLimit,
+            None => Type::Stop,
+        };
+        legs.push(Order {
+            stop_price: Some(stop_loss.stop_price.clone()),
+            limit_price: stop_loss.limit_price.clone(),
+            ..leg(order_type)
+        });
+    }
+    legs
+}
+
+impl SimBook {
+    /// Fill the order at `index`, `fee` is the rate charged on the notional
+    fn fill(&mut self, index: usize, price: Num, fee: &Num, at: &str) {
+        let order = &self.orders[index];
+        let qty = match &order.request.qty {
+            Some(qty) => qty.clone(),
+            None => order.request.national.clone().unwrap_or_default() / &price,
+        };
+        let signed_qty = match order.request.side {
+            Side::Buy => qty.clone(),
+            Side::Sell => -&qty,
+        };
+        let fee = &qty * &price * fee;
+
+        if order.request.side == Side::Buy && &qty * &price + &fee > self.cash {
+            tracing::warn!(
+                "sim: rejecting order {}, insufficient buying power",
+                order.info.id
+            );
+            self.close(index, "rejected", TradeEvent::Rejected, at);
+            return;
+        }
+
+        let symbol = order.request.symbol.clone();
+        self.cash -= &signed_qty * &price + &fee;
+
+        let position = self.positions.entry(symbol.clone()).or_insert(SimPosition {
+            qty: Num::from(0),
+            avg_entry_price: Num::from(0),
+        });
+        let new_qty = &position.qty + &signed_qty;
+        let mut realized_pnl = None;
+        if position.qty.is_zero() || position.qty.is_positive() == signed_qty.is_positive() {
+            position.avg_entry_price =
+                (abs(&position.qty) * &position.avg_entry_price + &qty * &price) / abs(&new_qty);
+        } else {
+            let closed_qty = qty.clone().min(abs(&position.qty));
+            let pnl = closed_qty * (&price - &position.avg_entry_price);
+            realized_pnl = Some(if position.qty.is_positive() {
+                pnl
+            } else {
+                -pnl
+            });
+            if !new_qty.is_zero() && new_qty.is_positive() != position.qty.is_positive() {
+                // the position flipped side
+                position.avg_entry_price = price.clone();
+            }
+        }
+        position.qty = new_qty;
+        if position.qty.is_zero() {
+            self.positions.remove(&symbol);
+        }
+        self.last_prices.insert(symbol.clone(), price.clone());
+
+        self.fills.push(SimFill {
+            order_id: order.info.id.clone(),
+            symbol,
+            side: order.request.side.clone(),
+            qty: qty.clone(),
+            price: price.clone(),
+            fee,
+            realized_pnl,
+            timestamp: at.to_string(),
+        });
+
+        let info = &mut self.orders[index].info;
+        info.status = "filled".to_string();
+        info.filled_qty = Some(qty.clone());
+        info.filled_avg_price = Some(price.clone());
+        info.filled_at = Some(at.to_string());
+        info.updated_at = Some(at.to_string());
+        tracing::info!("sim: order {} filled {} @ {}", info.id, qty, price);
+        self.notify(index, TradeEvent::Fill, Some((&qty, &price)), at);
+
+        // the exits of the position wait for the entry to fill
+        let id = self.orders[index].info.id.clone();
+        for leg in 0..self.orders.len() {
+            if self.orders[leg].parent.as_ref() == Some(&id)
+                && self.orders[leg].info.status == "held"
+            {
+                self.orders[leg].info.status = "new".to_string();
+                self.orders[leg].info.updated_at = Some(at.to_string());
+                self.orders[leg].track(&price);
+                self.notify(leg, TradeEvent::New, None, at);
+            }
+        }
+        if let Some(group) = self.orders[index].oco_group.clone() {
+            for other in 0..self.orders.len() {
+                if other != index
+                    && self.orders[other].oco_group.as_ref() == Some(&group)
+                    && !self.orders[other].is_closed()
+                {
+                    self.close(other, "canceled", TradeEvent::Canceled, at);
+                }
+            }
+        }
+    }
+
+    /// Close an order without a fill, its held legs are canceled with it
+    fn close(&mut self, index: usize, status: &str, event: TradeEvent, at: &str) {
+        let order = &mut self.orders[index];
+        order.info.status = status.to_string();
+        order.info.updated_at = Some(at.to_string());
+        let id = order.info.id.clone();
+        self.notify(index, event, None, at);
+
+        for leg in 0..self.orders.len() {
+            if self.orders[leg].parent.as_ref() == Some(&id)
+                && self.orders[leg].info.status == "held"
+            {
+                self.close(leg, "canceled", TradeEvent::Canceled, at);
+            }
+        }
+    }
+
+    /// Index of the order `order_id`
+    fn find(&self, order_id: &str) -> Result<usize, RequestError> {
+        self.orders
+            .iter()
+            .position(|order| order.info.id == order_id)
+            .ok_or(RequestError::ApiError(StatusCode::NOT_FOUND))
+    }
+
+    /// Broker view of an order, with the current state of its legs
+    fn info(&self, index: usize) -> BrokerOrder {
+        let mut info = self.orders[index].info.clone();
+        let legs: Vec<BrokerOrder> = self
+            .orders
+            .iter()
+            .filter(|order| order.parent.as_ref() == Some(&info.id))
+            .map(|order| order.info.clone())
+            .collect();
+        if !legs.is_empty() {
+            info.legs = Some(legs);
+        }
+        info
+    }
+
+    fn notify(&self, index: usize, event: TradeEvent, fill: Option<(&Num, &Num)>, at: &str) {
+        let Some(updates) = &self.updates else {
+            return;
+        };
+        let order = &self.orders[index].info;
+        let position_qty = self
+            .positions
+            .get(&order.symbol)
+            .map_or_else(Num::default, |position| position.qty.clone());
+        let _ = updates.send(TradeUpdate {
+            event,
+            order: order.clone(),
+            timestamp: Some(at.to_string()),
+            price: fill.map(|(_, price)| price.clone()),
+            qty: fill.map(|(qty, _)| qty.clone()),
+            position_qty: fill.map(|_| position_qty),
+        });
+    }
+
+    /// Position in `symbol`, crypto pairs are also found without their slash
+    fn position(&self, symbol: &str) -> Option<Position> {
+        let (symbol, position) = self.positions.get_key_value(symbol).or_else(|| {
+            self.positions
+                .iter()
+                .find(|(key, _)| key.replace('/', "") == symbol)
+        })?;
+        let current_price = self
+            .last_prices
+            .get(symbol)
+            .unwrap_or(&position.avg_entry_price);
+        let cost_basis = &position.qty * &position.avg_entry_price;
+        let market_value = &position.qty * current_price;
+        let unrealized_pl = &market_value - &cost_basis;
+        Some(Position {
+            asset_id: symbol.to_string(),
+            symbol: symbol.to_string(),
+            exchange: "SIM".to_string(),
+            asset_class: "sim".to_string(),
+            side: if position.qty.is_negative() {
+                PositionSide::Short
+            } else {
+                PositionSide::Long
+            },
+            qty: position.qty.clone(),
+            qty_available: Some(position.qty.clone()),
+            avg_entry_price: position.avg_entry_price.clone(),
+            current_price: Some(current_price.clone()),
+            market_value: Some(market_value),
+            unrealized_plpc: (!cost_basis.is_zero()).then(|| &unrealized_pl / abs(&cost_basis)),
+            unrealized_pl: Some(unrealized_pl),
+            cost_basis,
+        })
+    }
+
+    fn equity(&self) -> Num {
+        self.positions
+            .iter()
+            .fold(self.cash.clone(), |equity, (symbol, position)| {
+                let price = self
+                    .last_prices
+                    .get(symbol)
+                    .unwrap_or(&position.avg_entry_price);
+                equity + &position.qty * price
+            })
+    }
+}
+
+#[async_trait]
+impl Broker for SimBroker {
+    async fn get_account(&self) -> Result<Account, RequestError> {
+        let book = self.book.lock().await;
+        Ok(Account {
+            id: "sim".to_string(),
+            equity: book.equity(),
+            buying_power: book.cash.clone().max(Num::from(0)),
+            last_equity: None,
+        })
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, RequestError> {
+        let book = self.book.lock().await;
+        Ok(book
+            .positions
+            .keys()
+            .filter_map(|symbol| book.position(symbol))
+            .collect())
+    }
+
+    async fn get_position(&self, symbol: &str) -> Result<Position, RequestError> {
+        self.book
+            .lock()
+            .await
+            .position(symbol)
+            .ok_or(RequestError::ApiError(StatusCode::NOT_FOUND))
+    }
+
+    async fn close_position(
+        &self,
+        symbol: &str,
+        close: &ClosePosition,
+    ) -> Result<BrokerOrder, RequestError> {
+        close.validate()?;
+        let position = self.get_position(symbol).await?;
+        let order = Order {
+            symbol: position.symbol.clone(),
+            qty: Some(close.qty_of(&position.qty)),
+            side: match position.side {
+                PositionSide::Long => Side::Sell,
+                PositionSide::Short => Side::Buy,
+            },
+            order_type: Type::Market,
+            time_in_force: TimeInForce::Day,
+            ..Order::default()
+        };
+        self.submit_order(&order).await
+    }
+
+    async fn close_all_positions(
+        &self,
+        cancel_orders: bool,
+    ) -> Result<Vec<PositionClosure>, RequestError> {
+        if cancel_orders {
+            self.cancel_all_orders().await?;
+        }
+        let symbols: Vec<String> = self.book.lock().await.positions.keys().cloned().collect();
+        let mut closures = vec![];
+        for symbol in symbols {
+            let closure = match self
+                .close_position(&symbol, &ClosePosition::default())
+                .await
+            {
+                Ok(order) => PositionClosure {
+                    symbol,
+                    status: StatusCode::OK.as_u16(),
+                    order: Some(order),
+                },
+                Err(e) => {
+                    tracing::error!("sim: cannot close position in {}: {}", symbol, e);
+                    PositionClosure {
+                        symbol,
+                        status: match e {
+                            RequestError::ApiError(status) => status.as_u16(),
+                            _ => StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                        },
+                        order: None,
+                    }
+                }
+            };
+            closures.push(closure);
+        }
+        Ok(closures)
+    }
+
+    async fn get_asset(&self, symbol: &str) -> Result<Asset, RequestError> {
+        match &self.data {
+            Some(data) => data.get_asset(symbol).await,
+            None => Ok(Asset::unknown(symbol)),
+        }
+    }
+
+    async fn get_clock(&self) -> Result<Clock, RequestError> {
+        match &self.data {
+            Some(data) => data.get_clock().await,
+            None => Ok(Clock { is_open: true }),
+        }
+    }
+
+    async fn get_bars(
+        &self,
+        symbols: &[String],
+        params: &BarQueryParams,
+        request_type: RequestType,
+    ) -> Result<HashMap<String, Vec<Bar>>, RequestError> {
+        let Some(data) = &self.data else {
+            // replay: serve the latest bars seen so far, the simulated clock
+            // is the last bar so the time range does not apply
+            let book = self.book.lock().await;
+            let bars = symbols
+                .iter()
+                .filter_map(|symbol| {
+                    let history = book.history.get(symbol)?;
+                    let latest = history
+                        .iter()
+                        .rev()
+                        .take(params.limit)
+                        .cloned()
+                        .collect::<Vec<Bar>>();
+                    Some((symbol.clone(), latest))
+                })
+                .collect();
+            return Ok(bars);
+        };
+
+        let bars = data.get_bars(symbols, params, request_type).await?;
+        // bars are sorted latest first
+        for (symbol, symbol_bars) in &bars {
+            if let Some(bar) = symbol_bars.first() {
+                self.on_bar(symbol, bar).await;
+            }
+        }
+        Ok(bars)
+    }
+
+    async fn submit_order(&self, order: &Order) -> Result<BrokerOrder, RequestError> {
+        if let Err(e) = order.validate() {
+            tracing::error!("sim: invalid order {:?}: {}", order, e);
+            return Err(e.into());
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let info = order_info(order, "new", &now);
+        let id = info.id.clone();
+
+        let mut book = self.book.lock().await;
+        let last_price = book.last_prices.get(&order.symbol).cloned();
+        book.orders.push(SimOrder {
+            request: order.clone(),
+            info,
+            parent: None,
+            oco_group: (order.order_class == OrderClass::Oco).then(|| id.clone()),
+            hwm: None,
+        });
+        let index = book.orders.len() - 1;
+        book.notify(index, TradeEvent::New, None, &now);
+
+        // the legs of an oco order work right away, the others wait for the entry
+        let legs = leg_orders(order);
+        let leg_status = match order.order_class {
+            OrderClass::Oco => "new",
+            _ => "held",
+        };
+        let leg_group = match order.order_class {
+            OrderClass::Oco => Some(id.clone()),
+            _ if legs.len() > 1 => Some(id.clone()),
+            _ => None,
+        };
+        for leg in legs {
+            let info = order_info(&leg, leg_status, &now);
+            book.orders.push(SimOrder {
+                request: leg,
+                info,
+                parent: Some(id.clone()),
+                oco_group: leg_group.clone(),
+                hwm: None,
+            });
+            if leg_status == "new" {
+                let leg_index = book.orders.len() - 1;
+                book.notify(leg_index, TradeEvent::New, None, &now);
+            }
+        }
+
+        // marketable orders fill right away against the last known price
+        if let Some(last_price) = last_price {
+            book.orders[index].track(&last_price);
+            let stop = book.orders[index].stop();
+            if let Some(price) = match_price(
+                order,
+                stop,
+                &last_price,
+                &last_price,
+                &last_price,
+                &bps(self.config.slippage_bps),
+            ) {
+                book.fill(index, price, &bps(self.config.fee_bps), &now);
+            }
+        }
+
+        // what did not fill right away never will
+        if matches!(
+            order.time_in_force,
+            TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill
+        ) && !book.orders[index].is_closed()
+        {
+            book.close(index, "expired", TradeEvent::Expired, &now);
+        }
+
+        Ok(book.info(index))
+    }
+
+    async fn get_order(&self, order_id: &str) -> Result<BrokerOrder, RequestError> {
+        let book = self.book.lock().await;
+        let index = book.find(order_id)?;
+        Ok(book.info(index))
+    }
+
+    async fn get_order_by_client_id(
+        &self,
+        client_order_id: &str,
+    ) -> Result<BrokerOrder, RequestError> {
+        let book = self.book.lock().await;
+        let index = book
+            .orders
+            .iter()
+            .rposition(|order| order.info.client_order_id.as_deref() == Some(client_order_id))
+            .ok_or(RequestError::ApiError(StatusCode::NOT_FOUND))?;
+        Ok(book.info(index))
+    }
+
+    async fn replace_order(
+        &self,
+        order_id: &str,
+        replace: &ReplaceOrder,
+    ) -> Result<BrokerOrder, RequestError> {
+        let mut book = self.book.lock().await;
+        let index = book.find(order_id)?;
+        let previous = &book.orders[index];
+        if previous.is_closed() {
+            return Err(RequestError::ApiError(StatusCode::UNPROCESSABLE_ENTITY));
+        }
+
+        let request = replace.apply(&previous.request);
+        // legs are kept as they are, only the order itself changes
+        let check = Order {
+            order_class: OrderClass::Simple,
+            take_profit: None,
+            stop_loss: None,
+            ..request.clone()
+        };
+        if let Err(e) = check.validate() {
+            tracing::error!("sim: invalid replacement of {}: {}", order_id, e);
+            return Err(e.into());
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let status = previous.info.status.clone();
+        let mut info = order_info(&request, &status, &now);
+        info.replaces = Some(order_id.to_string());
+        let new_id = info.id.clone();
+        let replacement = SimOrder {
+            request,
+            info,
+            parent: previous.parent.clone(),
+            oco_group: previous.oco_group.clone(),
+            hwm: None,
+        };
+
+        let previous = &mut book.orders[index];
+        previous.info.status = "replaced".to_string();
+        previous.info.replaced_by = Some(new_id.clone());
+        previous.info.updated_at = Some(now.clone());
+        book.notify(index, TradeEvent::Replaced, None, &now);
+
+        // the legs and the group follow the replacement
+        book.orders.push(replacement);
+        for order in book.orders.iter_mut() {
+            if order.parent.as_deref() == Some(order_id) {
+                order.parent = Some(new_id.clone());
+            }
+            if order.oco_group.as_deref() == Some(order_id) {
+                order.oco_group = Some(new_id.clone());
+            }
+        }
+        let replacement = book.orders.len() - 1;
+        if book.orders[replacement].is_active() {
+            book.notify(replacement, TradeEvent::New, None, &now);
+        }
+        Ok(book.info(replacement))
+    }
+
+    async fn cancel_order(&self, order_id: &str) -> Result<(), RequestError> {
+        let mut book = self.book.lock().await;
+        match book
+            .orders
+            .iter()
+            .position(|order| order.info.id == order_id)
+        {
+            Some(index) if !book.orders[index].is_closed() => {
+                let now = Utc::now().to_rfc3339();
+                book.close(index, "canceled", TradeEvent::Canceled, &now);
+                Ok(())
+            }
+            Some(_) => Err(RequestError::ApiError(StatusCode::UNPROCESSABLE_ENTITY)),
+            None => Err(RequestError::ApiError(StatusCode::NOT_FOUND)),
+        }
+    }
+
+    async fn cancel_all_orders(&self) -> Result<Vec<OrderCancellation>, RequestError> {
+        let mut book = self.book.lock().await;
+        let now = Utc::now().to_rfc3339();
+        let mut canceled = vec![];
+        for index in 0..book.orders.len() {
+            // held legs may have been canceled with their parent
+            if book.orders[index].is_closed() {
+                continue;
+            }
+            book.close(index, "canceled", TradeEvent::Canceled, &now);
+            canceled.push(OrderCancellation {
+                id: book.orders[index].info.id.clone(),
+                status: StatusCode::OK.as_u16(),
+            });
+        }
+        Ok(canceled)
+    }
+
+    async fn list_orders(&self, params: &OrderParams) -> Result<Vec<BrokerOrder>, RequestError> {
+        let book = self.book.lock().await;
+        let symbols: Option<Vec<&str>> = params
+            .symbols
+            .as_ref()
+            .map(|symbols| symbols.split(',').collect());
+
+        let mut orders: Vec<BrokerOrder> = book
+            .orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| match params.status.as_deref() {
+                Some("all") => true,
+                Some("closed") => order.is_closed(),
+                _ => !order.is_closed(),
+            })
+            .filter(|(_, order)| {
+                symbols
+                    .as_ref()
+                    .is_none_or(|symbols| symbols.contains(&order.info.symbol.as_str()))
+            })
+            .filter(|(_, order)| match params.side.as_deref() {
+                Some("buy") => order.info.side == Side::Buy,
+                Some("sell") => order.info.side == Side::Sell,
+                _ => true,
+            })
+            .filter(
+                |(_, order)| match (&params.after, &order.info.submitted_at) {
+                    (Some(after), Some(submitted_at)) => {
+                        match (
+                            after.parse::<DateTime<Utc>>(),
+                            submitted_at.parse::<DateTime<Utc>>(),
+                        ) {
+                            (Ok(after), Ok(submitted_at)) => submitted_at > after,
+                            _ => true,
+                        }
+                    }
+                    _ => true,
+                },
+            )
+            .map(|(index, _)| book.info(index))
+            .collect();
+
+        if params.direction.as_deref() != Some("asc") {
+            orders.reverse();
+        }
+        orders.truncate(params.limit.unwrap_or(50) as usize);
+        Ok(orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::allocator::CapitalAllocator;
+    use crate::core::order_tracker::OrderUpdate;
+    use crate::models::order::{OrderStatus, StopLoss, TakeProfit};
+
+    fn bar(timestamp: &str, open: f64, low: f64, high: f64, close: f64) -> Bar {
+        Bar {
+            close_price: close,
+            high_price: high,
+            low_price: low,
+            n: 1,
+            open_price: open,
+            timestamp: timestamp.to_string(),
+            volume: 100.0,
+            vw: close,
+        }
+    }
+
+    fn order(side: Side, order_type: Type, qty: i32, limit_price: Option<i32>) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            qty: Some(Num::from(qty)),
+            side,
+            order_type,
+            time_in_force: TimeInForce::Day,
+            limit_price: limit_price.map(Num::from),
+            ..Order::default()
+        }
+    }
+
+    fn broker(slippage_bps: f64, fee_bps: f64) -> SimBroker {
+        SimBroker::new(
+            SimConfig {
+                initial_cash: 10_000.0,
+                slippage_bps,
+                fee_bps,
+            },
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn market_order_fills_on_next_bar_with_slippage_and_fees() {
+        let broker = broker(100.0, 10.0);
+
+        let placed = broker
+            .submit_order(&order(Side::Buy, Type::Market, 10, None))
+            .await
+            .unwrap();
+        assert_eq!(placed.status, "new");
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 101.0, 100.0),
+            )
+            .await;
+
+        let positions = broker.get_positions().await.unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].qty, Num::from(10));
+
+        // 10 shares at 101 (1% slippage) plus 0.1% fees
+        let account = broker.get_account().await.unwrap();
+        let cost = Num::from(10 * 101);
+        assert_eq!(
+            account.buying_power,
+            Num::from(10_000) - cost * Num::new(1001, 1000)
+        );
+    }
+
+    #[tokio::test]
+    async fn limit_order_fills_only_when_crossed() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 101.0, 100.0),
+            )
+            .await;
+
+        broker
+            .submit_order(&order(Side::Buy, Type::Limit, 5, Some(95)))
+            .await
+            .unwrap();
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:02:00Z", 99.0, 96.0, 100.0, 97.0),
+            )
+            .await;
+        assert!(broker.get_positions().await.unwrap().is_empty());
+
+        broker
+            .on_bar("AAPL", &bar("2024-01-01T00:03:00Z", 97.0, 94.0, 98.0, 95.0))
+            .await;
+        let positions = broker.get_positions().await.unwrap();
+        assert_eq!(positions[0].qty, Num::from(5));
+        assert_eq!(positions[0].avg_entry_price, Num::from(95));
+    }
+
+    #[tokio::test]
+    async fn cancel_removes_order_from_open_orders() {
+        let broker = broker(0.0, 0.0);
+        let placed = broker
+            .submit_order(&order(Side::Sell, Type::Limit, 1, Some(150)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            broker
+                .list_orders(&OrderParams::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        broker.cancel_order(&placed.id).await.unwrap();
+        assert!(broker
+            .list_orders(&OrderParams::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(broker.cancel_order(&placed.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn order_transitions_are_published() {
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let broker = broker(0.0, 0.0).with_trade_updates(sender);
+        let placed = broker
+            .submit_order(&order(Side::Buy, Type::Limit, 2, Some(100)))
+            .await
+            .unwrap();
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 101.0, 99.0, 102.0, 100.0),
+            )
+            .await;
+
+        let new = updates.recv().await.unwrap();
+        assert_eq!(new.event, TradeEvent::New);
+        assert_eq!(new.order.id, placed.id);
+        let fill = updates.recv().await.unwrap();
+        assert_eq!(fill.event, TradeEvent::Fill);
+        assert_eq!(fill.price, Some(Num::from(100)));
+        assert_eq!(fill.position_qty, Some(Num::from(2)));
+    }
+
+    #[tokio::test]
+    async fn buy_beyond_cash_is_rejected() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 101.0, 100.0),
+            )
+            .await;
+
+        let placed = broker
+            .submit_order(&order(Side::Buy, Type::Market, 1_000, None))
+            .await
+            .unwrap();
+        assert_eq!(placed.status, "rejected");
+    }
+
+    #[tokio::test]
+    async fn stop_order_triggers_at_its_stop_price() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .submit_order(&order(Side::Buy, Type::Market, 10, None))
+            .await
+            .unwrap();
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 101.0, 100.0),
+            )
+            .await;
+
+        let stop = Order {
+            stop_price: Some(Num::from(95)),
+            ..order(Side::Sell, Type::Stop, 10, None)
+        };
+        broker.submit_order(&stop).await.unwrap();
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:02:00Z", 99.0, 96.0, 100.0, 97.0),
+            )
+            .await;
+        assert_eq!(broker.get_positions().await.unwrap().len(), 1);
+
+        broker
+            .on_bar("AAPL", &bar("2024-01-01T00:03:00Z", 97.0, 93.0, 98.0, 94.0))
+            .await;
+        assert!(broker.get_positions().await.unwrap().is_empty());
+        let fills = broker.fills().await;
+        assert_eq!(fills[1].price, Num::from(95));
+        assert_eq!(fills[1].realized_pnl, Some(Num::from(-50)));
+    }
+
+    #[tokio::test]
+    async fn bracket_legs_activate_on_entry_and_cancel_each_other() {
+        let broker = broker(0.0, 0.0);
+        let bracket = Order {
+            order_class: OrderClass::Bracket,
+            take_profit: Some(TakeProfit {
+                limit_price: Num::from(110),
+            }),
+            stop_loss: Some(StopLoss {
+                stop_price: Num::from(90),
+                limit_price: None,
+            }),
+            ..order(Side::Buy, Type::Limit, 10, Some(100))
+        };
+        let placed = broker.submit_order(&bracket).await.unwrap();
+        let legs = placed.legs.unwrap();
+        assert_eq!(legs.len(), 2);
+        assert!(legs.iter().all(|leg| leg.status == "held"));
+
+        // the take profit is crossed but the entry is not filled yet
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 112.0, 101.0, 115.0, 105.0),
+            )
+            .await;
+        assert!(broker.fills().await.is_empty());
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:02:00Z", 101.0, 99.0, 102.0, 100.0),
+            )
+            .await;
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:03:00Z", 105.0, 104.0, 111.0, 110.0),
+            )
+            .await;
+
+        let fills = broker.fills().await;
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].price, Num::from(110));
+        assert!(broker.get_positions().await.unwrap().is_empty());
+        let open = broker.list_orders(&OrderParams::default()).await.unwrap();
+        assert!(open.is_empty());
+
+        let params = OrderParams {
+            status: Some("all".to_string()),
+            ..OrderParams::default()
+        };
+        let orders = broker.list_orders(&params).await.unwrap();
+        let stop = orders
+            .iter()
+            .find(|order| order.order_type == Type::Stop)
+            .unwrap();
+        assert_eq!(stop.status, "canceled");
+    }
+
+    #[tokio::test]
+    async fn stop_leg_fill_flattens_the_book_of_the_bot() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let broker = broker(0.0, 0.0).with_trade_updates(sender);
+        let allocator = CapitalAllocator::new();
+        let entry = Order {
+            order_class: OrderClass::Oto,
+            stop_loss: Some(StopLoss {
+                stop_price: Num::from(90),
+                limit_price: None,
+            }),
+            client_order_id: Some(bot_client_order_id("bot")),
+            ..order(Side::Buy, Type::Limit, 10, Some(100))
+        };
+        broker.submit_order(&entry).await.unwrap();
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 101.0, 99.0, 102.0, 100.0),
+            )
+            .await;
+        broker
+            .on_bar("AAPL", &bar("2024-01-01T00:02:00Z", 95.0, 85.0, 96.0, 88.0))
+            .await;
+
+        let mut fills = 0;
+        while let Ok(update) = receiver.try_recv() {
+            fills += update.qty.is_some() as usize;
+            allocator.on_order_update(&OrderUpdate {
+                event: update.event,
+                status: OrderStatus::New,
+                order: update.order,
+                price: update.price,
+                qty: update.qty,
+            });
+        }
+        assert_eq!(fills, 2);
+        assert!(broker.get_positions().await.unwrap().is_empty());
+        let book = allocator.portfolio("bot").unwrap();
+        assert!(book.position("AAPL").is_zero());
+    }
+
+    #[tokio::test]
+    async fn open_orders_are_listed_page_by_page() {
+        let broker = broker(0.0, 0.0);
+        for _ in 0..510 {
+            broker
+                .submit_order(&order(Side::Buy, Type::Limit, 1, Some(1)))
+                .await
+                .unwrap();
+        }
+        let params = OrderParams {
+            limit: Some(500),
+            ..OrderParams::default()
+        };
+        assert_eq!(broker.list_orders(&params).await.unwrap().len(), 500);
+
+        let open = broker.list_open_orders(None).await.unwrap();
+        assert_eq!(open.len(), 510);
+        let ids: std::collections::HashSet<&str> =
+            open.iter().map(|order| order.id.as_str()).collect();
+        assert_eq!(ids.len(), 510);
+    }
+
+    #[tokio::test]
+    async fn trailing_stop_follows_the_price() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .submit_order(&order(Side::Buy, Type::Market, 1, None))
+            .await
+            .unwrap();
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 100.0, 100.0),
+            )
+            .await;
+
+        let trailing = Order {
+            trail_price: Some(Num::from(5)),
+            ..order(Side::Sell, Type::TrailingStop, 1, None)
+        };
+        broker.submit_order(&trailing).await.unwrap();
+        // the stop moves up to 115 with the high of 120
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:02:00Z", 101.0, 100.0, 120.0, 118.0),
+            )
+            .await;
+        assert!(!broker.get_positions().await.unwrap().is_empty());
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:03:00Z", 117.0, 112.0, 118.0, 113.0),
+            )
+            .await;
+        assert!(broker.get_positions().await.unwrap().is_empty());
+        assert_eq!(broker.fills().await[1].price, Num::from(115));
+    }
+
+    #[tokio::test]
+    async fn unfilled_immediate_or_cancel_order_expires() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 99.0, 101.0, 100.0),
+            )
+            .await;
+
+        let ioc = Order {
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            ..order(Side::Buy, Type::Limit, 1, Some(95))
+        };
+        let placed = broker.submit_order(&ioc).await.unwrap();
+        assert_eq!(placed.status, "expired");
+
+        let ioc = Order {
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            ..order(Side::Buy, Type::Limit, 1, Some(105))
+        };
+        let placed = broker.submit_order(&ioc).await.unwrap();
+        assert_eq!(placed.status, "filled");
+    }
+
+    #[tokio::test]
+    async fn replaced_order_works_at_its_new_price() {
+        let broker = broker(0.0, 0.0);
+        let placed = broker
+            .submit_order(&Order {
+                client_order_id: Some("bot:1".to_string()),
+                ..order(Side::Buy, Type::Limit, 1, Some(90))
+            })
+            .await
+            .unwrap();
+
+        let replace = ReplaceOrder {
+            limit_price: Some(Num::from(99)),
+            ..ReplaceOrder::default()
+        };
+        let replacement = broker.replace_order(&placed.id, &replace).await.unwrap();
+        assert_eq!(replacement.replaces.as_deref(), Some(placed.id.as_str()));
+        assert_eq!(replacement.limit_price, Some(Num::from(99)));
+        let previous = broker.get_order(&placed.id).await.unwrap();
+        assert_eq!(previous.status, "replaced");
+        assert_eq!(previous.replaced_by, Some(replacement.id.clone()));
+        assert!(broker.replace_order(&placed.id, &replace).await.is_err());
+
+        broker
+            .on_bar(
+                "AAPL",
+                &bar("2024-01-01T00:01:00Z", 100.0, 98.0, 101.0, 100.0),
+            )
+            .await;
+        let filled = broker.get_order_by_client_id("bot:1").await.unwrap();
+        assert_eq!(filled.id, replacement.id);
+        assert_eq!(filled.status, "filled");
+        assert_eq!(filled.filled_avg_price, Some(Num::from(99)));
+    }
+
+    #[tokio::test]
+    async fn cancel_all_orders_cancels_the_open_ones() {
+        let broker = broker(0.0, 0.0);
+        let bracket = Order {
+            order_class: OrderClass::Bracket,
+            take_profit: Some(TakeProfit {
+                limit_price: Num::from(110),
+            }),
+            stop_loss: Some(StopLoss {
+                stop_price: Num::from(90),
+                limit_price: None,
+            }),
+            ..order(Side::Buy, Type::Limit, 1, Some(100))
+        };
+        broker.submit_order(&bracket).await.unwrap();
+        broker
+            .submit_order(&order(Side::Sell, Type::Limit, 1, Some(150)))
+            .await
+            .unwrap();
+
+        let canceled = broker.cancel_all_orders().await.unwrap();
+        assert_eq!(canceled.len(), 2);
+        assert!(canceled
+            .iter()
+            .all(|cancellation| cancellation.status == 200));
+        assert!(broker
+            .list_orders(&OrderParams::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(broker.get_order("unknown").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn positions_are_closed_at_market() {
+        let broker = broker(0.0, 0.0);
+        broker
+            .on_bar("AAPL", &bar("t0", 100.0, 100.0, 100.0, 100.0))
+            .await;
+        broker
+            .submit_order(&order(Side::Buy, Type::Market, 10, None))
+            .await
+            .unwrap();
+        broker
+            .on_bar("AAPL", &bar("t1", 110.0, 110.0, 110.0, 110.0))
+            .await;
+
+        let position = broker.get_position("AAPL").await.unwrap();
+        assert_eq!(position.side, PositionSide::Long);
+        assert_eq!(position.cost_basis, Num::from(1000));
+        assert_eq!(position.market_value, Some(Num::from(1100)));
+        assert_eq!(position.unrealized_pl, Some(Num::from(100)));
+        assert_eq!(position.unrealized_plpc, Some(Num::new(1, 10)));
+
+        let close = ClosePosition {
+            percentage: Some(Num::from(40)),
+            ..ClosePosition::default()
+        };
+        let closing = broker.close_position("AAPL", &close).await.unwrap();
+        assert_eq!(closing.side, Side::Sell);
+        assert_eq!(closing.status, "filled");
+        let position = broker.get_position("AAPL").await.unwrap();
+        assert_eq!(position.qty, Num::from(6));
+
+        broker
+            .submit_order(&order(Side::Buy, Type::Limit, 1, Some(50)))
+            .await
+            .unwrap();
+        let closures = broker.close_all_positions(true).await.unwrap();
+        assert_eq!(closures.len(), 1);
+        assert_eq!(closures[0].status, 200);
+        assert!(broker.get_positions().await.unwrap().is_empty());
+        assert!(broker
+            .list_orders(&OrderParams::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(broker.get_position("AAPL").await.is_err());
+    }
+}
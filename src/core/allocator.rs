@@ -0,0 +1,551 @@
+use crate::bot::BotConfig;
+use crate::core::order_tracker::OrderUpdate;
+use crate::models::account::Account;
+use crate::models::bar::Bar;
+use crate::models::num_from_f64;
+use crate::models::order::{client_order_bot_id, Fill, Order};
+use crate::models::trade::Side;
+use chrono::{DateTime, NaiveDate, Utc};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Capital assigned to a bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Allocation {
+    /// Fixed amount of cash
+    Amount(Num),
+    /// Percentage of the account equity when the bot first starts
+    Percent(Num),
+}
+
+impl Allocation {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Allocation::Amount(amount) if !amount.is_positive() => {
+                Err("amount must be positive".to_string())
+            }
+            Allocation::Percent(percent) if !percent.is_positive() || *percent > Num::from(100) => {
+                Err("percent must be between 0 and 100".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Capital of the allocation out of an account of `equity`
+    pub fn capital(&self, equity: &Num) -> Num {
+        match self {
+            Allocation::Amount(amount) => amount.clone(),
+            Allocation::Percent(percent) => equity * percent / 100,
+        }
+    }
+}
+
+/// Position of a virtual book
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VirtualPosition {
+    pub symbol: String,
+    /// Quantity held, negative when short
+    pub qty: Num,
+    pub avg_entry_price: Num,
+    /// Close of the latest bar, the entry price until a bar is seen
+    pub current_price: Num,
+    pub market_value: Num,
+    pub unrealized_pl: Num,
+}
+
+/// Virtual book of a bot, built from the fills of its orders
+#[derive(Debug, Clone, Serialize)]
+pub struct Portfolio {
+    pub bot_id: String,
+    pub allocation: Option<Allocation>,
+    /// Capital allocated to the bot
+    pub capital: Num,
+    pub cash: Num,
+    /// Cash committed to the open orders of the bot
+    pub reserved: Num,
+    /// Cash left for new orders, unlimited without an allocation
+    pub buying_power: Option<Num>,
+    pub equity: Num,
+    pub realized_pl: Num,
+    pub unrealized_pl: Num,
+    /// Change of the equity since the first fill or check of the day
+    pub daily_pl: Num,
+    pub positions: Vec<VirtualPosition>,
+}
+
+impl Portfolio {
+    /// The book as an account, what the sizing models of the bot see
+    pub fn account(&self) -> Account {
+        Account {
+            id: self.bot_id.clone(),
+            equity: self.equity.clone(),
+            buying_power: self
+                .buying_power
+                .clone()
+                .unwrap_or_else(|| self.equity.clone()),
+            last_equity: None,
+        }
+    }
+
+    /// Quantity held in `symbol`, crypto pairs match with or without their
+    /// slash
+    pub fn position(&self, symbol: &str) -> Num {
+        self.positions
+            .iter()
+            .find(|position| position.symbol.replace('/', "") == symbol.replace('/', ""))
+            .map_or_else(|| Num::from(0), |position| position.qty.clone())
+    }
+}
+
+fn abs(value: &Num) -> Num {
+    if value.is_negative() {
+        -value
+    } else {
+        value.clone()
+    }
+}
+
+/// Part of a quantity, signed by side, opening or increasing a position of
+/// `held`
+pub fn opening_qty(held: &Num, qty: &Num) -> Num {
+    if held.is_zero() || held.is_positive() == qty.is_positive() {
+        abs(qty)
+    } else {
+        (abs(qty) - abs(held)).max(Num::from(0))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Book {
+    allocation: Option<Allocation>,
+    capital: Num,
+    cash: Num,
+    /// Quantity and average entry price, per symbol
+    positions: HashMap<String, (Num, Num)>,
+    realized: Num,
+    /// Cash reserved per open order
+    reservations: HashMap<String, Num>,
+    day: Option<NaiveDate>,
+    day_start_equity: Num,
+    /// Whether the positions were rebuilt from the recorded fills
+    seeded: bool,
+}
+
+impl Book {
+    fn equity(&self, closes: &HashMap<String, (DateTime<Utc>, Num)>) -> Num {
+        self.positions
+            .iter()
+            .fold(self.cash.clone(), |equity, (symbol, (qty, avg_price))| {
+                let price = closes.get(symbol).map_or(avg_price, |(_, close)| close);
+                equity + qty * price
+            })
+    }
+
+    /// Start the daily profit from the equity of the first event of the day
+    fn roll(&mut self, today: NaiveDate, closes: &HashMap<String, (DateTime<Utc>, Num)>) {
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.day_start_equity = self.equity(closes);
+        }
+    }
+
+    fn fill(&mut self, symbol: &str, qty: &Num, price: &Num) {
+        self.cash = &self.cash - qty * price;
+        let (held, avg_price) = self
+            .positions
+            .remove(symbol)
+            .unwrap_or((Num::from(0), Num::from(0)));
+        let new_qty = &held + qty;
+        let avg_price = if held.is_zero() || held.is_positive() == qty.is_positive() {
+            (abs(&held) * &avg_price + abs(qty) * price) / abs(&new_qty)
+        } else {
+            let pnl = abs(qty).min(abs(&held)) * (price - &avg_price);
+            self.realized = if held.is_positive() {
+                &self.realized + pnl
+            } else {
+                &self.realized - pnl
+            };
+            // the position flipped side
+            if !new_qty.is_zero() && new_qty.is_positive() != held.is_positive() {
+                price.clone()
+            } else {
+                avg_price
+            }
+        };
+        if !new_qty.is_zero() {
+            self.positions
+                .insert(symbol.to_string(), (new_qty, avg_price));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Books {
+    books: HashMap<String, Book>,
+    /// Timestamp and close of the latest bar, per symbol
+    closes: HashMap<String, (DateTime<Utc>, Num)>,
+}
+
+/// Capital of the bots sharing the account.
+///
+/// Each bot gets a virtual book from the fills of its orders, tagged by
+/// their client order id. A bot with an allocation sizes against its book
+/// and cannot commit more cash than the book holds, the others share the
+/// account.
+#[derive(Debug, Default)]
+pub struct CapitalAllocator {
+    books: Mutex<Books>,
+}
+
+impl CapitalAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the book of a bot with `capital`, or update its allocation.
+    ///
+    /// The first time a book is opened in the process its positions are
+    /// rebuilt from `fills`, the recorded fills of the bot oldest first, so
+    /// a restart does not free the cash of the open positions.
+    pub fn open(&self, config: &BotConfig, capital: &Num, fills: &[Fill]) {
+        let capital = match config.allocation {
+            Some(_) => capital.clone(),
+            None => Num::from(0),
+        };
+        let mut books = self.books.lock().unwrap();
+        let book = books.books.entry(config.id.clone()).or_default();
+        if !book.seeded {
+            // the fills already booked from the order updates are recorded too
+            book.cash = book.capital.clone();
+            book.positions.clear();
+            book.realized = Num::from(0);
+            for fill in fills {
+                let qty = if fill.side == Side::Sell.as_str() {
+                    -&fill.qty
+                } else {
+                    fill.qty.clone()
+                };
+                book.fill(&fill.symbol, &qty, &fill.price);
+            }
+            book.seeded = true;
+        }
+        book.cash = &book.cash + &capital - &book.capital;
+        book.capital = capital;
+        book.allocation = config.allocation.clone();
+        tracing::info!(
+            "Bot {} trades with {} of capital",
+            config.id,
+            if book.allocation.is_some() {
+                book.capital.to_string()
+            } else {
+                "the account".to_string()
+            }
+        );
+    }
+
+    /// Remember the close of `bar`, the marks of the books
+    pub fn on_bar(&self, symbol: &str, bar: &Bar) {
+        let (Ok(timestamp), Some(close)) = (
+            bar.timestamp.parse::<DateTime<Utc>>(),
+            num_from_f64(bar.close_price),
+        ) else {
+            return;
+        };
+        let mut books = self.books.lock().unwrap();
+        if books
+            .closes
+            .get(symbol)
+            .is_some_and(|(last, _)| *last >= timestamp)
+        {
+            return;
+        }
+        books.closes.insert(symbol.to_string(), (timestamp, close));
+    }
+
+    /// Book the fills of the orders of the bots and release the cash of
+    /// their closed orders
+    pub fn on_order_update(&self, update: &OrderUpdate) {
+        let Some(bot_id) = update
+            .order
+            .client_order_id
+            .as_deref()
+            .and_then(client_order_bot_id)
+        else {
+            return;
+        };
+        let mut books = self.books.lock().unwrap();
+        let Books { books, closes } = &mut *books;
+        let book = books.entry(bot_id.to_string()).or_default();
+        book.roll(Utc::now().date_naive(), closes);
+
+        if let (Some(price), Some(qty)) = (&update.price, &update.qty) {
+            let signed_qty = match update.order.side {
+                Side::Buy => qty.clone(),
+                Side::Sell => -qty,
+            };
+            book.fill(&update.order.symbol, &signed_qty, price);
+            if let Some(reserved) = book.reservations.get_mut(&update.order.id) {
+                *reserved = (&*reserved - qty * price).max(Num::from(0));
+            }
+        }
+        if update.status.is_terminal() {
+            book.reservations.remove(&update.order.id);
+        }
+    }
+
+    /// Reserve the cash of an order placed by a bot with an allocation until
+    /// it fills or closes, market orders are valued at the last close
+    pub fn reserve(&self, bot_id: &str, order_id: &str, order: &Order) {
+        let mut books = self.books.lock().unwrap();
+        let Books { books, closes } = &mut *books;
+        let Some(book) = books
+            .get_mut(bot_id)
+            .filter(|book| book.allocation.is_some())
+        else {
+            return;
+        };
+        let Some(price) = order
+            .limit_price
+            .as_ref()
+            .or(order.stop_price.as_ref())
+            .or_else(|| closes.get(&order.symbol).map(|(_, close)| close))
+        else {
+            return;
+        };
+        let held = book
+            .positions
+            .get(&order.symbol)
+            .map_or_else(|| Num::from(0), |(qty, _)| qty.clone());
+        let qty = match (&order.qty, &order.national) {
+            (Some(qty), _) => qty.clone(),
+            (None, Some(notional)) if price.is_positive() => notional / price,
+            _ => return,
+        };
+        let signed_qty = match order.side {
+            Side::Buy => qty,
+            Side::Sell => -qty,
+        };
+        let reserved = opening_qty(&held, &signed_qty) * price;
+        if reserved.is_positive() {
+            book.reservations.insert(order_id.to_string(), reserved);
+        }
+    }
+
+    /// Virtual book of a bot
+    pub fn portfolio(&self, bot_id: &str) -> Option<Portfolio> {
+        let mut books = self.books.lock().unwrap();
+        let Books { books, closes } = &mut *books;
+        let book = books.get_mut(bot_id)?;
+        book.roll(Utc::now().date_naive(), closes);
+
+        let mut positions: Vec<VirtualPosition> = book
+            .positions
+            .iter()
+            .map(|(symbol, (qty, avg_price))| {
+                let current_price = closes
+                    .get(symbol)
+                    .map_or_else(|| avg_price.clone(), |(_, close)| close.clone());
+                VirtualPosition {
+                    symbol: symbol.clone(),
+                    qty: qty.clone(),
+                    avg_entry_price: avg_price.clone(),
+                    market_value: qty * &current_price,
+                    unrealized_pl: qty * (&current_price - avg_price),
+                    current_price,
+                }
+            })
+            .collect();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        let reserved = book
+            .reservations
+            .values()
+            .fold(Num::from(0), |reserved, order| reserved + order);
+        let equity = book.equity(closes);
+        Some(Portfolio {
+            bot_id: bot_id.to_string(),
+            allocation: book.allocation.clone(),
+            capital: book.capital.clone(),
+            buying_power: book
+                .allocation
+                .as_ref()
+                .map(|_| (&book.cash - &reserved).max(Num::from(0))),
+            cash: book.cash.clone(),
+            reserved,
+            realized_pl: book.realized.clone(),
+            unrealized_pl: positions
+                .iter()
+                .fold(Num::from(0), |pnl, position| pnl + &position.unrealized_pl),
+            daily_pl: &equity - &book.day_start_equity,
+            equity,
+            positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::order::{bot_client_order_id, BrokerOrder, OrderStatus, TradeEvent};
+    use crate::models::trade::{TimeInForce, Type};
+
+    fn config(allocation: Option<Allocation>) -> BotConfig {
+        BotConfig {
+            allocation,
+            ..BotConfig::test_default()
+        }
+    }
+
+    fn update(id: &str, side: Side, status: OrderStatus, fill: Option<(i32, i32)>) -> OrderUpdate {
+        OrderUpdate {
+            event: match fill {
+                Some(_) => TradeEvent::Fill,
+                None => TradeEvent::Canceled,
+            },
+            status,
+            order: BrokerOrder {
+                id: id.to_string(),
+                client_order_id: Some(bot_client_order_id("bot")),
+                symbol: "AAPL".to_string(),
+                side,
+                order_type: Type::Limit,
+                time_in_force: TimeInForce::Day,
+                ..BrokerOrder::default()
+            },
+            price: fill.map(|(_, price)| Num::from(price)),
+            qty: fill.map(|(qty, _)| Num::from(qty)),
+        }
+    }
+
+    fn buy(qty: i32) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            qty: Some(Num::from(qty)),
+            side: Side::Buy,
+            order_type: Type::Limit,
+            time_in_force: TimeInForce::Day,
+            limit_price: Some(Num::from(100)),
+            ..Order::default()
+        }
+    }
+
+    #[test]
+    fn book_of_a_bot_from_its_fills() {
+        let allocator = CapitalAllocator::new();
+        let allocation = Allocation::Percent(Num::from(10));
+        allocator.open(
+            &config(Some(allocation.clone())),
+            &allocation.capital(&Num::from(50_000)),
+            &[],
+        );
+
+        allocator.reserve("bot", "o1", &buy(20));
+        let portfolio = allocator.portfolio("bot").unwrap();
+        assert_eq!(portfolio.capital, Num::from(5_000));
+        assert_eq!(portfolio.buying_power, Some(Num::from(3_000)));
+
+        allocator.on_order_update(&update(
+            "o1",
+            Side::Buy,
+            OrderStatus::PartiallyFilled,
+            Some((10, 100)),
+        ));
+        allocator.on_order_update(&update("o1", Side::Buy, OrderStatus::Canceled, None));
+        allocator.on_order_update(&update(
+            "o2",
+            Side::Sell,
+            OrderStatus::Filled,
+            Some((4, 110)),
+        ));
+        allocator.on_bar(
+            "AAPL",
+            &Bar {
+                close_price: 120.0,
+                high_price: 120.0,
+                low_price: 120.0,
+                n: 1,
+                open_price: 120.0,
+                timestamp: "2024-01-02T15:00:00Z".to_string(),
+                volume: 10.0,
+                vw: 120.0,
+            },
+        );
+
+        let portfolio = allocator.portfolio("bot").unwrap();
+        assert_eq!(portfolio.cash, Num::from(4_440));
+        assert_eq!(portfolio.reserved, Num::from(0));
+        assert_eq!(portfolio.realized_pl, Num::from(40));
+        assert_eq!(portfolio.unrealized_pl, Num::from(120));
+        assert_eq!(portfolio.equity, Num::from(5_160));
+        assert_eq!(portfolio.position("AAPL"), Num::from(6));
+        assert_eq!(portfolio.account().buying_power, Num::from(4_440));
+
+        // a bigger allocation keeps the profit
+        allocator.open(
+            &config(Some(Allocation::Amount(Num::from(10_000)))),
+            &Num::from(10_000),
+            &[],
+        );
+        assert_eq!(allocator.portfolio("bot").unwrap().cash, Num::from(9_440));
+    }
+
+    #[test]
+    fn books_are_rebuilt_from_the_recorded_fills() {
+        let fill = |id: i64, side: Side, qty: i32, price: i32| Fill {
+            id,
+            order_id: format!("o{}", id),
+            bot_id: Some("bot".to_string()),
+            symbol: "AAPL".to_string(),
+            side: side.as_str().to_string(),
+            qty: Num::from(qty),
+            price: Num::from(price),
+            timestamp: Utc::now(),
+        };
+        let fills = [fill(1, Side::Buy, 10, 100), fill(2, Side::Sell, 4, 110)];
+        let allocator = CapitalAllocator::new();
+        // an update seen before the bot opens is recorded with the fills
+        allocator.on_order_update(&update(
+            "o2",
+            Side::Sell,
+            OrderStatus::Filled,
+            Some((4, 110)),
+        ));
+        allocator.open(
+            &config(Some(Allocation::Amount(Num::from(5_000)))),
+            &Num::from(5_000),
+            &fills,
+        );
+
+        let portfolio = allocator.portfolio("bot").unwrap();
+        assert_eq!(portfolio.position("AAPL"), Num::from(6));
+        assert_eq!(portfolio.cash, Num::from(4_440));
+        assert_eq!(portfolio.realized_pl, Num::from(40));
+
+        // reopened in the same process, the book is kept
+        allocator.open(
+            &config(Some(Allocation::Amount(Num::from(5_000)))),
+            &Num::from(5_000),
+            &fills,
+        );
+        assert_eq!(allocator.portfolio("bot").unwrap().cash, Num::from(4_440));
+    }
+
+    #[test]
+    fn only_opening_quantities_need_cash() {
+        assert_eq!(opening_qty(&Num::from(0), &Num::from(-5)), Num::from(5));
+        assert_eq!(opening_qty(&Num::from(10), &Num::from(-4)), Num::from(0));
+        assert_eq!(opening_qty(&Num::from(10), &Num::from(-14)), Num::from(4));
+        assert_eq!(opening_qty(&Num::from(-2), &Num::from(-3)), Num::from(3));
+
+        let allocator = CapitalAllocator::new();
+        allocator.open(&config(None), &Num::from(50_000), &[]);
+        allocator.reserve("bot", "o1", &buy(20));
+        let portfolio = allocator.portfolio("bot").unwrap();
+        assert_eq!(portfolio.buying_power, None);
+        assert_eq!(portfolio.reserved, Num::from(0));
+        assert!(Allocation::Percent(Num::from(120)).validate().is_err());
+    }
+}
@@ -0,0 +1,440 @@
+use crate::bot::MarketType;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use crate::stream::message::Trade;
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, SecondsFormat, TimeZone, Utc, Weekday,
+};
+use std::collections::HashMap;
+
+/// Length of the regular session of the US equity markets, 9:30 to 16:00
+const EQUITY_SESSION_MINUTES: i64 = 390;
+
+/// Offset of New York from UTC at `at`. Daylight saving time runs from the
+/// second Sunday of March to the first Sunday of November, at 2am local time.
+fn new_york_offset(at: DateTime<Utc>) -> FixedOffset {
+    let sunday =
+        |month, n| NaiveDate::from_weekday_of_month_opt(at.year(), month, Weekday::Sun, n).unwrap();
+    // 2am EST is 7am UTC, 2am EDT is 6am UTC
+    let dst_start = sunday(3, 2).and_hms_opt(7, 0, 0).unwrap().and_utc();
+    let dst_end = sunday(11, 1).and_hms_opt(6, 0, 0).unwrap().and_utc();
+    let hours = if at >= dst_start && at < dst_end {
+        -4
+    } else {
+        -5
+    };
+    FixedOffset::east_opt(hours * 3600).unwrap()
+}
+
+/// Start and end of the trading day of `market` holding `at`: the regular
+/// session in New York for equities, the UTC day for crypto
+fn trading_day(market: &MarketType, at: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    match market {
+        MarketType::Crypto => {
+            let midnight = at.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+            (midnight, midnight + Duration::days(1))
+        }
+        MarketType::Equity => {
+            let offset = new_york_offset(at);
+            let date = at.with_timezone(&offset).date_naive();
+            let open = offset
+                .from_local_datetime(&date.and_hms_opt(9, 30, 0).unwrap())
+                .unwrap()
+                .with_timezone(&Utc);
+            (open, open + Duration::minutes(EQUITY_SESSION_MINUTES))
+        }
+    }
+}
+
+/// Start of the history holding the last `bars` bars of `timeframe` before
+/// `now`, with the number of 1 minute bars it has at most. Equity bars only
+/// cover the regular sessions, the history leaves room for the weekends and
+/// the holidays.
+pub fn history(
+    market: &MarketType,
+    timeframe: Timeframe,
+    bars: usize,
+    now: DateTime<Utc>,
+) -> (DateTime<Utc>, usize) {
+    // the bar in progress is not part of the history
+    let bars = bars as i64 + 1;
+    let minutes = timeframe.minutes() as i64;
+    match market {
+        MarketType::Crypto => (
+            now - Duration::minutes(bars * minutes),
+            (bars * minutes) as usize,
+        ),
+        MarketType::Equity => {
+            let per_day = (EQUITY_SESSION_MINUTES + minutes - 1) / minutes;
+            let days = (bars + per_day - 1) / per_day;
+            let start = now - Duration::days(days * 7 / 5 + 5);
+            (start, (days * EQUITY_SESSION_MINUTES) as usize)
+        }
+    }
+}
+
+/// Start and end of the bar of `timeframe` holding `at`, none outside of the
+/// trading day. Bars are aligned on the start of the trading day, the last
+/// one of the day ends with it.
+pub fn bucket(
+    market: &MarketType,
+    timeframe: Timeframe,
+    at: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (open, close) = trading_day(market, at);
+    if at < open || at >= close {
+        return None;
+    }
+    let minutes = timeframe.minutes() as i64;
+    let start = open + Duration::minutes((at - open).num_minutes() / minutes * minutes);
+    Some((start, (start + timeframe.duration()).min(close)))
+}
+
+/// Bar of a higher timeframe being built
+#[derive(Debug, Clone)]
+struct Bucket {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bar: Bar,
+    /// Time covered by the bars and trades added so far
+    until: DateTime<Utc>,
+    /// Started after the beginning of the bucket, it is never emitted
+    partial: bool,
+    emitted: bool,
+}
+
+/// Price a bar traded at on average
+fn average_price(bar: &Bar) -> f64 {
+    if bar.vw > 0.0 {
+        bar.vw
+    } else {
+        bar.close_price
+    }
+}
+
+/// Add `bar` to the end of `into`
+fn merge(into: &mut Bar, bar: &Bar) {
+    let volume = into.volume + bar.volume;
+    if volume > 0.0 {
+        into.vw = (average_price(into) * into.volume + average_price(bar) * bar.volume) / volume;
+    }
+    into.high_price = into.high_price.max(bar.high_price);
+    into.low_price = into.low_price.min(bar.low_price);
+    into.close_price = bar.close_price;
+    into.volume = volume;
+    into.n += bar.n;
+}
+
+/// Builds the bars of higher timeframes from the 1 minute bars or the
+/// trades of the market, so a bot needs a single feed for all its
+/// timeframes.
+///
+/// A bar is complete once its last minute is in, or once the first bar or
+/// trade of a later one comes for the quiet markets. The bar the aggregator
+/// starts in the middle of is dropped rather than emitted incomplete, and so
+/// are the bars and trades older than the ones already added.
+#[derive(Debug, Clone)]
+pub struct BarAggregator {
+    market: MarketType,
+    timeframes: Vec<Timeframe>,
+    buckets: HashMap<(String, Timeframe), Bucket>,
+}
+
+impl BarAggregator {
+    /// Aggregator of the `timeframes` above the base one
+    pub fn new(market: MarketType, timeframes: &[Timeframe]) -> Self {
+        Self {
+            market,
+            timeframes: timeframes
+                .iter()
+                .copied()
+                .filter(|timeframe| *timeframe != Timeframe::BASE)
+                .collect(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Timeframes built by the aggregator
+    pub fn timeframes(&self) -> &[Timeframe] {
+        &self.timeframes
+    }
+
+    /// Add a 1 minute bar, returns the bars it completes
+    pub fn push_bar(&mut self, symbol: &str, bar: &Bar) -> Vec<(Timeframe, Bar)> {
+        let at = match bar.timestamp.parse::<DateTime<Utc>>() {
+            Ok(at) => at,
+            Err(e) => {
+                tracing::warn!(
+                    "Not aggregating bar with bad timestamp {}: {}",
+                    bar.timestamp,
+                    e
+                );
+                return vec![];
+            }
+        };
+        self.push(symbol, bar, at, at + Timeframe::BASE.duration())
+    }
+
+    /// Add a trade, returns the bars it completes
+    pub fn push_trade(&mut self, trade: &Trade) -> Vec<(Timeframe, Bar)> {
+        let at = match trade.timestamp.parse::<DateTime<Utc>>() {
+            Ok(at) => at,
+            Err(e) => {
+                tracing::warn!(
+                    "Not aggregating trade with bad timestamp {}: {}",
+                    trade.timestamp,
+                    e
+                );
+                return vec![];
+            }
+        };
+        let bar = Bar {
+            close_price: trade.price,
+            high_price: trade.price,
+            low_price: trade.price,
+            n: 1,
+            open_price: trade.price,
+            timestamp: trade.timestamp.clone(),
+            volume: trade.size,
+            vw: trade.price,
+        };
+        self.push(&trade.symbol, &bar, at, at)
+    }
+
+    /// Complete the bars that ended by `now` without a later bar or trade to
+    /// close them, returns them with their symbol
+    pub fn flush(&mut self, now: DateTime<Utc>) -> Vec<(String, Timeframe, Bar)> {
+        let mut completed = Vec::new();
+        for ((symbol, timeframe), bucket) in self.buckets.iter_mut() {
+            if bucket.emitted || bucket.end > now {
+                continue;
+            }
+            bucket.emitted = true;
+            if !bucket.partial {
+                completed.push((symbol.clone(), *timeframe, bucket.bar.clone()));
+            }
+        }
+        completed.sort_by(|a, b| a.2.timestamp.cmp(&b.2.timestamp));
+        completed
+    }
+
+    /// Add `bar`, covering the time from `at` to `until`
+    fn push(
+        &mut self,
+        symbol: &str,
+        bar: &Bar,
+        at: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<(Timeframe, Bar)> {
+        let mut completed = Vec::new();
+        for &timeframe in &self.timeframes {
+            let Some((start, end)) = bucket(&self.market, timeframe, at) else {
+                continue;
+            };
+            let key = (symbol.to_string(), timeframe);
+            let current = match self.buckets.get_mut(&key) {
+                Some(current) if current.start == start => {
+                    if current.emitted || at < current.until {
+                        continue;
+                    }
+                    merge(&mut current.bar, bar);
+                    current.until = until;
+                    current
+                }
+                Some(current) if current.start > start => continue,
+                _ => {
+                    let previous = self.buckets.remove(&key);
+                    if let Some(previous) = previous.filter(|b| !b.emitted && !b.partial) {
+                        completed.push((timeframe, previous.bar));
+                    }
+                    let bucket = Bucket {
+                        start,
+                        end,
+                        bar: Bar {
+                            timestamp: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+                            ..bar.clone()
+                        },
+                        until,
+                        partial: at > start,
+                        emitted: false,
+                    };
+                    self.buckets.entry(key).or_insert(bucket)
+                }
+            };
+            if current.until >= current.end {
+                current.emitted = true;
+                if !current.partial {
+                    completed.push((timeframe, current.bar.clone()));
+                }
+            }
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::strategies::tests::bar;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn aggregator() -> BarAggregator {
+        BarAggregator::new(MarketType::Crypto, &[Timeframe::BASE, Timeframe::Minute(5)])
+    }
+
+    #[test]
+    fn new_york_switches_to_daylight_saving_time() {
+        let hours = |s| new_york_offset(at(s)).local_minus_utc() / 3600;
+        assert_eq!(hours("2024-03-10T06:59:00Z"), -5);
+        assert_eq!(hours("2024-03-10T07:00:00Z"), -4);
+        assert_eq!(hours("2024-11-03T05:59:00Z"), -4);
+        assert_eq!(hours("2024-11-03T06:00:00Z"), -5);
+    }
+
+    #[test]
+    fn buckets_are_aligned_on_the_trading_day() {
+        let equity = MarketType::Equity;
+        // the session opens at 13:30 UTC in the summer
+        assert_eq!(
+            bucket(&equity, Timeframe::Hour(1), at("2024-07-01T14:10:00Z")),
+            Some((at("2024-07-01T13:30:00Z"), at("2024-07-01T14:30:00Z")))
+        );
+        // the last hour is cut at the close
+        assert_eq!(
+            bucket(&equity, Timeframe::Hour(1), at("2024-07-01T19:45:00Z")),
+            Some((at("2024-07-01T19:30:00Z"), at("2024-07-01T20:00:00Z")))
+        );
+        // and at 14:30 UTC in the winter
+        assert_eq!(
+            bucket(&equity, Timeframe::Day, at("2024-01-02T15:00:00Z")),
+            Some((at("2024-01-02T14:30:00Z"), at("2024-01-02T21:00:00Z")))
+        );
+        assert_eq!(
+            bucket(&equity, Timeframe::Minute(15), at("2024-01-02T14:00:00Z")),
+            None
+        );
+
+        assert_eq!(
+            bucket(
+                &MarketType::Crypto,
+                Timeframe::Day,
+                at("2024-01-02T15:00:00Z")
+            ),
+            Some((at("2024-01-02T00:00:00Z"), at("2024-01-03T00:00:00Z")))
+        );
+        assert_eq!(
+            bucket(
+                &MarketType::Crypto,
+                Timeframe::Minute(15),
+                at("2024-01-02T15:44:00Z")
+            ),
+            Some((at("2024-01-02T15:30:00Z"), at("2024-01-02T15:45:00Z")))
+        );
+    }
+
+    #[test]
+    fn history_holds_the_bars_of_the_timeframe() {
+        let now = at("2024-06-14T18:00:00Z");
+        let (start, minutes) = history(&MarketType::Crypto, Timeframe::Hour(1), 10, now);
+        assert_eq!(start, at("2024-06-14T07:00:00Z"));
+        assert_eq!(minutes, 660);
+
+        // 2 hour bars make 4 bars a session, 10 of them 3 sessions
+        let (start, minutes) = history(&MarketType::Equity, Timeframe::Hour(2), 10, now);
+        assert_eq!(start, at("2024-06-05T18:00:00Z"));
+        assert_eq!(minutes, 3 * 390);
+
+        let (start, minutes) = history(&MarketType::Equity, Timeframe::Day, 20, now);
+        assert_eq!(start, at("2024-05-11T18:00:00Z"));
+        assert_eq!(minutes, 21 * 390);
+    }
+
+    #[test]
+    fn build_higher_timeframes_from_minute_bars() {
+        let mut aggregator = aggregator();
+        assert_eq!(aggregator.timeframes(), &[Timeframe::Minute(5)]);
+        for (i, close) in [10.0, 12.0, 9.0, 11.0].into_iter().enumerate() {
+            assert!(aggregator
+                .push_bar("BTC/USD", &bar(i, close, 1.0))
+                .is_empty());
+        }
+        // duplicates are ignored
+        assert!(aggregator
+            .push_bar("BTC/USD", &bar(3, 50.0, 1.0))
+            .is_empty());
+
+        let completed = aggregator.push_bar("BTC/USD", &bar(4, 13.0, 4.0));
+        assert_eq!(completed.len(), 1);
+        let (timeframe, five) = &completed[0];
+        assert_eq!(*timeframe, Timeframe::Minute(5));
+        assert_eq!(five.timestamp, "2024-01-01T00:00:00Z");
+        assert_eq!(
+            (
+                five.open_price,
+                five.high_price,
+                five.low_price,
+                five.close_price
+            ),
+            (10.0, 13.0, 9.0, 13.0)
+        );
+        assert_eq!(five.volume, 8.0);
+        assert_eq!(five.vw, (10.0 + 12.0 + 9.0 + 11.0 + 13.0 * 4.0) / 8.0);
+        assert_eq!(five.n, 5);
+    }
+
+    #[test]
+    fn a_later_bar_completes_a_quiet_one() {
+        let mut aggregator = aggregator();
+        aggregator.push_bar("BTC/USD", &bar(0, 10.0, 1.0));
+        aggregator.push_bar("BTC/USD", &bar(2, 11.0, 1.0));
+
+        let completed = aggregator.push_bar("BTC/USD", &bar(6, 12.0, 1.0));
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].1.close_price, 11.0);
+        // older bars are ignored
+        assert!(aggregator
+            .push_bar("BTC/USD", &bar(3, 12.0, 1.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn the_first_bar_joined_late_is_dropped() {
+        let mut aggregator = aggregator();
+        for i in 2..9 {
+            assert!(aggregator
+                .push_bar("BTC/USD", &bar(i, 10.0, 1.0))
+                .is_empty());
+        }
+        let completed = aggregator.push_bar("BTC/USD", &bar(9, 10.0, 1.0));
+        assert_eq!(completed[0].1.timestamp, "2024-01-01T00:05:00Z");
+    }
+
+    #[test]
+    fn build_bars_from_trades() {
+        let mut aggregator = aggregator();
+        let trade = |timestamp: &str, price| Trade {
+            symbol: "BTC/USD".to_string(),
+            price,
+            size: 0.5,
+            timestamp: timestamp.to_string(),
+        };
+        assert!(aggregator
+            .push_trade(&trade("2024-01-01T00:00:00Z", 10.0))
+            .is_empty());
+        assert!(aggregator
+            .push_trade(&trade("2024-01-01T00:03:30.5Z", 12.0))
+            .is_empty());
+
+        assert!(aggregator.flush(at("2024-01-01T00:04:59Z")).is_empty());
+        let completed = aggregator.flush(at("2024-01-01T00:05:00Z"));
+        assert_eq!(completed.len(), 1);
+        let (symbol, _, five) = &completed[0];
+        assert_eq!(symbol, "BTC/USD");
+        assert_eq!((five.open_price, five.close_price), (10.0, 12.0));
+        assert_eq!((five.volume, five.vw, five.n), (1.0, 11.0, 2));
+        assert!(aggregator.flush(at("2024-01-01T00:06:00Z")).is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::broker::Broker;
+use crate::dao;
+use crate::error::{Error, RequestError};
+use crate::models::bar::{Bar, BarCoverage, BarQueryParams};
+use crate::models::timeframe::Timeframe;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use traidano::RequestType;
+
+/// Number of bars per request of a backfill
+const BACKFILL_PAGE: usize = 10_000;
+
+/// Cache of the bars fetched from the broker, so the history of a symbol is
+/// downloaded once and then only topped up with the new bars.
+///
+/// Each symbol and timeframe has a stored range whose bars are all in the
+/// database. Failures of the database are logged and the bars then come
+/// straight from the broker.
+#[derive(Clone)]
+pub struct BarStore {
+    db: Option<PgPool>,
+}
+
+impl BarStore {
+    pub fn new(db: PgPool) -> Self {
+        Self { db: Some(db) }
+    }
+
+    /// Store caching nothing, for the backtests
+    pub fn disabled() -> Self {
+        Self { db: None }
+    }
+
+    /// Get the bars of `params`, latest bar first.
+    ///
+    /// Symbols with enough stored bars are only fetched from the end of
+    /// their stored range, and not at all until a new bar is due. Bars of
+    /// another feed or adjustment than the default ones are not stored.
+    pub async fn get_bars(
+        &self,
+        broker: &dyn Broker,
+        symbols: &[String],
+        params: &BarQueryParams,
+        request_type: RequestType,
+    ) -> Result<HashMap<String, Vec<Bar>>, RequestError> {
+        let db = match &self.db {
+            Some(db) if params.feed.is_none() && params.adjustment.is_none() => db,
+            _ => return broker.get_bars(symbols, params, request_type).await,
+        };
+        let timeframe = params.timeframe;
+        let coverage = match dao::bar::get_coverage(db, symbols, timeframe, params.start).await {
+            Ok(coverage) => coverage,
+            Err(e) => {
+                tracing::error!("Cannot read the bar store: {}", e);
+                return broker.get_bars(symbols, params, request_type).await;
+            }
+        };
+
+        let now = Utc::now();
+        let mut missing = Vec::new();
+        let mut top_up = Vec::new();
+        let mut top_up_start: Option<DateTime<Utc>> = None;
+        for symbol in symbols {
+            let Some((range, count)) = coverage.get(symbol) else {
+                missing.push(symbol.clone());
+                continue;
+            };
+            let enough = range.start <= params.start || *count >= params.limit as i64;
+            match params.end {
+                Some(end) if range.start <= params.start && range.end >= end => {}
+                Some(_) => missing.push(symbol.clone()),
+                // the bar after the last stored one is published once it closes
+                None if enough => {
+                    if range.end + timeframe.duration() * 2 <= now {
+                        top_up.push(symbol.clone());
+                        top_up_start = Some(top_up_start.map_or(range.end, |s| s.min(range.end)));
+                    }
+                }
+                None => missing.push(symbol.clone()),
+            }
+        }
+
+        let mut stored = true;
+        let top_up_params = top_up_start.map(|start| BarQueryParams {
+            start,
+            end: None,
+            ..params.clone()
+        });
+        let requests = [(missing, Some(params.clone())), (top_up, top_up_params)];
+        for (group, group_params) in requests {
+            let Some(group_params) = group_params.filter(|_| !group.is_empty()) else {
+                continue;
+            };
+            let fetched = broker.get_bars(&group, &group_params, request_type).await?;
+            for symbol in &group {
+                let bars = fetched.get(symbol).map(Vec::as_slice).unwrap_or_default();
+                let current = coverage.get(symbol).map(|(range, _)| *range);
+                if let Err(e) = store(db, symbol, &group_params, bars, current).await {
+                    tracing::error!("Cannot store the bars of {}: {}", symbol, e);
+                    stored = false;
+                }
+            }
+        }
+
+        if stored {
+            let limit = Some(params.limit as i64);
+            match dao::bar::get_bars(db, symbols, timeframe, params.start, params.end, limit).await
+            {
+                Ok(bars) => return Ok(bars),
+                Err(e) => tracing::error!("Cannot read the bar store: {}", e),
+            }
+        }
+        broker.get_bars(symbols, params, request_type).await
+    }
+
+    /// Download and store the bars of `symbols` between `start` and `end`,
+    /// the number of bars stored for each symbol is returned
+    pub async fn backfill(
+        &self,
+        broker: &dyn Broker,
+        symbols: &[String],
+        timeframe: Timeframe,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        request_type: RequestType,
+    ) -> Result<HashMap<String, usize>, Error> {
+        let Some(db) = &self.db else {
+            return Err(Error::InvalidData("the bar store is disabled".to_string()));
+        };
+        let coverage = dao::bar::get_coverage(db, symbols, timeframe, start).await?;
+
+        let mut counts = HashMap::new();
+        for symbol in symbols {
+            // pages are walked from `end` back to `start`
+            let mut cursor = end;
+            let mut fetched: Option<BarCoverage> = None;
+            let mut count = 0;
+            loop {
+                let params = BarQueryParams {
+                    end: cursor,
+                    ..BarQueryParams::new(timeframe, start, BACKFILL_PAGE)
+                };
+                let bars = broker
+                    .get_bars(std::slice::from_ref(symbol), &params, request_type)
+                    .await
+                    .map_err(|e| Error::Broker(e.to_string()))?
+                    .remove(symbol)
+                    .unwrap_or_default();
+                let (Some(latest), Some(oldest)) = (bars.first(), bars.last()) else {
+                    break;
+                };
+                let (latest, oldest) = (bar_time(latest)?, bar_time(oldest)?);
+                dao::bar::save_bars(db, symbol, timeframe, &bars).await?;
+                count += bars.len();
+                fetched = Some(BarCoverage {
+                    start: oldest,
+                    end: fetched.map_or(latest, |fetched| fetched.end),
+                });
+                if bars.len() < BACKFILL_PAGE {
+                    fetched = fetched.map(|fetched| BarCoverage { start, ..fetched });
+                    break;
+                }
+                cursor = Some(oldest - Duration::seconds(1));
+            }
+
+            if let Some(fetched) = fetched {
+                let current = coverage.get(symbol).map(|(range, _)| *range);
+                let coverage = current.map_or(fetched, |current| current.merge(fetched));
+                dao::bar::set_coverage(db, symbol, timeframe, &coverage).await?;
+            }
+            tracing::info!("Backfilled {} bars of {} {}", count, symbol, timeframe);
+            counts.insert(symbol.clone(), count);
+        }
+        Ok(counts)
+    }
+
+    /// Get the stored bars of `symbols` between `start` and `end`, for the
+    /// backtests
+    pub async fn load(
+        &self,
+        symbols: &[String],
+        timeframe: Timeframe,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> Result<HashMap<String, Vec<Bar>>, Error> {
+        let Some(db) = &self.db else {
+            return Err(Error::InvalidData("the bar store is disabled".to_string()));
+        };
+        dao::bar::get_bars(db, symbols, timeframe, start, end, None).await
+    }
+}
+
+/// Save the bars of a symbol fetched with `params`, latest first, and extend
+/// the stored range of the symbol with them
+async fn store(
+    db: &PgPool,
+    symbol: &str,
+    params: &BarQueryParams,
+    bars: &[Bar],
+    current: Option<BarCoverage>,
+) -> Result<(), Error> {
+    dao::bar::save_bars(db, symbol, params.timeframe, bars).await?;
+    let fetched = match (bars.first(), bars.last()) {
+        (Some(latest), Some(oldest)) => BarCoverage {
+            // a full response may leave bars out between the start and the oldest bar
+            start: if bars.len() < params.limit {
+                params.start
+            } else {
+                bar_time(oldest)?
+            },
+            end: bar_time(latest)?,
+        },
+        _ => BarCoverage {
+            start: params.start,
+            end: params.start,
+        },
+    };
+    let coverage = current.map_or(fetched, |current| current.merge(fetched));
+    dao::bar::set_coverage(db, symbol, params.timeframe, &coverage).await
+}
+
+fn bar_time(bar: &Bar) -> Result<DateTime<Utc>, Error> {
+    bar.timestamp
+        .parse()
+        .map_err(|e| Error::InvalidData(format!("bad timestamp '{}': {}", bar.timestamp, e)))
+}
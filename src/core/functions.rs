@@ -1,7 +1,14 @@
 use crate::models::account::Account;
+use crate::models::num_from_f64;
+use num_decimal::Num;
 
-pub fn calculate_position_size(account: &Account, current_price: f64, risk_per_trade: f64) -> f64 {
-    let risk_amount = account.equity * risk_per_trade;
-    let shares = (risk_amount / current_price).floor();
-    shares.min(account.buying_power / current_price)
+/// Quantity risking `risk_per_trade` of the equity at `current_price`, capped
+/// by the buying power. It is rounded to the lot size of the asset when placed.
+pub fn calculate_position_size(account: &Account, current_price: &Num, risk_per_trade: f64) -> Num {
+    if !current_price.is_positive() {
+        return Num::from(0);
+    }
+    let risk_amount = &account.equity * num_from_f64(risk_per_trade).unwrap_or_default();
+    let qty = risk_amount / current_price;
+    qty.min(&account.buying_power / current_price)
 }
@@ -0,0 +1,428 @@
+use crate::core::order_tracker::OrderUpdate;
+use crate::error::RequestError;
+use crate::models::account::Account;
+use crate::models::order::{client_order_bot_id, TradeEvent};
+use chrono::{DateTime, NaiveDate, Utc};
+use num_decimal::Num;
+use opentelemetry::metrics::{Counter, Meter};
+use opentelemetry::KeyValue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Conditions halting the trading automatically, `None` disables one
+#[derive(Debug, Clone, Default)]
+pub struct HaltConfig {
+    /// Drawdown of the equity since the previous close, as a ratio
+    pub max_drawdown: Option<Num>,
+    /// Consecutive rejected orders of a bot, or of the api
+    pub max_rejections: Option<usize>,
+    /// Consecutive broker failures when placing orders
+    pub max_broker_errors: Option<usize>,
+    /// Cancel the open orders of an automatic halt
+    pub cancel_orders: bool,
+    /// Close the positions of an automatic halt
+    pub flatten: bool,
+}
+
+impl HaltConfig {
+    /// Read `HALT_MAX_DRAWDOWN`, `HALT_MAX_REJECTIONS`,
+    /// `HALT_MAX_BROKER_ERRORS`, `HALT_CANCEL_ORDERS` and `HALT_FLATTEN`, the
+    /// conditions not set are disabled
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok();
+        Self {
+            max_drawdown: var("HALT_MAX_DRAWDOWN").and_then(|value| value.trim().parse().ok()),
+            max_rejections: var("HALT_MAX_REJECTIONS").and_then(|value| value.parse().ok()),
+            max_broker_errors: var("HALT_MAX_BROKER_ERRORS").and_then(|value| value.parse().ok()),
+            cancel_orders: var("HALT_CANCEL_ORDERS").is_some_and(|value| value == "true"),
+            flatten: var("HALT_FLATTEN").is_some_and(|value| value == "true"),
+        }
+    }
+}
+
+/// What halted the trading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HaltTrigger {
+    Manual,
+    Drawdown,
+    Rejections,
+    BrokerErrors,
+}
+
+impl HaltTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HaltTrigger::Manual => "manual",
+            HaltTrigger::Drawdown => "drawdown",
+            HaltTrigger::Rejections => "rejections",
+            HaltTrigger::BrokerErrors => "broker_errors",
+        }
+    }
+}
+
+impl fmt::Display for HaltTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HaltTrigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(HaltTrigger::Manual),
+            "drawdown" => Ok(HaltTrigger::Drawdown),
+            "rejections" => Ok(HaltTrigger::Rejections),
+            "broker_errors" => Ok(HaltTrigger::BrokerErrors),
+            _ => Err(format!("unknown halt trigger '{}'", s)),
+        }
+    }
+}
+
+/// Halt of the trading of a bot, or of the whole account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Halt {
+    /// Halted bot, the whole account when none
+    pub bot_id: Option<String>,
+    pub trigger: HaltTrigger,
+    pub reason: String,
+    pub halted_at: DateTime<Utc>,
+}
+
+impl Halt {
+    pub fn new(bot_id: Option<&str>, trigger: HaltTrigger, reason: String) -> Self {
+        Self {
+            bot_id: bot_id.map(str::to_string),
+            trigger,
+            reason,
+            halted_at: Utc::now(),
+        }
+    }
+}
+
+/// Result of an order, counted by the automatic halts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderOutcome {
+    Placed,
+    Rejected,
+    BrokerError,
+}
+
+impl OrderOutcome {
+    pub fn of<T>(result: &Result<T, RequestError>) -> Self {
+        match result {
+            Ok(_) => OrderOutcome::Placed,
+            Err(RequestError::InvalidOrder(_) | RequestError::Risk(_)) => OrderOutcome::Rejected,
+            Err(RequestError::ApiError(status)) if status.is_client_error() => {
+                OrderOutcome::Rejected
+            }
+            Err(_) => OrderOutcome::BrokerError,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HaltBook {
+    halts: HashMap<Option<String>, Halt>,
+    /// Consecutive rejections, per bot
+    rejections: HashMap<Option<String>, usize>,
+    broker_errors: usize,
+    day: Option<NaiveDate>,
+    /// Equity at the first check of the day, for the brokers not reporting
+    /// the previous close
+    start_equity: Option<Num>,
+}
+
+/// Trading halts consulted by the order path.
+///
+/// A halt of the account stops every order, a halt of a bot only its own.
+/// The halts are only recorded here, persisting them and winding down the
+/// trading is up to the caller.
+pub struct TradingHalt {
+    config: HaltConfig,
+    book: Mutex<HaltBook>,
+    halts: Counter<u64>,
+}
+
+impl TradingHalt {
+    pub fn new(config: HaltConfig, meter: &Meter) -> Self {
+        Self {
+            config,
+            book: Mutex::new(HaltBook::default()),
+            halts: meter
+                .u64_counter("trading_halts")
+                .with_description("Halts of the trading")
+                .init(),
+        }
+    }
+
+    pub fn config(&self) -> &HaltConfig {
+        &self.config
+    }
+
+    /// Current halts, the account one first
+    pub fn halts(&self) -> Vec<Halt> {
+        let mut halts: Vec<Halt> = self.book.lock().unwrap().halts.values().cloned().collect();
+        halts.sort_by(|a, b| a.bot_id.cmp(&b.bot_id));
+        halts
+    }
+
+    /// Halt stopping the orders of `bot_id`, or of the api when none
+    pub fn halted(&self, bot_id: Option<&str>) -> Option<Halt> {
+        let book = self.book.lock().unwrap();
+        book.halts
+            .get(&None)
+            .or_else(|| bot_id.and_then(|id| book.halts.get(&Some(id.to_string()))))
+            .cloned()
+    }
+
+    /// Record `halt`, replacing the one of the same scope
+    pub fn halt(&self, halt: Halt) {
+        tracing::error!(
+            "Trading halted for {}: {} ({})",
+            scope(halt.bot_id.as_deref()),
+            halt.reason,
+            halt.trigger
+        );
+        self.halts.add(
+            1,
+            &[
+                KeyValue::new("trigger", halt.trigger.as_str()),
+                KeyValue::new("bot_id", halt.bot_id.clone().unwrap_or_default()),
+            ],
+        );
+        self.book
+            .lock()
+            .unwrap()
+            .halts
+            .insert(halt.bot_id.clone(), halt);
+    }
+
+    /// Restore the halts persisted before a restart
+    pub fn restore(&self, halts: Vec<Halt>) {
+        let mut book = self.book.lock().unwrap();
+        for halt in halts {
+            tracing::warn!(
+                "Trading of {} halted since {}: {}",
+                scope(halt.bot_id.as_deref()),
+                halt.halted_at,
+                halt.reason
+            );
+            book.halts.insert(halt.bot_id.clone(), halt);
+        }
+    }
+
+    /// Lift the halt of `bot_id`, or of the account when none, and reset
+    /// its counters
+    pub fn resume(&self, bot_id: Option<&str>) -> Option<Halt> {
+        let key = bot_id.map(str::to_string);
+        let mut book = self.book.lock().unwrap();
+        book.rejections.remove(&key);
+        if key.is_none() {
+            book.broker_errors = 0;
+        }
+        let halt = book.halts.remove(&key);
+        if halt.is_some() {
+            tracing::info!("Trading resumed for {}", scope(bot_id));
+        }
+        halt
+    }
+
+    /// Count the outcome of an order of `bot_id`, the halt recorded when it
+    /// reaches a limit is returned
+    pub fn record(&self, bot_id: Option<&str>, outcome: OrderOutcome) -> Option<Halt> {
+        let key = bot_id.map(str::to_string);
+        let halt = {
+            let mut book = self.book.lock().unwrap();
+            match outcome {
+                OrderOutcome::Placed => {
+                    book.rejections.remove(&key);
+                    book.broker_errors = 0;
+                    None
+                }
+                OrderOutcome::Rejected => {
+                    let rejections = book.rejections.entry(key.clone()).or_default();
+                    *rejections += 1;
+                    let rejections = *rejections;
+                    self.config
+                        .max_rejections
+                        .filter(|max| rejections >= *max && !book.halts.contains_key(&key))
+                        .map(|_| {
+                            Halt::new(
+                                bot_id,
+                                HaltTrigger::Rejections,
+                                format!("{} consecutive orders rejected", rejections),
+                            )
+                        })
+                }
+                OrderOutcome::BrokerError => {
+                    book.broker_errors += 1;
+                    let errors = book.broker_errors;
+                    self.config
+                        .max_broker_errors
+                        .filter(|max| errors >= *max && !book.halts.contains_key(&None))
+                        .map(|_| {
+                            Halt::new(
+                                None,
+                                HaltTrigger::BrokerErrors,
+                                format!("{} consecutive broker errors", errors),
+                            )
+                        })
+                }
+            }
+        };
+        halt.inspect(|halt| self.halt(halt.clone()))
+    }
+
+    /// Count the orders the broker rejected after accepting them
+    pub fn on_order_update(&self, update: &OrderUpdate) -> Option<Halt> {
+        if update.event != TradeEvent::Rejected {
+            return None;
+        }
+        let bot_id = update
+            .order
+            .client_order_id
+            .as_deref()
+            .and_then(client_order_bot_id);
+        self.record(bot_id, OrderOutcome::Rejected)
+    }
+
+    /// Check the drawdown of `account` since the previous close, the halt
+    /// recorded when it is beyond the limit is returned
+    pub fn check_drawdown(&self, account: &Account, now: DateTime<Utc>) -> Option<Halt> {
+        let max_drawdown = self.config.max_drawdown.as_ref()?;
+        let halt = {
+            let mut book = self.book.lock().unwrap();
+            let today = now.date_naive();
+            if book.day != Some(today) {
+                book.day = Some(today);
+                book.start_equity = None;
+            }
+            let start_equity = match &account.last_equity {
+                Some(last_equity) => last_equity.clone(),
+                None => book
+                    .start_equity
+                    .get_or_insert_with(|| account.equity.clone())
+                    .clone(),
+            };
+            if !start_equity.is_positive() || book.halts.contains_key(&None) {
+                return None;
+            }
+            let drawdown = (&start_equity - &account.equity) / &start_equity;
+            (drawdown >= *max_drawdown).then(|| {
+                Halt::new(
+                    None,
+                    HaltTrigger::Drawdown,
+                    format!(
+                        "equity {} is down {}% from {}",
+                        account.equity,
+                        (drawdown * 100).round_with(2),
+                        start_equity
+                    ),
+                )
+            })
+        };
+        halt.inspect(|halt| self.halt(halt.clone()))
+    }
+}
+
+fn scope(bot_id: Option<&str>) -> String {
+    match bot_id {
+        Some(bot_id) => format!("bot '{}'", bot_id),
+        None => "the account".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::order::{bot_client_order_id, BrokerOrder, OrderStatus};
+
+    fn trading_halt(config: HaltConfig) -> TradingHalt {
+        TradingHalt::new(config, &opentelemetry::global::meter("halt"))
+    }
+
+    #[test]
+    fn account_halt_stops_every_bot() {
+        let halt = trading_halt(HaltConfig::default());
+        halt.halt(Halt::new(Some("a"), HaltTrigger::Manual, "a".to_string()));
+        assert!(halt.halted(Some("a")).is_some());
+        assert!(halt.halted(Some("b")).is_none());
+        assert!(halt.halted(None).is_none());
+
+        halt.halt(Halt::new(None, HaltTrigger::Manual, "all".to_string()));
+        assert_eq!(halt.halted(Some("b")).unwrap().reason, "all");
+        assert_eq!(halt.halts().len(), 2);
+
+        assert!(halt.resume(None).is_some());
+        assert!(halt.halted(Some("b")).is_none());
+        assert!(halt.halted(Some("a")).is_some());
+        assert!(halt.resume(None).is_none());
+    }
+
+    #[test]
+    fn consecutive_rejections_halt_the_bot() {
+        let halt = trading_halt(HaltConfig {
+            max_rejections: Some(3),
+            max_broker_errors: Some(2),
+            ..HaltConfig::default()
+        });
+        assert!(halt.record(Some("a"), OrderOutcome::Rejected).is_none());
+        assert!(halt.record(Some("a"), OrderOutcome::Rejected).is_none());
+        assert!(halt.record(Some("a"), OrderOutcome::Placed).is_none());
+        assert!(halt.record(Some("a"), OrderOutcome::Rejected).is_none());
+        assert!(halt.record(Some("b"), OrderOutcome::Rejected).is_none());
+        assert!(halt.record(Some("a"), OrderOutcome::Rejected).is_none());
+
+        let rejected = OrderUpdate {
+            event: TradeEvent::Rejected,
+            status: OrderStatus::Rejected,
+            order: BrokerOrder {
+                client_order_id: Some(bot_client_order_id("a")),
+                ..BrokerOrder::default()
+            },
+            price: None,
+            qty: None,
+        };
+        let tripped = halt.on_order_update(&rejected).unwrap();
+        assert_eq!(tripped.bot_id.as_deref(), Some("a"));
+        assert_eq!(tripped.trigger, HaltTrigger::Rejections);
+        assert!(halt.halted(Some("a")).is_some());
+        assert!(halt.halted(Some("b")).is_none());
+
+        assert!(halt.record(Some("b"), OrderOutcome::BrokerError).is_none());
+        let tripped = halt.record(None, OrderOutcome::BrokerError).unwrap();
+        assert_eq!(tripped.bot_id, None);
+        assert!(halt.halted(Some("b")).is_some());
+    }
+
+    #[test]
+    fn drawdown_halts_the_account() {
+        let halt = trading_halt(HaltConfig {
+            max_drawdown: Some("0.05".parse().unwrap()),
+            ..HaltConfig::default()
+        });
+        let account = |equity: i32| Account {
+            id: "a".to_string(),
+            equity: Num::from(equity),
+            buying_power: Num::from(equity),
+            last_equity: None,
+        };
+        let now = Utc::now();
+
+        assert!(halt.check_drawdown(&account(10_000), now).is_none());
+        assert!(halt.check_drawdown(&account(9_600), now).is_none());
+        let tripped = halt.check_drawdown(&account(9_500), now).unwrap();
+        assert_eq!(tripped.trigger, HaltTrigger::Drawdown);
+        assert!(halt.halted(None).is_some());
+        // the next day starts from its own equity
+        halt.resume(None);
+        let tomorrow = now + chrono::Duration::days(1);
+        assert!(halt.check_drawdown(&account(9_000), tomorrow).is_none());
+    }
+}
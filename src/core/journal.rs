@@ -0,0 +1,77 @@
+use crate::bot::strategies::Signal;
+use crate::bot::BotConfig;
+use crate::dao::bot_run::{start_bot_run, stop_bot_runs};
+use crate::dao::order::save_order;
+use crate::dao::signal::create_signal;
+use crate::models::order::BrokerOrder;
+use crate::models::signal::SignalOutcome;
+use num_decimal::Num;
+use sqlx::PgPool;
+
+/// Records the orders, signals and runs of the bots in the database, for the
+/// post-mortems and the performance of each bot.
+///
+/// Failures are logged, recording never gets in the way of trading. The
+/// fills and the order updates are recorded by the order tracker.
+#[derive(Clone)]
+pub struct Journal {
+    db: Option<PgPool>,
+}
+
+impl Journal {
+    pub fn new(db: PgPool) -> Self {
+        Self { db: Some(db) }
+    }
+
+    /// Journal recording nothing, for the backtests
+    pub fn disabled() -> Self {
+        Self { db: None }
+    }
+
+    /// Record an order accepted by the broker
+    pub async fn order(&self, order: &BrokerOrder) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = save_order(db, order, &order.status).await {
+            tracing::error!("Cannot record order {}: {}", order.id, e);
+        }
+    }
+
+    /// Record a signal of a bot and what became of it
+    pub async fn signal(
+        &self,
+        bot_id: &str,
+        signal: &Signal,
+        qty: Option<&Num>,
+        outcome: SignalOutcome,
+        reason: Option<&str>,
+        order_id: Option<&str>,
+    ) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = create_signal(db, bot_id, signal, qty, outcome, reason, order_id).await {
+            tracing::error!("Cannot record signal of bot {}: {}", bot_id, e);
+        }
+    }
+
+    pub async fn run_started(&self, config: &BotConfig) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = start_bot_run(db, config).await {
+            tracing::error!("Cannot record run of bot {}: {}", config.id, e);
+        }
+    }
+
+    /// End the open runs of a bot, of every bot when none
+    pub async fn run_stopped(&self, bot_id: Option<&str>, reason: &str) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = stop_bot_runs(db, bot_id, reason).await {
+            tracing::error!("Cannot record end of bot runs: {}", e);
+        }
+    }
+}
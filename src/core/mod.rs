@@ -1,2 +1,10 @@
+pub mod allocator;
+pub mod bar_aggregator;
+pub mod bar_store;
 pub mod functions;
+pub mod halt;
+pub mod journal;
+pub mod order_tracker;
 pub mod rate_limiter;
+pub mod risk;
+pub mod sizing;
@@ -0,0 +1,751 @@
+use crate::bot::BotConfig;
+use crate::broker::Broker;
+use crate::core::allocator::{opening_qty, CapitalAllocator, Portfolio};
+use crate::error::RequestError;
+use crate::models::bar::Bar;
+use crate::models::num_from_f64;
+use crate::models::order::Order;
+use crate::models::position::Position;
+use crate::models::trade::Side;
+use chrono::{DateTime, NaiveDate, Utc};
+use num_decimal::Num;
+use opentelemetry::metrics::{Counter, Meter};
+use opentelemetry::KeyValue;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Window of the order rate limit
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits checked before an order reaches the broker, `None` disables a check
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    /// Largest notional of a single order
+    pub max_order_notional: Option<Num>,
+    /// Largest notional held in a symbol once the order fills
+    pub max_position_notional: Option<Num>,
+    /// Largest loss of the account since the previous close
+    pub max_daily_loss: Option<Num>,
+    /// Largest loss of a bot during the day
+    pub max_bot_daily_loss: Option<Num>,
+    /// Largest number of orders placed within a minute
+    pub max_orders_per_minute: Option<usize>,
+    /// Largest distance of a limit or stop price from the close of the last
+    /// bar, as a ratio of that close
+    pub price_band: Option<Num>,
+    /// Symbols in which no position can be opened or increased
+    pub restricted_symbols: HashSet<String>,
+}
+
+impl RiskLimits {
+    /// Read `RISK_MAX_ORDER_NOTIONAL`, `RISK_MAX_POSITION_NOTIONAL`,
+    /// `RISK_MAX_DAILY_LOSS`, `RISK_MAX_BOT_DAILY_LOSS`,
+    /// `RISK_MAX_ORDERS_PER_MINUTE`, `RISK_PRICE_BAND` and the comma separated
+    /// `RISK_RESTRICTED_SYMBOLS`, the limits not set are disabled
+    pub fn from_env() -> Self {
+        Self {
+            max_order_notional: env_num("RISK_MAX_ORDER_NOTIONAL"),
+            max_position_notional: env_num("RISK_MAX_POSITION_NOTIONAL"),
+            max_daily_loss: env_num("RISK_MAX_DAILY_LOSS"),
+            max_bot_daily_loss: env_num("RISK_MAX_BOT_DAILY_LOSS"),
+            max_orders_per_minute: std::env::var("RISK_MAX_ORDERS_PER_MINUTE")
+                .ok()
+                .and_then(|value| value.parse().ok()),
+            price_band: env_num("RISK_PRICE_BAND"),
+            restricted_symbols: std::env::var("RISK_RESTRICTED_SYMBOLS")
+                .map(|symbols| {
+                    symbols
+                        .split(',')
+                        .map(|symbol| symbol.trim().to_uppercase())
+                        .filter(|symbol| !symbol.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn env_num(key: &str) -> Option<Num> {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn abs(value: &Num) -> Num {
+    if value.is_negative() {
+        -value
+    } else {
+        value.clone()
+    }
+}
+
+/// Check an order failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskReason {
+    RestrictedSymbol,
+    OrderRate,
+    PriceBand,
+    MaxOrderNotional,
+    MaxPositionNotional,
+    MaxPositions,
+    DailyLoss,
+    BotDailyLoss,
+    Allocation,
+}
+
+impl RiskReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskReason::RestrictedSymbol => "restricted_symbol",
+            RiskReason::OrderRate => "order_rate",
+            RiskReason::PriceBand => "price_band",
+            RiskReason::MaxOrderNotional => "max_order_notional",
+            RiskReason::MaxPositionNotional => "max_position_notional",
+            RiskReason::MaxPositions => "max_positions",
+            RiskReason::DailyLoss => "daily_loss",
+            RiskReason::BotDailyLoss => "bot_daily_loss",
+            RiskReason::Allocation => "allocation",
+        }
+    }
+}
+
+impl fmt::Display for RiskReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Order refused by the risk checks
+#[derive(Debug, Clone, Error, Serialize)]
+#[error("{reason}: {message}")]
+pub struct RiskRejection {
+    pub reason: RiskReason,
+    pub message: String,
+}
+
+fn reject(reason: RiskReason, message: String) -> Result<(), RiskRejection> {
+    Err(RiskRejection { reason, message })
+}
+
+/// State of the account an order is checked against
+#[derive(Debug, Clone)]
+pub struct Exposure {
+    pub equity: Num,
+    /// Equity at the previous close, when the broker reports it
+    pub last_equity: Option<Num>,
+    pub positions: Vec<Position>,
+    /// Virtual book of the bot placing the order
+    pub portfolio: Option<Portfolio>,
+}
+
+#[derive(Debug, Default)]
+struct RiskBook {
+    day: Option<NaiveDate>,
+    /// Equity at the first check of the day, for the brokers not reporting
+    /// the previous close
+    start_equity: Option<Num>,
+    /// Orders submitted within the rate window
+    accepted: VecDeque<Instant>,
+    /// Timestamp and close of the latest bar, per symbol
+    closes: HashMap<String, (DateTime<Utc>, Num)>,
+}
+
+impl RiskBook {
+    /// Reset the daily figures when the day changes
+    fn roll(&mut self, today: NaiveDate) {
+        if self.day == Some(today) {
+            return;
+        }
+        self.day = Some(today);
+        self.start_equity = None;
+    }
+}
+
+/// Pre-trade risk checks.
+///
+/// Every order goes through [`RiskEngine::check`] before reaching the
+/// broker. Orders only reducing a position are never refused by the
+/// position and loss limits, so positions can always be closed.
+pub struct RiskEngine {
+    limits: RiskLimits,
+    book: Mutex<RiskBook>,
+    rejections: Counter<u64>,
+}
+
+impl RiskEngine {
+    pub fn new(limits: RiskLimits, meter: &Meter) -> Self {
+        Self {
+            limits,
+            book: Mutex::new(RiskBook::default()),
+            rejections: meter
+                .u64_counter("risk_rejections")
+                .with_description("Orders refused by the risk checks")
+                .init(),
+        }
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    /// Count an order accepted by the broker towards the rate limit
+    pub fn record_submitted(&self) {
+        if self.limits.max_orders_per_minute.is_some() {
+            self.book.lock().unwrap().accepted.push_back(Instant::now());
+        }
+    }
+
+    /// Remember the close of `bar`, the reference of the price bands
+    pub fn on_bar(&self, symbol: &str, bar: &Bar) {
+        let (Ok(timestamp), Some(close)) = (
+            bar.timestamp.parse::<DateTime<Utc>>(),
+            num_from_f64(bar.close_price),
+        ) else {
+            return;
+        };
+        let mut book = self.book.lock().unwrap();
+        if book
+            .closes
+            .get(symbol)
+            .is_some_and(|(last, _)| *last >= timestamp)
+        {
+            return;
+        }
+        book.closes.insert(symbol.to_string(), (timestamp, close));
+    }
+
+    /// Check `order`, placed by `bot` when given, against the account of
+    /// `broker` and the book of the bot in `allocator`. Rejections are
+    /// logged and counted.
+    pub async fn check(
+        &self,
+        broker: &dyn Broker,
+        allocator: &CapitalAllocator,
+        order: &Order,
+        bot: Option<&BotConfig>,
+    ) -> Result<(), RequestError> {
+        let account = broker.get_account().await?;
+        let exposure = Exposure {
+            equity: account.equity,
+            last_equity: account.last_equity,
+            positions: broker.get_positions().await?,
+            portfolio: bot.and_then(|bot| allocator.portfolio(&bot.id)),
+        };
+
+        self.evaluate(order, &exposure, bot, Utc::now())
+            .map_err(|rejection| {
+                let bot_id = bot.map_or("", |bot| bot.id.as_str());
+                tracing::warn!(
+                    "Order {:?} {} of bot '{}' rejected by the risk checks: {}",
+                    order.side,
+                    order.symbol,
+                    bot_id,
+                    rejection
+                );
+                self.rejections.add(
+                    1,
+                    &[
+                        KeyValue::new("reason", rejection.reason.as_str()),
+                        KeyValue::new("symbol", order.symbol.clone()),
+                        KeyValue::new("bot_id", bot_id.to_string()),
+                    ],
+                );
+                RequestError::Risk(rejection)
+            })
+    }
+
+    /// Run the checks of `order` against `exposure`
+    pub fn evaluate(
+        &self,
+        order: &Order,
+        exposure: &Exposure,
+        bot: Option<&BotConfig>,
+        now: DateTime<Utc>,
+    ) -> Result<(), RiskRejection> {
+        let limits = &self.limits;
+        let mut book = self.book.lock().unwrap();
+        book.roll(now.date_naive());
+
+        let position = exposure
+            .positions
+            .iter()
+            .find(|position| same_symbol(&position.symbol, &order.symbol));
+        let held = position.map_or_else(|| Num::from(0), |position| position.qty.clone());
+        let close = book
+            .closes
+            .get(&order.symbol)
+            .map(|(_, close)| close.clone());
+
+        // market orders are valued at the last close
+        let price = order
+            .limit_price
+            .clone()
+            .or_else(|| order.stop_price.clone())
+            .or_else(|| close.clone())
+            .or_else(|| position.and_then(|position| position.current_price.clone()));
+        let qty = match (&order.qty, &order.national, &price) {
+            (Some(qty), _, _) => Some(qty.clone()),
+            (None, Some(notional), Some(price)) if price.is_positive() => Some(notional / price),
+            _ => None,
+        };
+        let notional = match (&order.national, &qty, &price) {
+            (Some(notional), _, _) => Some(notional.clone()),
+            (None, Some(qty), Some(price)) => Some(qty * price),
+            _ => None,
+        };
+        let signed_qty = qty.map(|qty| match order.side {
+            Side::Buy => qty,
+            Side::Sell => -qty,
+        });
+        let increases = held.is_zero()
+            || held.is_positive() == (order.side == Side::Buy)
+            || signed_qty.as_ref().is_some_and(|qty| abs(qty) > abs(&held));
+
+        if increases
+            && limits
+                .restricted_symbols
+                .contains(&order.symbol.to_uppercase())
+        {
+            return reject(
+                RiskReason::RestrictedSymbol,
+                format!("{} is restricted", order.symbol),
+            );
+        }
+
+        if let Some(max_orders) = limits.max_orders_per_minute {
+            let instant = Instant::now();
+            while book
+                .accepted
+                .front()
+                .is_some_and(|accepted| instant.duration_since(*accepted) > RATE_WINDOW)
+            {
+                book.accepted.pop_front();
+            }
+            if book.accepted.len() >= max_orders {
+                return reject(
+                    RiskReason::OrderRate,
+                    format!("more than {} orders within a minute", max_orders),
+                );
+            }
+        }
+
+        if let (Some(band), Some(close)) = (&limits.price_band, &close) {
+            let order_prices = [("limit", &order.limit_price), ("stop", &order.stop_price)];
+            for (name, order_price) in order_prices {
+                let Some(order_price) = order_price else {
+                    continue;
+                };
+                if close.is_positive() && abs(&(order_price - close)) / close > *band {
+                    return reject(
+                        RiskReason::PriceBand,
+                        format!(
+                            "{} price {} is more than {}% away from the last close {}",
+                            name,
+                            order_price,
+                            band * 100,
+                            close
+                        ),
+                    );
+                }
+            }
+        }
+
+        if let (Some(max_notional), Some(notional)) = (&limits.max_order_notional, &notional) {
+            if notional > max_notional {
+                return reject(
+                    RiskReason::MaxOrderNotional,
+                    format!(
+                        "order notional {} is above the {} limit",
+                        notional, max_notional
+                    ),
+                );
+            }
+        }
+
+        if increases {
+            if let (Some(max_notional), Some(qty), Some(price)) =
+                (&limits.max_position_notional, &signed_qty, &price)
+            {
+                let position_notional = abs(&(&held + qty)) * price;
+                if position_notional > *max_notional {
+                    return reject(
+                        RiskReason::MaxPositionNotional,
+                        format!(
+                            "position notional in {} would be {}, above the {} limit",
+                            order.symbol, position_notional, max_notional
+                        ),
+                    );
+                }
+            }
+
+            // zero keeps the number of positions of the bot unlimited
+            if let Some(bot) = bot.filter(|bot| bot.max_positions > 0 && held.is_zero()) {
+                let open = exposure
+                    .positions
+                    .iter()
+                    .filter(|position| !position.qty.is_zero())
+                    .filter(|position| {
+                        bot.symbols
+                            .iter()
+                            .any(|symbol| same_symbol(symbol, &position.symbol))
+                    })
+                    .count();
+                if open >= bot.max_positions {
+                    return reject(
+                        RiskReason::MaxPositions,
+                        format!(
+                            "bot '{}' already holds {} positions out of {}",
+                            bot.id, open, bot.max_positions
+                        ),
+                    );
+                }
+            }
+
+            if let Some(max_loss) = &limits.max_daily_loss {
+                let start_equity = match &exposure.last_equity {
+                    Some(last_equity) => last_equity.clone(),
+                    None => book
+                        .start_equity
+                        .get_or_insert_with(|| exposure.equity.clone())
+                        .clone(),
+                };
+                let loss = start_equity - &exposure.equity;
+                if loss >= *max_loss {
+                    return reject(
+                        RiskReason::DailyLoss,
+                        format!("daily loss {} reached the {} limit", loss, max_loss),
+                    );
+                }
+            }
+
+            if let (Some(max_loss), Some(bot), Some(portfolio)) =
+                (&limits.max_bot_daily_loss, bot, &exposure.portfolio)
+            {
+                let loss = -&portfolio.daily_pl;
+                if loss >= *max_loss {
+                    return reject(
+                        RiskReason::BotDailyLoss,
+                        format!(
+                            "daily loss {} of bot '{}' reached the {} limit",
+                            loss, bot.id, max_loss
+                        ),
+                    );
+                }
+            }
+        }
+
+        // the book of the bot is checked on its own positions
+        if let (Some(portfolio), Some(qty), Some(price)) =
+            (&exposure.portfolio, &signed_qty, &price)
+        {
+            if let Some(buying_power) = &portfolio.buying_power {
+                let required = opening_qty(&portfolio.position(&order.symbol), qty) * price;
+                if required > *buying_power {
+                    return reject(
+                        RiskReason::Allocation,
+                        format!(
+                            "order needs {} of cash, bot '{}' has {} left of its allocation",
+                            required, portfolio.bot_id, buying_power
+                        ),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Crypto positions are reported without the slash of the pair
+pub fn same_symbol(a: &str, b: &str) -> bool {
+    a == b || a.replace('/', "") == b.replace('/', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::allocator::Allocation;
+    use crate::core::order_tracker::OrderUpdate;
+    use crate::models::order::{bot_client_order_id, BrokerOrder, OrderStatus, TradeEvent};
+    use crate::models::position::PositionSide;
+    use crate::models::trade::{TimeInForce, Type};
+
+    fn num(value: &str) -> Num {
+        value.parse().unwrap()
+    }
+
+    fn engine(limits: RiskLimits) -> RiskEngine {
+        RiskEngine::new(limits, &opentelemetry::global::meter("risk"))
+    }
+
+    fn order(symbol: &str, side: Side, qty: i32, limit_price: i32) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            qty: Some(Num::from(qty)),
+            side,
+            order_type: Type::Limit,
+            time_in_force: TimeInForce::Day,
+            limit_price: Some(Num::from(limit_price)),
+            ..Order::default()
+        }
+    }
+
+    fn position(symbol: &str, qty: i32, price: i32) -> Position {
+        Position {
+            asset_id: symbol.to_string(),
+            symbol: symbol.to_string(),
+            exchange: "NASDAQ".to_string(),
+            asset_class: "us_equity".to_string(),
+            side: if qty < 0 {
+                PositionSide::Short
+            } else {
+                PositionSide::Long
+            },
+            qty: Num::from(qty),
+            qty_available: None,
+            avg_entry_price: Num::from(price),
+            current_price: Some(Num::from(price)),
+            market_value: Some(Num::from(qty * price)),
+            cost_basis: Num::from(qty * price),
+            unrealized_pl: None,
+            unrealized_plpc: None,
+        }
+    }
+
+    fn exposure(equity: i32, positions: Vec<Position>) -> Exposure {
+        Exposure {
+            equity: Num::from(equity),
+            last_equity: Some(Num::from(10_000)),
+            positions,
+            portfolio: None,
+        }
+    }
+
+    fn bot() -> BotConfig {
+        BotConfig {
+            symbols: vec!["AAPL".to_string(), "MSFT".to_string(), "TSLA".to_string()],
+            max_positions: 2,
+            ..BotConfig::test_default()
+        }
+    }
+
+    fn reason(result: Result<(), RiskRejection>) -> Option<RiskReason> {
+        result.err().map(|rejection| rejection.reason)
+    }
+
+    #[test]
+    fn order_and_position_notional() {
+        let engine = engine(RiskLimits {
+            max_order_notional: Some(Num::from(5_000)),
+            max_position_notional: Some(Num::from(8_000)),
+            ..RiskLimits::default()
+        });
+        let now = Utc::now();
+        let held = exposure(10_000, vec![position("AAPL", 40, 100)]);
+
+        let big = order("AAPL", Side::Buy, 60, 100);
+        assert_eq!(
+            reason(engine.evaluate(&big, &held, None, now)),
+            Some(RiskReason::MaxOrderNotional)
+        );
+        let more = order("AAPL", Side::Buy, 45, 100);
+        assert_eq!(
+            reason(engine.evaluate(&more, &held, None, now)),
+            Some(RiskReason::MaxPositionNotional)
+        );
+        // reducing the position is always allowed
+        let less = order("AAPL", Side::Sell, 40, 100);
+        assert!(engine.evaluate(&less, &held, None, now).is_ok());
+        assert!(engine
+            .evaluate(&order("AAPL", Side::Buy, 40, 100), &held, None, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn price_band_around_the_last_close() {
+        let engine = engine(RiskLimits {
+            price_band: Some(num("0.05")),
+            ..RiskLimits::default()
+        });
+        let bar = Bar {
+            close_price: 100.0,
+            high_price: 101.0,
+            low_price: 99.0,
+            n: 1,
+            open_price: 100.0,
+            timestamp: "2024-01-02T15:00:00Z".to_string(),
+            volume: 10.0,
+            vw: 100.0,
+        };
+        engine.on_bar("AAPL", &bar);
+        let now = Utc::now();
+
+        assert!(engine
+            .evaluate(
+                &order("AAPL", Side::Buy, 1, 104),
+                &exposure(10_000, vec![]),
+                None,
+                now
+            )
+            .is_ok());
+        assert_eq!(
+            reason(engine.evaluate(
+                &order("AAPL", Side::Buy, 1, 1000),
+                &exposure(10_000, vec![]),
+                None,
+                now
+            )),
+            Some(RiskReason::PriceBand)
+        );
+        // without a bar there is no reference price
+        assert!(engine
+            .evaluate(
+                &order("MSFT", Side::Buy, 1, 1000),
+                &exposure(10_000, vec![]),
+                None,
+                now
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn bot_positions_rate_and_restricted_symbols() {
+        let engine = engine(RiskLimits {
+            max_orders_per_minute: Some(2),
+            restricted_symbols: HashSet::from(["GME".to_string()]),
+            ..RiskLimits::default()
+        });
+        let now = Utc::now();
+        let bot = bot();
+        let held = exposure(
+            10_000,
+            vec![position("AAPL", 1, 100), position("MSFT", 1, 100)],
+        );
+
+        assert_eq!(
+            reason(engine.evaluate(&order("gme", Side::Buy, 1, 10), &held, None, now)),
+            Some(RiskReason::RestrictedSymbol)
+        );
+        assert_eq!(
+            reason(engine.evaluate(&order("TSLA", Side::Buy, 1, 10), &held, Some(&bot), now)),
+            Some(RiskReason::MaxPositions)
+        );
+        assert!(engine
+            .evaluate(&order("AAPL", Side::Buy, 1, 100), &held, Some(&bot), now)
+            .is_ok());
+        assert!(engine
+            .evaluate(&order("MSFT", Side::Sell, 1, 100), &held, Some(&bot), now)
+            .is_ok());
+        // only the orders the broker accepted count
+        assert!(engine
+            .evaluate(&order("AAPL", Side::Sell, 1, 100), &held, Some(&bot), now)
+            .is_ok());
+        engine.record_submitted();
+        engine.record_submitted();
+        assert_eq!(
+            reason(engine.evaluate(&order("AAPL", Side::Sell, 1, 100), &held, Some(&bot), now)),
+            Some(RiskReason::OrderRate)
+        );
+    }
+
+    #[test]
+    fn daily_loss_limits() {
+        let engine = engine(RiskLimits {
+            max_daily_loss: Some(Num::from(500)),
+            max_bot_daily_loss: Some(Num::from(100)),
+            ..RiskLimits::default()
+        });
+        let now = Utc::now();
+        let bot = bot();
+
+        assert_eq!(
+            reason(engine.evaluate(
+                &order("AAPL", Side::Buy, 1, 100),
+                &exposure(9_400, vec![]),
+                None,
+                now
+            )),
+            Some(RiskReason::DailyLoss)
+        );
+
+        let fill = |side: Side, price: i32| OrderUpdate {
+            event: TradeEvent::Fill,
+            status: OrderStatus::Filled,
+            order: BrokerOrder {
+                id: "o1".to_string(),
+                client_order_id: Some(bot_client_order_id("bot")),
+                symbol: "AAPL".to_string(),
+                side,
+                order_type: Type::Market,
+                time_in_force: TimeInForce::Day,
+                status: "filled".to_string(),
+                ..BrokerOrder::default()
+            },
+            price: Some(Num::from(price)),
+            qty: Some(Num::from(10)),
+        };
+        let allocator = CapitalAllocator::new();
+        allocator.on_order_update(&fill(Side::Buy, 100));
+        allocator.on_order_update(&fill(Side::Sell, 88));
+
+        let flat = Exposure {
+            portfolio: allocator.portfolio("bot"),
+            ..exposure(9_900, vec![])
+        };
+        assert_eq!(
+            reason(engine.evaluate(&order("AAPL", Side::Buy, 1, 88), &flat, Some(&bot), now)),
+            Some(RiskReason::BotDailyLoss)
+        );
+        assert!(engine
+            .evaluate(&order("AAPL", Side::Buy, 1, 88), &flat, None, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn orders_within_the_allocation() {
+        let engine = engine(RiskLimits::default());
+        let now = Utc::now();
+        let bot = BotConfig {
+            allocation: Some(Allocation::Amount(Num::from(1_000))),
+            ..bot()
+        };
+        let allocator = CapitalAllocator::new();
+        allocator.open(&bot, &Num::from(1_000), &[]);
+        let allocated = Exposure {
+            portfolio: allocator.portfolio("bot"),
+            ..exposure(10_000, vec![])
+        };
+
+        assert!(engine
+            .evaluate(
+                &order("AAPL", Side::Buy, 10, 100),
+                &allocated,
+                Some(&bot),
+                now
+            )
+            .is_ok());
+        assert_eq!(
+            reason(engine.evaluate(
+                &order("AAPL", Side::Buy, 11, 100),
+                &allocated,
+                Some(&bot),
+                now
+            )),
+            Some(RiskReason::Allocation)
+        );
+        // orders placed outside of the bot use the whole account
+        assert!(engine
+            .evaluate(
+                &order("AAPL", Side::Buy, 11, 100),
+                &exposure(10_000, vec![]),
+                None,
+                now
+            )
+            .is_ok());
+    }
+}
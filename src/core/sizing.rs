@@ -0,0 +1,316 @@
+use crate::core::functions::calculate_position_size;
+use crate::models::account::Account;
+use crate::models::asset::{round_down, Asset};
+use crate::models::bar::Bar;
+use crate::models::{num_from_f64, num_to_f64};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+
+/// How a bot turns a signal into a quantity.
+///
+/// Every model is capped by the buying power and rounded down to the lot
+/// size of the asset, fractional quantities are kept when the asset allows
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum SizingModel {
+    /// Notional of `risk_per_trade` of the equity
+    #[default]
+    FixedFraction,
+    /// Lose `risk_per_trade` of the equity when the stop of the signal is
+    /// hit, signals without a stop are sized as a fixed fraction
+    StopDistance,
+    /// Lose `risk_per_trade` of the equity on a move of `multiple` average
+    /// true ranges over `period` bars
+    Atr { period: usize, multiple: f64 },
+    /// Position moving by `target` of the equity on a one standard deviation
+    /// bar, measured over `volatility_window` bars
+    VolatilityTarget { target: f64 },
+    /// Kelly fraction of the equity for a strategy winning `win_rate` of its
+    /// trades with an average win `payoff` times its average loss, capped at
+    /// `cap` of the equity
+    Kelly {
+        win_rate: f64,
+        payoff: f64,
+        cap: f64,
+    },
+    /// Fixed notional per order
+    FixedNotional { notional: f64 },
+}
+
+/// What a sizing model knows about the trade
+#[derive(Debug, Clone)]
+pub struct SizingInput<'a> {
+    pub account: &'a Account,
+    pub price: &'a Num,
+    /// Protective stop of the signal
+    pub stop_price: Option<&'a Num>,
+    /// Latest bars of the symbol, oldest first
+    pub bars: &'a [Bar],
+    pub risk_per_trade: f64,
+    pub volatility_window: usize,
+}
+
+impl SizingModel {
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |name: &str, value: f64| {
+            if value > 0.0 && value.is_finite() {
+                Ok(())
+            } else {
+                Err(format!("{} must be positive", name))
+            }
+        };
+        match self {
+            SizingModel::FixedFraction | SizingModel::StopDistance => Ok(()),
+            SizingModel::Atr { period, multiple } => {
+                if *period == 0 {
+                    return Err("period must be positive".to_string());
+                }
+                positive("multiple", *multiple)
+            }
+            SizingModel::VolatilityTarget { target } => positive("target", *target),
+            SizingModel::Kelly {
+                win_rate,
+                payoff,
+                cap,
+            } => {
+                if !(0.0..=1.0).contains(win_rate) {
+                    return Err("win_rate must be between 0 and 1".to_string());
+                }
+                if !(0.0..=1.0).contains(cap) {
+                    return Err("cap must be between 0 and 1".to_string());
+                }
+                positive("payoff", *payoff)
+            }
+            SizingModel::FixedNotional { notional } => positive("notional", *notional),
+        }
+    }
+
+    /// Number of bars the model needs, oldest first
+    pub fn history(&self, volatility_window: usize) -> usize {
+        match self {
+            SizingModel::Atr { period, .. } => period + 1,
+            SizingModel::VolatilityTarget { .. } => volatility_window + 1,
+            _ => 0,
+        }
+    }
+
+    /// Quantity to trade, zero when the model cannot size the trade
+    pub fn size(&self, input: &SizingInput, asset: &Asset) -> Num {
+        if !input.price.is_positive() {
+            return Num::from(0);
+        }
+        let equity = num_to_f64(&input.account.equity);
+        let price = num_to_f64(input.price);
+        let risk_amount = equity * input.risk_per_trade;
+
+        let qty = match self {
+            SizingModel::FixedFraction => {
+                calculate_position_size(input.account, input.price, input.risk_per_trade)
+            }
+            SizingModel::StopDistance => match input.stop_price {
+                Some(stop_price) if stop_price != input.price => {
+                    let distance = (price - num_to_f64(stop_price)).abs();
+                    num(risk_amount / distance)
+                }
+                _ => calculate_position_size(input.account, input.price, input.risk_per_trade),
+            },
+            SizingModel::Atr { period, multiple } => {
+                match average_true_range(input.bars, *period) {
+                    Some(atr) if atr > 0.0 => num(risk_amount / (atr * multiple)),
+                    _ => Num::from(0),
+                }
+            }
+            SizingModel::VolatilityTarget { target } => {
+                match volatility(input.bars, input.volatility_window) {
+                    Some(volatility) if volatility > 0.0 => {
+                        num(equity * target / (volatility * price))
+                    }
+                    _ => Num::from(0),
+                }
+            }
+            SizingModel::Kelly {
+                win_rate,
+                payoff,
+                cap,
+            } => {
+                let fraction = (win_rate - (1.0 - win_rate) / payoff).clamp(0.0, *cap);
+                num(equity * fraction / price)
+            }
+            SizingModel::FixedNotional { notional } => num(notional / price),
+        };
+
+        let qty = qty
+            .min(&input.account.buying_power / input.price)
+            .max(Num::from(0));
+        match asset.lot_size() {
+            Some(lot) => round_down(&qty, &lot),
+            None => qty,
+        }
+    }
+}
+
+fn num(value: f64) -> Num {
+    num_from_f64(value).unwrap_or_default()
+}
+
+/// Average true range of the last `period` bars, oldest first
+fn average_true_range(bars: &[Bar], period: usize) -> Option<f64> {
+    if period == 0 || bars.len() < period + 1 {
+        return None;
+    }
+    let bars = &bars[bars.len() - period - 1..];
+    let total: f64 = bars
+        .windows(2)
+        .map(|pair| {
+            let previous_close = pair[0].close_price;
+            let bar = &pair[1];
+            (bar.high_price - bar.low_price)
+                .max((bar.high_price - previous_close).abs())
+                .max((bar.low_price - previous_close).abs())
+        })
+        .sum();
+    Some(total / period as f64)
+}
+
+/// Standard deviation of the returns of the last `window` bars, oldest first
+pub fn volatility(bars: &[Bar], window: usize) -> Option<f64> {
+    if window < 2 || bars.len() < window + 1 {
+        return None;
+    }
+    let returns: Vec<f64> = bars[bars.len() - window - 1..]
+        .windows(2)
+        .map(|pair| pair[1].close_price / pair[0].close_price - 1.0)
+        .collect();
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance =
+        returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account() -> Account {
+        Account {
+            id: "a".to_string(),
+            equity: Num::from(10_000),
+            buying_power: Num::from(10_000),
+            last_equity: None,
+        }
+    }
+
+    fn bars(closes: &[f64], range: f64) -> Vec<Bar> {
+        closes
+            .iter()
+            .map(|&close| Bar {
+                close_price: close,
+                high_price: close + range / 2.0,
+                low_price: close - range / 2.0,
+                n: 1,
+                open_price: close,
+                timestamp: "2024-01-02T15:00:00Z".to_string(),
+                volume: 10.0,
+                vw: close,
+            })
+            .collect()
+    }
+
+    fn size(model: SizingModel, stop_price: Option<&Num>, bars: &[Bar], asset: &Asset) -> Num {
+        let account = account();
+        let price = Num::from(100);
+        model.size(
+            &SizingInput {
+                account: &account,
+                price: &price,
+                stop_price,
+                bars,
+                risk_per_trade: 0.01,
+                volatility_window: 4,
+            },
+            asset,
+        )
+    }
+
+    #[test]
+    fn risk_is_taken_at_the_stop() {
+        let whole_shares = Asset {
+            fractionable: false,
+            ..Asset::unknown("AAPL")
+        };
+        let stop = Num::from(97);
+        // 100 at risk over a 3 dollar stop
+        assert_eq!(
+            size(SizingModel::StopDistance, Some(&stop), &[], &whole_shares),
+            Num::from(33)
+        );
+        assert_eq!(
+            size(SizingModel::StopDistance, None, &[], &whole_shares),
+            Num::from(1)
+        );
+
+        let bitcoin = Asset::unknown("BTC/USD");
+        let qty = size(SizingModel::StopDistance, Some(&stop), &[], &bitcoin);
+        assert!(qty > Num::from(33) && qty < Num::from(34));
+    }
+
+    #[test]
+    fn volatility_models() {
+        let asset = Asset::unknown("AAPL");
+        let atr = SizingModel::Atr {
+            period: 3,
+            multiple: 2.0,
+        };
+        // true range of 2 on flat closes, 100 at risk over 4 dollars
+        let flat = bars(&[100.0, 100.0, 100.0, 100.0], 2.0);
+        assert_eq!(size(atr.clone(), None, &flat, &asset), Num::from(25));
+        assert_eq!(size(atr, None, &flat[..3], &asset), Num::from(0));
+
+        let target = SizingModel::VolatilityTarget { target: 0.01 };
+        let swinging = bars(&[100.0, 101.0, 100.0, 101.0, 100.0], 1.0);
+        let qty = size(target.clone(), None, &swinging, &asset);
+        assert!(qty > Num::from(0) && qty < Num::from(100));
+        // capped by the buying power
+        let calm = bars(&[100.0, 100.01, 100.0, 100.01, 100.0], 1.0);
+        assert_eq!(size(target, None, &calm, &asset), Num::from(100));
+    }
+
+    #[test]
+    fn kelly_and_fixed_notional() {
+        let asset = Asset::unknown("AAPL");
+        let kelly = SizingModel::Kelly {
+            win_rate: 0.6,
+            payoff: 1.0,
+            cap: 0.1,
+        };
+        // a 20% kelly fraction capped at 10% of the equity
+        assert_eq!(size(kelly, None, &[], &asset), Num::from(10));
+        let losing = SizingModel::Kelly {
+            win_rate: 0.3,
+            payoff: 1.0,
+            cap: 0.5,
+        };
+        assert_eq!(size(losing, None, &[], &asset), Num::from(0));
+
+        let notional = SizingModel::FixedNotional { notional: 250.0 };
+        assert_eq!(size(notional, None, &[], &asset), Num::new(5, 2));
+
+        assert!(SizingModel::Kelly {
+            win_rate: 1.5,
+            payoff: 1.0,
+            cap: 0.1
+        }
+        .validate()
+        .is_err());
+        let parsed: SizingModel =
+            serde_json::from_str(r#"{"model": "atr", "period": 14, "multiple": 2.5}"#).unwrap();
+        assert_eq!(
+            parsed,
+            SizingModel::Atr {
+                period: 14,
+                multiple: 2.5
+            }
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use crate::backtest::report::BacktestReport;
+use crate::bot::BotConfig;
+use crate::error::Error;
+use sqlx::PgPool;
+
+/// save a backtest report
+pub async fn create_backtest(
+    db: &PgPool,
+    id: &str,
+    config: &BotConfig,
+    report: &BacktestReport,
+) -> Result<String, Error> {
+    let backtest_id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO backtests (
+                id,
+                bot_name,
+                trading_strategy,
+                symbols,
+                config,
+                report
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+        "#,
+        id,
+        config.name,
+        config.strategy.kind(),
+        &config.symbols.join(","),
+        serde_json::to_value(config)?,
+        serde_json::to_value(report)?
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(backtest_id)
+}
+
+/// get a saved backtest report
+pub async fn get_backtest(db: &PgPool, id: &str) -> Result<Option<BacktestReport>, Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT report
+        FROM backtests
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match record {
+        Some(r) => Ok(Some(serde_json::from_value(r.report)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,176 @@
+use crate::error::Error;
+use crate::models::bar::{Bar, BarCoverage};
+use crate::models::timeframe::Timeframe;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// save the bars of a symbol, replacing the stored ones with the same time
+pub async fn save_bars(
+    db: &PgPool,
+    symbol: &str,
+    timeframe: Timeframe,
+    bars: &[Bar],
+) -> Result<u64, Error> {
+    let timestamps = bars
+        .iter()
+        .map(|bar| {
+            bar.timestamp.parse::<DateTime<Utc>>().map_err(|e| {
+                Error::InvalidData(format!("bad timestamp '{}': {}", bar.timestamp, e))
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let column = |value: fn(&Bar) -> f64| bars.iter().map(value).collect::<Vec<f64>>();
+
+    let result = sqlx::query!(
+        r#"
+            INSERT INTO bars (symbol, timeframe, timestamp, open, high, low, close, volume, trades, vwap)
+            SELECT $1, $2, *
+            FROM UNNEST(
+                $3::TIMESTAMPTZ[],
+                $4::DOUBLE PRECISION[],
+                $5::DOUBLE PRECISION[],
+                $6::DOUBLE PRECISION[],
+                $7::DOUBLE PRECISION[],
+                $8::DOUBLE PRECISION[],
+                $9::BIGINT[],
+                $10::DOUBLE PRECISION[]
+            )
+            ON CONFLICT (symbol, timeframe, timestamp) DO UPDATE SET
+                open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                volume = EXCLUDED.volume,
+                trades = EXCLUDED.trades,
+                vwap = EXCLUDED.vwap
+        "#,
+        symbol,
+        timeframe.to_string(),
+        &timestamps,
+        &column(|bar| bar.open_price),
+        &column(|bar| bar.high_price),
+        &column(|bar| bar.low_price),
+        &column(|bar| bar.close_price),
+        &column(|bar| bar.volume),
+        &bars.iter().map(|bar| bar.n as i64).collect::<Vec<i64>>(),
+        &column(|bar| bar.vw)
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// get the stored bars of symbols between `start` and `end`, latest first and
+/// at most `limit` per symbol
+pub async fn get_bars(
+    db: &PgPool,
+    symbols: &[String],
+    timeframe: Timeframe,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<HashMap<String, Vec<Bar>>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT symbol AS "symbol!", timestamp AS "timestamp!", open AS "open!", high AS "high!",
+            low AS "low!", close AS "close!", volume AS "volume!", trades AS "trades!",
+            vwap AS "vwap!"
+        FROM (
+            SELECT *, ROW_NUMBER() OVER (PARTITION BY symbol ORDER BY timestamp DESC) AS rank
+            FROM bars
+            WHERE symbol = ANY($1) AND timeframe = $2 AND timestamp >= $3
+                AND ($4::TIMESTAMPTZ IS NULL OR timestamp <= $4)
+        ) latest
+        WHERE $5::BIGINT IS NULL OR rank <= $5
+        ORDER BY symbol, timestamp DESC
+        "#,
+        symbols,
+        timeframe.to_string(),
+        start,
+        end,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut bars: HashMap<String, Vec<Bar>> = HashMap::new();
+    for row in rows {
+        bars.entry(row.symbol).or_default().push(Bar {
+            close_price: row.close,
+            high_price: row.high,
+            low_price: row.low,
+            n: row.trades as u32,
+            open_price: row.open,
+            timestamp: row.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+            volume: row.volume,
+            vw: row.vwap,
+        });
+    }
+    Ok(bars)
+}
+
+/// get the stored range of symbols, with the number of stored bars since
+/// `since` in it
+pub async fn get_coverage(
+    db: &PgPool,
+    symbols: &[String],
+    timeframe: Timeframe,
+    since: DateTime<Utc>,
+) -> Result<HashMap<String, (BarCoverage, i64)>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT c.symbol, c.start_at, c.end_at, (
+            SELECT COUNT(*)
+            FROM bars b
+            WHERE b.symbol = c.symbol AND b.timeframe = c.timeframe
+                AND b.timestamp >= GREATEST(c.start_at, $3) AND b.timestamp <= c.end_at
+        ) AS "stored!"
+        FROM bar_coverage c
+        WHERE c.symbol = ANY($1) AND c.timeframe = $2
+        "#,
+        symbols,
+        timeframe.to_string(),
+        since
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let coverage = BarCoverage {
+                start: row.start_at,
+                end: row.end_at,
+            };
+            (row.symbol, (coverage, row.stored))
+        })
+        .collect())
+}
+
+/// set the stored range of a symbol
+pub async fn set_coverage(
+    db: &PgPool,
+    symbol: &str,
+    timeframe: Timeframe,
+    coverage: &BarCoverage,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO bar_coverage (symbol, timeframe, start_at, end_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (symbol, timeframe) DO UPDATE SET
+                start_at = EXCLUDED.start_at,
+                end_at = EXCLUDED.end_at
+        "#,
+        symbol,
+        timeframe.to_string(),
+        coverage.start,
+        coverage.end
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -1,12 +1,20 @@
-use crate::bot::{BotStrategy, MarketType};
+use crate::bot::supervisor::{BotHealth, RestartPolicy};
+use crate::bot::{BotConfig, BotInfo, Drain, MarketType};
+use crate::core::sizing::SizingModel;
 use crate::error::Error;
-use crate::{
-    bot::{BotConfig, BotInfo},
-    error::RequestError,
-};
+use crate::models::timeframe::Timeframe;
+use num_decimal::Num;
 use sqlx::PgPool;
 use std::str::FromStr;
-use uuid::Uuid;
+
+/// timeframes of a bot as stored, comma separated
+fn timeframes(timeframes: &[Timeframe]) -> String {
+    timeframes
+        .iter()
+        .map(Timeframe::to_string)
+        .collect::<Vec<String>>()
+        .join(",")
+}
 
 pub async fn create_bot(db: &PgPool, data: BotConfig) -> Result<String, Error> {
     let bot_id = sqlx::query_scalar!(
@@ -15,32 +23,37 @@ pub async fn create_bot(db: &PgPool, data: BotConfig) -> Result<String, Error> {
                 id,
                 name,
                 market,
-                trading_strategy,
+                strategy,
                 symbols,
-                lookback,
-                threshold,
                 risk_per_trade,
                 max_positions,
                 timeframes,
                 volatility_window,
-                volatility_threshold,
+                sizing,
+                allocation,
+                restart,
+                on_stop,
                 is_running
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             RETURNING id
         "#,
         data.id,
         data.name,
         data.market.to_string(),
-        data.trading_strategy.to_string(),
+        serde_json::to_value(&data.strategy)?,
         &data.symbols.join(","),
-        data.lookback as i32,
-        data.threshold,
         data.risk_per_trade,
         data.max_positions as i32,
-        &data.timeframes.join(","),
+        &timeframes(&data.timeframes),
         data.volatility_window as i32,
-        data.volatility_threshold,
+        serde_json::to_value(&data.sizing)?,
+        data.allocation
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?,
+        serde_json::to_value(&data.restart)?,
+        data.on_stop.to_string(),
         true
     )
     .fetch_one(db)
@@ -49,74 +62,317 @@ pub async fn create_bot(db: &PgPool, data: BotConfig) -> Result<String, Error> {
     Ok(bot_id)
 }
 
-/// update bot
-pub async fn kill_bot(db: &PgPool, bot_id: String) -> Result<String, Error> {
-    let bot_id = sqlx::query!(
+/// update the config of a bot, false when the bot does not exist
+pub async fn update_bot(db: &PgPool, data: &BotConfig) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE bots
+            SET name = $2,
+                market = $3,
+                strategy = $4,
+                symbols = $5,
+                risk_per_trade = $6,
+                max_positions = $7,
+                timeframes = $8,
+                volatility_window = $9,
+                sizing = $10,
+                allocation = $11,
+                restart = $12,
+                on_stop = $13,
+                capital = CASE WHEN allocation IS DISTINCT FROM $11 THEN NULL ELSE capital END
+            WHERE id = $1 AND archived_at IS NULL
+        "#,
+        data.id,
+        data.name,
+        data.market.to_string(),
+        serde_json::to_value(&data.strategy)?,
+        &data.symbols.join(","),
+        data.risk_per_trade,
+        data.max_positions as i32,
+        &timeframes(&data.timeframes),
+        data.volatility_window as i32,
+        serde_json::to_value(&data.sizing)?,
+        data.allocation
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?,
+        serde_json::to_value(&data.restart)?,
+        data.on_stop.to_string()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// set whether a bot runs, it is started on startup when it does, false
+/// when the bot does not exist
+pub async fn set_bot_running(db: &PgPool, bot_id: &str, is_running: bool) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE bots
+            SET is_running = $2
+            WHERE id = $1 AND archived_at IS NULL
+        "#,
+        bot_id,
+        is_running
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// get the capital a bot was given when it first started, none before or
+/// since its allocation changed
+pub async fn get_bot_capital(db: &PgPool, bot_id: &str) -> Result<Option<Num>, Error> {
+    let capital = sqlx::query_scalar!(
+        r#"
+        SELECT capital::TEXT
+        FROM bots
+        WHERE id = $1
+        "#,
+        bot_id
+    )
+    .fetch_optional(db)
+    .await?
+    .flatten();
+
+    capital
+        .map(|capital| {
+            capital
+                .parse::<Num>()
+                .map_err(|e| Error::InvalidData(format!("capital of bot {}: {}", bot_id, e)))
+        })
+        .transpose()
+}
+
+/// set the capital of a bot
+pub async fn set_bot_capital(db: &PgPool, bot_id: &str, capital: &Num) -> Result<(), Error> {
+    sqlx::query!(
         r#"
             UPDATE bots
-            SET is_running = false
+            SET capital = $2::TEXT::NUMERIC
             WHERE id = $1
-            RETURNING id
+        "#,
+        bot_id,
+        capital.to_string()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// archive a bot, its orders, signals and runs are kept, false when the bot
+/// does not exist
+pub async fn archive_bot(db: &PgPool, bot_id: &str) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE bots
+            SET is_running = false, archived_at = NOW()
+            WHERE id = $1 AND archived_at IS NULL
         "#,
         bot_id
     )
-        .fetch_one(db)
-        .await?;
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
 
-    Ok(bot_id.id)
+/// Row of the `bots` table
+struct BotRow {
+    id: String,
+    name: Option<String>,
+    market: String,
+    strategy: serde_json::Value,
+    symbols: String,
+    risk_per_trade: f64,
+    max_positions: i32,
+    timeframes: String,
+    volatility_window: i32,
+    sizing: serde_json::Value,
+    allocation: Option<serde_json::Value>,
+    restart: serde_json::Value,
+    on_stop: String,
+    is_running: Option<bool>,
 }
 
-/// get_all_running_bot: get all running bots
-pub async fn get_all_running_bot(db: &PgPool) -> Result<Vec<BotInfo>, Error> {
-    let bots_record = sqlx::query!(
+/// A bot of an unknown market or strategy cannot be run, the other invalid
+/// settings fall back to their defaults
+impl TryFrom<BotRow> for BotInfo {
+    type Error = Error;
+
+    fn try_from(r: BotRow) -> Result<Self, Error> {
+        let market = MarketType::from_str(&r.market).map_err(|_| {
+            Error::InvalidData(format!("unknown market of bot {}: {}", r.id, r.market))
+        })?;
+        let strategy = serde_json::from_value(r.strategy)
+            .map_err(|e| Error::InvalidData(format!("invalid strategy of bot {}: {}", r.id, e)))?;
+        let sizing = serde_json::from_value(r.sizing).unwrap_or_else(|e| {
+            tracing::warn!("Invalid sizing of bot {}, using the default: {}", r.id, e);
+            SizingModel::default()
+        });
+        let allocation = r.allocation.and_then(|allocation| {
+            serde_json::from_value(allocation)
+                .map_err(|e| tracing::warn!("Invalid allocation of bot {}: {}", r.id, e))
+                .ok()
+        });
+        let restart = serde_json::from_value(r.restart).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Invalid restart policy of bot {}, using the default: {}",
+                r.id,
+                e
+            );
+            RestartPolicy::default()
+        });
+        let on_stop = Drain::from_str(&r.on_stop).unwrap_or_else(|_| {
+            tracing::warn!(
+                "Invalid drain of bot {}, using the default: {}",
+                r.id,
+                r.on_stop
+            );
+            Drain::default()
+        });
+        let timeframes = r
+            .timeframes
+            .split(',')
+            .filter_map(|timeframe| {
+                timeframe
+                    .parse()
+                    .map_err(|e| tracing::warn!("Invalid timeframe of bot {}: {}", r.id, e))
+                    .ok()
+            })
+            .collect();
+        let config = BotConfig {
+            id: r.id,
+            name: r.name.unwrap_or_default(),
+            market,
+            strategy,
+            symbols: r.symbols.split(',').map(String::from).collect(),
+            risk_per_trade: r.risk_per_trade,
+            max_positions: r.max_positions as usize,
+            timeframes,
+            volatility_window: r.volatility_window as usize,
+            sizing,
+            allocation,
+            restart,
+            on_stop,
+        };
+
+        Ok(BotInfo {
+            config,
+            is_running: r.is_running.unwrap_or_default(),
+            health: BotHealth::default(),
+        })
+    }
+}
+
+/// get a bot, none when it does not exist or is archived
+pub async fn get_bot(db: &PgPool, bot_id: &str) -> Result<Option<BotInfo>, Error> {
+    let bot = sqlx::query_as!(
+        BotRow,
         r#"
         SELECT
             id,
             name,
             market,
-            trading_strategy,
+            strategy,
             symbols,
-            lookback,
-            threshold,
             risk_per_trade,
             max_positions,
             timeframes,
             volatility_window,
-            volatility_threshold,
+            sizing,
+            allocation,
+            restart,
+            on_stop,
             is_running
         FROM bots
+        WHERE id = $1 AND archived_at IS NULL
+        "#,
+        bot_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    bot.map(BotInfo::try_from).transpose()
+}
+
+/// get the bots not archived, running or not, the invalid ones are skipped
+pub async fn get_bots(db: &PgPool) -> Result<Vec<BotInfo>, Error> {
+    let bots = sqlx::query_as!(
+        BotRow,
+        r#"
+        SELECT
+            id,
+            name,
+            market,
+            strategy,
+            symbols,
+            risk_per_trade,
+            max_positions,
+            timeframes,
+            volatility_window,
+            sizing,
+            allocation,
+            restart,
+            on_stop,
+            is_running
+        FROM bots
+        WHERE archived_at IS NULL
         "#
     )
     .fetch_all(db)
     .await
     .map_err(Error::Database)?;
 
-    let bots = bots_record
+    Ok(bots
         .into_iter()
-        .map(|r| {
-            let config = BotConfig {
-                id: r.id,
-                name: r.name.unwrap(),
-                market: MarketType::from_str(&r.market).unwrap(),
-                trading_strategy: BotStrategy::from_str(&r.trading_strategy).unwrap(),
-                symbols: r.symbols.split(',').map(String::from).collect(),
-                lookback: r.lookback as usize,
-                threshold: r.threshold,
-                risk_per_trade: r.risk_per_trade,
-                max_positions: r.max_positions as usize,
-                timeframes: r.timeframes.split(',').map(String::from).collect(),
-                volatility_window: r.volatility_window as usize,
-                volatility_threshold: r.volatility_threshold,
-            };
-
-            BotInfo {
-                config,
-                is_running: r.is_running.unwrap(),
-            }
+        .filter_map(|bot| {
+            BotInfo::try_from(bot)
+                .map_err(|e| tracing::warn!("Skipping bot: {}", e))
+                .ok()
         })
-        .collect();
-
-    Ok(bots)
+        .collect())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
+    fn row(market: &str, strategy: serde_json::Value) -> BotRow {
+        BotRow {
+            id: "bot".to_string(),
+            name: None,
+            market: market.to_string(),
+            strategy,
+            symbols: "AAPL".to_string(),
+            risk_per_trade: 0.01,
+            max_positions: 1,
+            timeframes: "1Min".to_string(),
+            volatility_window: 5,
+            sizing: json!({"model": "fixed_fraction"}),
+            allocation: None,
+            restart: json!({"policy": "never"}),
+            on_stop: "leave".to_string(),
+            is_running: None,
+        }
+    }
+
+    #[test]
+    fn bots_of_unknown_strategy_or_market_are_invalid() {
+        let strategy = json!({"kind": "mean_reversion", "params": {"lookback": 5}});
+        assert!(BotInfo::try_from(row("Equity", strategy.clone())).is_ok());
+        assert!(matches!(
+            BotInfo::try_from(row("Forex", strategy)),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            BotInfo::try_from(row("Equity", json!({"kind": "martingale"}))),
+            Err(Error::InvalidData(_))
+        ));
+    }
+}
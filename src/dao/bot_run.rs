@@ -0,0 +1,68 @@
+use crate::bot::{BotConfig, BotRun};
+use crate::error::Error;
+use sqlx::PgPool;
+
+/// save the start of a run of a bot with its config
+pub async fn start_bot_run(db: &PgPool, config: &BotConfig) -> Result<i64, Error> {
+    let id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO bot_runs (bot_id, config)
+            VALUES ($1, $2)
+            RETURNING id
+        "#,
+        config.id,
+        serde_json::to_value(config)?
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(id)
+}
+
+/// end the open runs of a bot, of every bot when none
+pub async fn stop_bot_runs(db: &PgPool, bot_id: Option<&str>, reason: &str) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE bot_runs
+        SET stopped_at = NOW(), stop_reason = $2
+        WHERE stopped_at IS NULL AND ($1::VARCHAR IS NULL OR bot_id = $1)
+        "#,
+        bot_id,
+        reason
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// get the runs of a bot, newest first
+pub async fn get_bot_runs(db: &PgPool, bot_id: &str, limit: i64) -> Result<Vec<BotRun>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, bot_id, config, started_at, stopped_at, stop_reason
+        FROM bot_runs
+        WHERE bot_id = $1
+        ORDER BY started_at DESC, id DESC
+        LIMIT $2
+        "#,
+        bot_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let runs = rows
+        .into_iter()
+        .map(|row| BotRun {
+            id: row.id,
+            bot_id: row.bot_id,
+            config: row.config,
+            started_at: row.started_at,
+            stopped_at: row.stopped_at,
+            stop_reason: row.stop_reason,
+        })
+        .collect();
+
+    Ok(runs)
+}
@@ -0,0 +1,81 @@
+use crate::error::Error;
+use crate::models::order::{client_order_bot_id, BrokerOrder, Fill};
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use sqlx::PgPool;
+
+/// save an execution of `order`
+pub async fn create_fill(
+    db: &PgPool,
+    order: &BrokerOrder,
+    qty: &Num,
+    price: &Num,
+    timestamp: DateTime<Utc>,
+) -> Result<i64, Error> {
+    let bot_id = order
+        .client_order_id
+        .as_deref()
+        .and_then(client_order_bot_id);
+
+    let id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO fills (order_id, bot_id, symbol, side, qty, price, timestamp)
+            VALUES ($1, $2, $3, $4, $5::TEXT::NUMERIC, $6::TEXT::NUMERIC, $7)
+            RETURNING id
+        "#,
+        order.id,
+        bot_id,
+        order.symbol,
+        order.side.as_str(),
+        qty.to_string(),
+        price.to_string(),
+        timestamp
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(id)
+}
+
+/// get the latest fills of a bot, newest first, every fill when `limit` is
+/// none
+pub async fn get_bot_fills(
+    db: &PgPool,
+    bot_id: &str,
+    limit: Option<i64>,
+) -> Result<Vec<Fill>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, order_id, bot_id, symbol, side, qty::TEXT AS "qty!", price::TEXT AS "price!",
+            timestamp
+        FROM fills
+        WHERE bot_id = $1
+        ORDER BY timestamp DESC, id DESC
+        LIMIT $2
+        "#,
+        bot_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let num = |value: &str| {
+                value
+                    .parse::<Num>()
+                    .map_err(|e| Error::InvalidData(format!("fill {}: {}", row.id, e)))
+            };
+            Ok(Fill {
+                qty: num(&row.qty)?,
+                price: num(&row.price)?,
+                id: row.id,
+                order_id: row.order_id,
+                bot_id: row.bot_id,
+                symbol: row.symbol,
+                side: row.side,
+                timestamp: row.timestamp,
+            })
+        })
+        .collect()
+}
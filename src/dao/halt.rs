@@ -0,0 +1,62 @@
+use crate::core::halt::Halt;
+use crate::error::Error;
+use sqlx::PgPool;
+
+/// save a halt, replacing the one of the same bot, the whole account when
+/// the halt has no bot
+pub async fn save_halt(db: &PgPool, halt: &Halt) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO trading_halts (bot_id, trigger, reason, halted_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (bot_id) DO UPDATE
+            SET trigger = EXCLUDED.trigger, reason = EXCLUDED.reason, halted_at = EXCLUDED.halted_at
+        "#,
+        halt.bot_id.as_deref().unwrap_or(""),
+        halt.trigger.as_str(),
+        halt.reason,
+        halt.halted_at
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// delete the halt of a bot, of the whole account when none
+pub async fn delete_halt(db: &PgPool, bot_id: Option<&str>) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM trading_halts
+        WHERE bot_id = $1
+        "#,
+        bot_id.unwrap_or("")
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// get the halts in place
+pub async fn get_halts(db: &PgPool) -> Result<Vec<Halt>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT bot_id, trigger, reason, halted_at
+        FROM trading_halts
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Halt {
+                bot_id: Some(row.bot_id).filter(|bot_id| !bot_id.is_empty()),
+                trigger: row.trigger.parse().map_err(Error::InvalidData)?,
+                reason: row.reason,
+                halted_at: row.halted_at,
+            })
+        })
+        .collect()
+}
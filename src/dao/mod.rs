@@ -1 +1,40 @@
+use crate::error::Error;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+
+pub mod backtest;
+pub mod bar;
 pub mod bot;
+pub mod bot_run;
+pub mod fill;
+pub mod halt;
+pub mod order;
+pub mod signal;
+
+/// Versioned schema of `migrations/`, embedded in the binary
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// apply the migrations not applied yet, in order
+pub async fn migrate(db: &PgPool) -> Result<(), Error> {
+    MIGRATOR.run(db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_ordered() {
+        let versions: Vec<i64> = MIGRATOR
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| migration.version)
+            .collect();
+        assert!(!versions.is_empty());
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(MIGRATOR
+            .iter()
+            .any(|migration| migration.sql.contains("CREATE TABLE IF NOT EXISTS bots")));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::error::Error;
+use crate::models::order::{
+    client_order_bot_id, BrokerOrder, OrderEvent, OrderRecord, OrderStatus, TradeUpdate,
+};
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use sqlx::PgPool;
+
+/// save a transition of an order
+pub async fn create_order_event(
+    db: &PgPool,
+    update: &TradeUpdate,
+    status: OrderStatus,
+) -> Result<i64, Error> {
+    let timestamp = update
+        .timestamp
+        .as_deref()
+        .and_then(|timestamp| timestamp.parse::<DateTime<Utc>>().ok())
+        .unwrap_or_else(Utc::now);
+    let text = |value: &Option<Num>| value.as_ref().map(Num::to_string);
+
+    let id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO order_events (
+                order_id,
+                client_order_id,
+                symbol,
+                event,
+                status,
+                price,
+                qty,
+                filled_qty,
+                position_qty,
+                timestamp
+            )
+            VALUES ($1, $2, $3, $4, $5, $6::TEXT::NUMERIC, $7::TEXT::NUMERIC, $8::TEXT::NUMERIC, $9::TEXT::NUMERIC, $10)
+            RETURNING id
+        "#,
+        update.order.id,
+        update.order.client_order_id,
+        update.order.symbol,
+        update.event.to_string(),
+        status.to_string(),
+        text(&update.price),
+        text(&update.qty),
+        text(&update.order.filled_qty),
+        text(&update.position_qty),
+        timestamp
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(id)
+}
+
+/// get the transitions of an order, oldest first
+pub async fn get_order_events(db: &PgPool, order_id: &str) -> Result<Vec<OrderEvent>, Error> {
+    // decimals are read as text, sqlx has no mapping for them
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, order_id, client_order_id, symbol, event, status,
+            price::TEXT AS price, qty::TEXT AS qty, filled_qty::TEXT AS filled_qty,
+            position_qty::TEXT AS position_qty, timestamp
+        FROM order_events
+        WHERE order_id = $1
+        ORDER BY timestamp, id
+        "#,
+        order_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let num = |value: Option<String>| value.and_then(|value| value.parse::<Num>().ok());
+    let events = rows
+        .into_iter()
+        .map(|row| OrderEvent {
+            id: row.id,
+            order_id: row.order_id,
+            client_order_id: row.client_order_id,
+            symbol: row.symbol,
+            event: row.event,
+            status: row.status,
+            price: num(row.price),
+            qty: num(row.qty),
+            filled_qty: num(row.filled_qty),
+            position_qty: num(row.position_qty),
+            timestamp: row.timestamp,
+        })
+        .collect();
+
+    Ok(events)
+}
+
+/// get the last recorded status of an order
+pub async fn get_order_status(db: &PgPool, order_id: &str) -> Result<Option<OrderStatus>, Error> {
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT status
+        FROM order_events
+        WHERE order_id = $1
+        ORDER BY timestamp DESC, id DESC
+        LIMIT 1
+        "#,
+        order_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match status {
+        Some(status) => Ok(Some(serde_json::from_value(serde_json::Value::String(
+            status,
+        ))?)),
+        None => Ok(None),
+    }
+}
+
+/// save an order with its latest `status`, the recorded one is updated
+pub async fn save_order(db: &PgPool, order: &BrokerOrder, status: &str) -> Result<(), Error> {
+    let text = |value: &Option<Num>| value.as_ref().map(Num::to_string);
+    let bot_id = order
+        .client_order_id
+        .as_deref()
+        .and_then(client_order_bot_id);
+    let created_at = order
+        .created_at
+        .as_deref()
+        .and_then(|created_at| created_at.parse::<DateTime<Utc>>().ok())
+        .unwrap_or_else(Utc::now);
+
+    sqlx::query!(
+        r#"
+            INSERT INTO orders (
+                id,
+                client_order_id,
+                bot_id,
+                symbol,
+                side,
+                order_type,
+                time_in_force,
+                order_class,
+                qty,
+                notional,
+                limit_price,
+                stop_price,
+                filled_qty,
+                filled_avg_price,
+                status,
+                replaces,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::TEXT::NUMERIC, $10::TEXT::NUMERIC, $11::TEXT::NUMERIC, $12::TEXT::NUMERIC, $13::TEXT::NUMERIC, $14::TEXT::NUMERIC, $15, $16, $17)
+            ON CONFLICT (id) DO UPDATE
+            SET qty = EXCLUDED.qty,
+                limit_price = EXCLUDED.limit_price,
+                stop_price = EXCLUDED.stop_price,
+                filled_qty = EXCLUDED.filled_qty,
+                filled_avg_price = EXCLUDED.filled_avg_price,
+                status = EXCLUDED.status,
+                updated_at = NOW()
+        "#,
+        order.id,
+        order.client_order_id,
+        bot_id,
+        order.symbol,
+        order.side.as_str(),
+        order.order_type.as_str(),
+        order.time_in_force.as_str(),
+        order.order_class.as_str(),
+        text(&order.qty),
+        text(&order.notional),
+        text(&order.limit_price),
+        text(&order.stop_price),
+        text(&order.filled_qty),
+        text(&order.filled_avg_price),
+        status,
+        order.replaces,
+        created_at
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// get the latest orders of a bot, newest first
+pub async fn get_bot_orders(
+    db: &PgPool,
+    bot_id: &str,
+    limit: i64,
+) -> Result<Vec<OrderRecord>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, client_order_id, bot_id, symbol, side, order_type, time_in_force, order_class,
+            qty::TEXT AS qty, notional::TEXT AS notional, limit_price::TEXT AS limit_price,
+            stop_price::TEXT AS stop_price, filled_qty::TEXT AS filled_qty,
+            filled_avg_price::TEXT AS filled_avg_price, status, replaces, created_at, updated_at
+        FROM orders
+        WHERE bot_id = $1
+        ORDER BY created_at DESC, id
+        LIMIT $2
+        "#,
+        bot_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let num = |value: Option<String>| value.and_then(|value| value.parse::<Num>().ok());
+    let orders = rows
+        .into_iter()
+        .map(|row| OrderRecord {
+            id: row.id,
+            client_order_id: row.client_order_id,
+            bot_id: row.bot_id,
+            symbol: row.symbol,
+            side: row.side,
+            order_type: row.order_type,
+            time_in_force: row.time_in_force,
+            order_class: row.order_class,
+            qty: num(row.qty),
+            notional: num(row.notional),
+            limit_price: num(row.limit_price),
+            stop_price: num(row.stop_price),
+            filled_qty: num(row.filled_qty),
+            filled_avg_price: num(row.filled_avg_price),
+            status: row.status,
+            replaces: row.replaces,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+        .collect();
+
+    Ok(orders)
+}
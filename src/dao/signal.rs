@@ -0,0 +1,93 @@
+use crate::bot::strategies::Signal;
+use crate::error::Error;
+use crate::models::num_from_f64;
+use crate::models::signal::{SignalOutcome, SignalRecord};
+use num_decimal::Num;
+use sqlx::PgPool;
+
+/// save a signal of a bot with its outcome, `qty` is the quantity sized for
+/// it and `order_id` the order placed
+pub async fn create_signal(
+    db: &PgPool,
+    bot_id: &str,
+    signal: &Signal,
+    qty: Option<&Num>,
+    outcome: SignalOutcome,
+    reason: Option<&str>,
+    order_id: Option<&str>,
+) -> Result<i64, Error> {
+    let text = |value: f64| num_from_f64(value).map(|value| value.to_string());
+
+    let id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO signals (
+                bot_id,
+                symbol,
+                side,
+                price,
+                stop_price,
+                qty,
+                outcome,
+                reason,
+                order_id
+            )
+            VALUES ($1, $2, $3, $4::TEXT::NUMERIC, $5::TEXT::NUMERIC, $6::TEXT::NUMERIC, $7, $8, $9)
+            RETURNING id
+        "#,
+        bot_id,
+        signal.symbol,
+        signal.side.as_str(),
+        text(signal.price).unwrap_or_else(|| "NaN".to_string()),
+        signal.stop_price.and_then(text),
+        qty.map(Num::to_string),
+        outcome.as_str(),
+        reason,
+        order_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(id)
+}
+
+/// get the latest signals of a bot, newest first
+pub async fn get_bot_signals(
+    db: &PgPool,
+    bot_id: &str,
+    limit: i64,
+) -> Result<Vec<SignalRecord>, Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, bot_id, symbol, side, price::TEXT AS "price!", stop_price::TEXT AS stop_price,
+            qty::TEXT AS qty, outcome, reason, order_id, created_at
+        FROM signals
+        WHERE bot_id = $1
+        ORDER BY created_at DESC, id DESC
+        LIMIT $2
+        "#,
+        bot_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let num = |value: Option<String>| value.and_then(|value| value.parse::<Num>().ok());
+    let signals = rows
+        .into_iter()
+        .map(|row| SignalRecord {
+            id: row.id,
+            bot_id: row.bot_id,
+            symbol: row.symbol,
+            side: row.side,
+            price: num(Some(row.price)).unwrap_or_default(),
+            stop_price: num(row.stop_price),
+            qty: num(row.qty),
+            outcome: row.outcome,
+            reason: row.reason,
+            order_id: row.order_id,
+            created_at: row.created_at,
+        })
+        .collect();
+
+    Ok(signals)
+}
@@ -1,8 +1,10 @@
+use crate::core::risk::RiskRejection;
 use axum::http::StatusCode;
 use hyper::http::Error as HttpError;
 use hyper::Error as HyperError;
 use hyper_util::client::legacy::Error as LegacyHyperError;
 use serde_json::Error as JsonError;
+use sqlx::migrate::MigrateError;
 use sqlx::Error as DataBaseError;
 use thiserror::Error;
 
@@ -22,6 +24,33 @@ pub enum RequestError {
 
     #[error("API returned an error status: {0}")]
     ApiError(StatusCode),
+
+    #[error("Malformed API response: {0}")]
+    MalformedResponse(JsonError),
+
+    #[error("Invalid order: {0}")]
+    InvalidOrder(#[from] traidano::OrderError),
+
+    #[error("Order rejected by the risk checks: {0}")]
+    Risk(#[from] RiskRejection),
+
+    #[error("Trading halted: {0}")]
+    Halted(String),
+}
+
+#[derive(Debug, Error)]
+pub enum StreamError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+
+    #[error("JSON deserialization error: {0}")]
+    Json(#[from] JsonError),
+
+    #[error("Stream authentication failed: {0}")]
+    Auth(String),
+
+    #[error("Stream closed by the server")]
+    Closed,
 }
 
 #[derive(Debug, Error)]
@@ -33,6 +62,21 @@ pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] DataBaseError),
 
+    #[error("Migration error: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error("Broker error: {0}")]
+    Broker(String),
+
     #[error("Failed to acquire lock")]
     LockError,
     #[error("Bot not found")]
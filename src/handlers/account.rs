@@ -1,36 +1,16 @@
 use crate::base::AppState;
 use crate::error::RequestError;
-use crate::handlers::rate_limited_request;
 use crate::models::account::Account;
-use axum::body::Body;
 use axum::extract::State;
-use axum::http::{Method, StatusCode};
 use axum::response::IntoResponse;
 use axum::Json;
-use serde::de::DeserializeOwned;
 use std::sync::Arc;
 use tracing::instrument;
-use traidano::RequestType;
-
-async fn rate_limited_get_account<T>(state: &Arc<AppState>) -> Result<T, RequestError>
-where
-    T: DeserializeOwned,
-{
-    rate_limited_request::<T>(
-        state,
-        Method::GET,
-        "account",
-        Body::empty(),
-        RequestType::Order,
-    )
-    .await
-}
 
 #[instrument(skip(state))]
 pub async fn get_account(state: &Arc<AppState>) -> Result<Account, RequestError> {
     tracing::info!("internal_request: get account information");
-    tracing::trace!("rate limit: {}", state.rate_limiter.lock().await.rate);
-    let response = rate_limited_get_account::<Account>(state).await?;
+    let response = state.broker.get_account().await?;
 
     Ok(response)
 }
@@ -38,8 +18,7 @@ pub async fn get_account(state: &Arc<AppState>) -> Result<Account, RequestError>
 #[instrument(skip(state))]
 pub async fn get_http_account(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     tracing::info!("app_events: get account information");
-    tracing::info!("rate limit: {}", state.rate_limiter.lock().await.rate);
-    match rate_limited_get_account::<Account>(&state).await {
+    match state.broker.get_account().await {
         Ok(account) => {
             tracing::info!(
                account_id = ?account.id,
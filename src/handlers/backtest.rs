@@ -0,0 +1,97 @@
+use crate::backtest::loader::{data_dir, load_symbols};
+use crate::backtest::report::BacktestReport;
+use crate::backtest::{run_backtest, BacktestRequest};
+use crate::base::AppState;
+use crate::dao;
+use crate::models::timeframe::Timeframe;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::DateTime;
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct BacktestResult {
+    pub id: String,
+    pub report: BacktestReport,
+}
+
+pub async fn create_backtest(
+    State(state): State<Arc<AppState>>,
+    Json(mut request): Json<BacktestRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Create backtest request");
+
+    let id = Uuid::new_v4().to_string();
+    request.config.id = id.clone();
+    if let Err(e) = request.config.strategy.validate() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid strategy params: {}", e),
+        )
+            .into_response();
+    }
+
+    let bars = if request.data.is_empty() {
+        // the higher timeframes are built from the minute bars
+        state
+            .bar_store
+            .load(
+                &request.config.symbols,
+                Timeframe::BASE,
+                request.start.unwrap_or(DateTime::UNIX_EPOCH),
+                request.end,
+            )
+            .await
+    } else {
+        load_symbols(&data_dir(), &request.data)
+    };
+    let bars = match bars {
+        Ok(bars) => bars,
+        Err(e) => {
+            // the error may tell which files exist on the server
+            tracing::error!("Cannot load backtest data: {}", e);
+            return (StatusCode::BAD_REQUEST, "Cannot load the backtest data").into_response();
+        }
+    };
+
+    let report = match run_backtest(&state, &request.config, request.sim_config(), bars).await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!("Backtest {} failed: {}", id, e);
+            return (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response();
+        }
+    };
+
+    match dao::backtest::create_backtest(&state.db, &id, &request.config, &report).await {
+        Ok(id) => {
+            tracing::info!("Backtest {} saved to database", id);
+            (StatusCode::CREATED, Json(BacktestResult { id, report })).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to save backtest in database: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save backtest in database",
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn get_backtest(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match dao::backtest::get_backtest(&state.db, &id).await {
+        Ok(Some(report)) => Json(BacktestResult { id, report }).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Backtest not Found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get backtest {}: {:?}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
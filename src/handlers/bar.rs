@@ -1,60 +1,89 @@
 use crate::base::AppState;
-use crate::error::RequestError;
-use crate::handlers::rate_limited_request;
-use crate::models::bar::Bar;
-use axum::body::Body;
-use axum::http::{Method, StatusCode};
-use chrono::{Duration, Utc};
+use crate::bot::MarketType;
+use crate::error::{Error, RequestError};
+use crate::models::bar::{Bar, BarQueryParams};
+use crate::models::timeframe::Timeframe;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use traidano::RequestType;
 
+/// Bars of `symbols`, latest first, from the bar store when it has them
 pub async fn get_bars(
     state: &AppState,
     symbols: &[String],
-    timeframe: &str,
-    limit: usize,
-    start_day: usize,
+    params: &BarQueryParams,
     request_type: &str,
 ) -> Result<HashMap<String, Vec<Bar>>, RequestError> {
     let request_type = RequestType::from(request_type);
-    let start_date = (chrono::Utc::now() - Duration::days(start_day as i64)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
-    match request_type {
-        RequestType::Order => {
-            tracing::error!("Cannot get bar of historical data from order query type");
-            Err(RequestError::ApiError(StatusCode::INTERNAL_SERVER_ERROR))
-        }
-        _ => {
-            let symbols_str = symbols.join(",");
-            let path = match &request_type {
-                RequestType::StockData => {
-                    format!("bars/{}?symbols={}&limit={}&start={}&sort=desc", timeframe, symbols_str, limit, start_date)
-                }
-                RequestType::CryptoData => format!(
-                    "us/bars?symbols={}&timeframe={}&limit={}&start={}&sort=desc",
-                    symbols_str, timeframe, limit, start_date
-                ),
-                _ => "".to_string(),
-            };
-            let response = rate_limited_request::<serde_json::Value>(
-                state,
-                Method::GET,
-                &path,
-                Body::empty(),
-                request_type,
-            )
-            .await?;
-            // Extract the "bars" field from the response
-            let bars_field = response.get("bars").ok_or_else(|| {
-                tracing::error!("Missing 'bars' field in response");
-                RequestError::ApiError(StatusCode::INTERNAL_SERVER_ERROR)
-            })?;
+    state
+        .bar_store
+        .get_bars(state.broker.as_ref(), symbols, params, request_type)
+        .await
+}
 
-            let res: HashMap<String, Vec<Bar>> = serde_json::from_value(bars_field.clone())
-                .unwrap_or_else(|err| {
-                    tracing::error!("Cannot deserialize bar value: {}", err);
-                    HashMap::new()
-                });
-            Ok(res)
+/// Bulk download of the `POST /data/backfill` endpoint, up to now when `end`
+/// is none
+#[derive(Debug, Deserialize)]
+pub struct BackfillRequest {
+    pub symbols: Vec<String>,
+    pub timeframe: Timeframe,
+    pub market: MarketType,
+    pub start: DateTime<Utc>,
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackfillReport {
+    /// Number of bars stored for each symbol
+    pub bars: HashMap<String, usize>,
+}
+
+/// Download the bars of a time range into the bar store, for the backtests
+pub async fn backfill_bars(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BackfillRequest>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Backfill {} bars of {:?} from {}",
+        request.timeframe,
+        request.symbols,
+        request.start
+    );
+    if request.end.is_some_and(|end| end <= request.start) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "end must be after start").into_response();
+    }
+
+    let request_type = match request.market {
+        MarketType::Crypto => RequestType::CryptoData,
+        MarketType::Equity => RequestType::StockData,
+    };
+    match state
+        .bar_store
+        .backfill(
+            state.broker.as_ref(),
+            &request.symbols,
+            request.timeframe,
+            request.start,
+            request.end,
+            request_type,
+        )
+        .await
+    {
+        Ok(bars) => Json(BackfillReport { bars }).into_response(),
+        Err(Error::Broker(e)) => {
+            tracing::error!("Backfill failed: {}", e);
+            (StatusCode::BAD_GATEWAY, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Backfill failed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
         }
     }
 }
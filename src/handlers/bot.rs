@@ -1,17 +1,50 @@
 use crate::base::AppState;
-use crate::bot::{Bot, BotConfig, BotInfo};
+use crate::bot::bot_manager::BotManager;
+use crate::bot::supervisor::BotHealth;
+use crate::bot::{Bot, BotConfig, BotInfo, Drain};
 use crate::dao;
-use crate::dao::bot::get_all_running_bot;
 use crate::error::Error;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::Json;
+use axum::{response, Json};
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
 use uuid::Uuid;
 
+/// Check the parts of a config its types do not
+fn validate_config(config: &BotConfig) -> Result<(), String> {
+    config
+        .strategy
+        .validate()
+        .map_err(|e| format!("Invalid strategy params: {}", e))?;
+    config
+        .sizing
+        .validate()
+        .map_err(|e| format!("Invalid sizing: {}", e))?;
+    if let Some(allocation) = &config.allocation {
+        allocation
+            .validate()
+            .map_err(|e| format!("Invalid allocation: {}", e))?;
+    }
+    config
+        .restart
+        .validate()
+        .map_err(|e| format!("Invalid restart policy: {}", e))?;
+    Ok(())
+}
+
+fn database_error(e: Error, action: &str) -> response::Response {
+    tracing::error!("Failed to {}: {:?}", action, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to {}", action)).into_response()
+}
+
+fn not_found() -> response::Response {
+    (StatusCode::NOT_FOUND, "Bot not Found").into_response()
+}
+
 pub async fn create_bot(
     State(state): State<Arc<AppState>>,
     Json(mut config): Json<BotConfig>,
@@ -21,6 +54,10 @@ pub async fn create_bot(
     // Generate a new UUID for the bot if not provided
     config.id = Uuid::new_v4().to_string();
 
+    if let Err(e) = validate_config(&config) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response();
+    }
+
     match dao::bot::create_bot(&state.db.clone(), config.clone()).await {
         Ok(bot_id) => {
             tracing::debug!("Bot {} saved to database", bot_id);
@@ -31,61 +68,205 @@ pub async fn create_bot(
             tracing::info!("Bot {} created", bot_id);
             (StatusCode::CREATED, Json(config)).into_response()
         }
-        Err(e) => {
-            tracing::error!("Failed to create bot in database: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create bot in database",
-            )
-                .into_response()
-        }
+        Err(e) => database_error(e, "create bot in database"),
+    }
+}
+
+/// Save the new config of a bot, a running bot is restarted with it
+async fn apply_config(state: &Arc<AppState>, config: BotConfig) -> response::Response {
+    if let Err(e) = validate_config(&config) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response();
+    }
+    match dao::bot::update_bot(&state.db, &config).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(),
+        Err(e) => return database_error(e, "update bot in database"),
+    }
+
+    let mut bot_manager = state.bot_manager.lock().await;
+    let is_running = bot_manager.is_running(&config.id);
+    if is_running {
+        state.journal.run_stopped(Some(&config.id), "updated").await;
+        bot_manager.create_bot(config.clone(), state.clone()).await;
+    } else if let Some(bot) = bot_manager.bots.get_mut(&config.id) {
+        bot.config = config.clone();
+    }
+    tracing::info!("Bot {} updated", config.id);
+    let info = match bot_manager.bots.get(&config.id) {
+        Some(bot) => BotInfo::from(bot),
+        None => BotInfo {
+            config,
+            is_running,
+            health: BotHealth::default(),
+        },
+    };
+    Json(info).into_response()
+}
+
+/// Replace the config of a bot
+pub async fn update_bot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(mut config): Json<BotConfig>,
+) -> impl IntoResponse {
+    config.id = id;
+    apply_config(&state, config).await
+}
+
+/// Change some fields of the config of a bot, the body is a JSON merge patch
+pub async fn patch_bot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(patch): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    let bot = match find_bot(&state, &id).await {
+        Ok(Some(bot)) => bot,
+        Ok(None) => return not_found(),
+        Err(e) => return database_error(e, "get bot from database"),
+    };
+    match bot.config.patch(patch) {
+        Ok(config) => apply_config(&state, config).await,
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, format!("Invalid config: {}", e)).into_response(),
     }
 }
 
+/// Start a bot with its saved config, it is started again on startup
+async fn run_bot(state: &Arc<AppState>, bot_manager: &mut BotManager, id: &str) -> response::Response {
+    let config = match dao::bot::get_bot(&state.db, id).await {
+        Ok(Some(bot)) => bot.config,
+        Ok(None) => return not_found(),
+        Err(e) => return database_error(e, "get bot from database"),
+    };
+    if let Err(e) = dao::bot::set_bot_running(&state.db, id, true).await {
+        return database_error(e, "start bot in database");
+    }
+    bot_manager.create_bot(config.clone(), state.clone()).await;
+    tracing::info!("Bot {} started", id);
+    Json(BotInfo::from(&bot_manager.bots[id])).into_response()
+}
+
+pub async fn start_bot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let mut bot_manager = state.bot_manager.lock().await;
+    if bot_manager.is_running(&id) {
+        return (StatusCode::CONFLICT, "Bot already running").into_response();
+    }
+    run_bot(&state, &mut bot_manager, &id).await
+}
+
+/// Stop a bot if running and start it again, with the config saved since
+pub async fn restart_bot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let mut bot_manager = state.bot_manager.lock().await;
+    if bot_manager.is_running(&id) {
+        state.journal.run_stopped(Some(&id), "restarted").await;
+    }
+    run_bot(&state, &mut bot_manager, &id).await
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StopParams {
+    /// What to do with the orders and positions of the bot, as its config
+    /// says when none
+    pub drain: Option<Drain>,
+}
+
+/// Stop a bot once the bars it handles are, then wind down its orders and
+/// positions
 pub async fn stop_bot(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    Query(params): Query<StopParams>,
 ) -> impl IntoResponse {
     let mut bot_manager = state.bot_manager.lock().await;
 
-    let id =  dao::bot::kill_bot(&state.db.clone(), id).await.unwrap_or("Bot not stopped".to_string());
+    match dao::bot::set_bot_running(&state.db, &id, false).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(),
+        Err(e) => return database_error(e, "stop bot in database"),
+    }
 
-    bot_manager.stop_bot(&id).await;
-    StatusCode::OK
+    bot_manager.stop_bot(&id, params.drain).await;
+    state.journal.run_stopped(Some(&id), "stopped").await;
+    StatusCode::OK.into_response()
 }
 
+/// Stop a bot and archive it, its orders, signals and runs are kept
 pub async fn remove_bot(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    Query(params): Query<StopParams>,
 ) -> impl IntoResponse {
     let mut bot_manager = state.bot_manager.lock().await;
-    bot_manager.remove_bot(&id).await;
-    StatusCode::OK
+    let archived = match dao::bot::archive_bot(&state.db, &id).await {
+        Ok(archived) => archived,
+        Err(e) => return database_error(e, "archive bot in database"),
+    };
+    if !archived && !bot_manager.bots.contains_key(&id) {
+        return not_found();
+    }
+
+    bot_manager.remove_bot(&id, params.drain).await;
+    state.journal.run_stopped(Some(&id), "removed").await;
+    StatusCode::OK.into_response()
+}
+
+/// Bot loaded in the manager, or saved in the database when it is not
+async fn find_bot(state: &AppState, id: &str) -> Result<Option<BotInfo>, Error> {
+    if let Some(bot) = state.bot_manager.lock().await.bots.get(id) {
+        return Ok(Some(BotInfo::from(bot)));
+    }
+    let bot = dao::bot::get_bot(&state.db, id).await?;
+    // not loaded, not running
+    Ok(bot.map(|bot| BotInfo {
+        is_running: false,
+        ..bot
+    }))
 }
 
 pub async fn get_bot(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> impl IntoResponse {
-    let manager = state.bot_manager.lock().await;
-
-    match manager.bots.get(&id) {
-        Some(bot) => {
-            let bot_info = BotInfo::from(bot);
-            Json(bot_info).into_response()
-        }
-        None => (StatusCode::NOT_FOUND, "Bot not Found").into_response(),
+    match find_bot(&state, &id).await {
+        Ok(Some(bot_info)) => Json(bot_info).into_response(),
+        Ok(None) => not_found(),
+        Err(e) => database_error(e, "get bot from database"),
     }
 }
 
+/// Bots saved in the database, the ones loaded in the manager with their
+/// current state
 pub async fn get_bots(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let saved = dao::bot::get_bots(&state.db).await;
     let manager = state.bot_manager.lock().await;
     tracing::info!("getting bots");
-    let bot_infos: HashMap<String, BotInfo> = manager
-        .bots
-        .iter()
-        .map(|(key, bot)| (key.clone(), BotInfo::from(bot)))
-        .collect();
+    let mut bot_infos: HashMap<String, BotInfo> = match saved {
+        Ok(bots) => bots
+            .into_iter()
+            .map(|bot| {
+                let info = BotInfo {
+                    is_running: false,
+                    ..bot
+                };
+                (info.config.id.clone(), info)
+            })
+            .collect(),
+        Err(e) => {
+            tracing::error!("Failed to get bots from database: {:?}", e);
+            HashMap::new()
+        }
+    };
+    bot_infos.extend(
+        manager
+            .bots
+            .iter()
+            .map(|(key, bot)| (key.clone(), BotInfo::from(bot))),
+    );
 
     // len bot
     let n_bots = bot_infos.clone().len();
@@ -93,3 +274,76 @@ pub async fn get_bots(State(state): State<Arc<AppState>>) -> impl IntoResponse {
 
     Json(bot_infos)
 }
+
+/// Virtual book of a bot, its cash, positions and profit from its fills
+pub async fn get_bot_portfolio(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.allocator.portfolio(&id) {
+        Some(portfolio) => Json(portfolio).into_response(),
+        None => (StatusCode::NOT_FOUND, "Bot not Found").into_response(),
+    }
+}
+
+/// Number of records returned by default by the journal endpoints
+const JOURNAL_LIMIT: i64 = 100;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JournalParams {
+    /// Number of records, newest first, up to 500
+    pub limit: Option<i64>,
+}
+
+impl JournalParams {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(JOURNAL_LIMIT).clamp(1, 500)
+    }
+}
+
+/// Response of a journal query
+fn journal_response<T: serde::Serialize>(records: Result<Vec<T>, Error>) -> response::Response {
+    match records {
+        Ok(records) => Json(records).into_response(),
+        Err(e) => {
+            tracing::error!("Cannot read the journal: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read the journal").into_response()
+        }
+    }
+}
+
+/// Recorded orders of a bot
+pub async fn get_bot_orders(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<JournalParams>,
+) -> impl IntoResponse {
+    journal_response(dao::order::get_bot_orders(&state.db, &id, params.limit()).await)
+}
+
+/// Recorded fills of a bot
+pub async fn get_bot_fills(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<JournalParams>,
+) -> impl IntoResponse {
+    journal_response(dao::fill::get_bot_fills(&state.db, &id, Some(params.limit())).await)
+}
+
+/// Recorded signals of a bot, with what became of them
+pub async fn get_bot_signals(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<JournalParams>,
+) -> impl IntoResponse {
+    journal_response(dao::signal::get_bot_signals(&state.db, &id, params.limit()).await)
+}
+
+/// Recorded runs of a bot
+pub async fn get_bot_runs(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<JournalParams>,
+) -> impl IntoResponse {
+    journal_response(dao::bot_run::get_bot_runs(&state.db, &id, params.limit()).await)
+}
@@ -0,0 +1,263 @@
+use crate::base::AppState;
+use crate::core::halt::{Halt, HaltTrigger};
+use crate::dao::halt::{delete_halt, get_halts as get_saved_halts, save_halt};
+use crate::error::RequestError;
+use crate::handlers::order::cancel_open_orders;
+use crate::models::order::{bot_client_order_id, CancelOrdersParams, Order, OrderCancellation};
+use crate::models::position::PositionClosure;
+use crate::models::trade::{Side, TimeInForce, Type};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{response, Json};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, instrument};
+
+/// Interval of the drawdown checks
+const DRAWDOWN_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Halt of the trading of a bot, of the whole account when `bot_id` is none
+#[derive(Debug, Default, Deserialize)]
+pub struct HaltRequest {
+    pub bot_id: Option<String>,
+    pub reason: Option<String>,
+    /// Cancel the open orders
+    #[serde(default)]
+    pub cancel_orders: bool,
+    /// Close the positions
+    #[serde(default)]
+    pub flatten: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResumeRequest {
+    pub bot_id: Option<String>,
+}
+
+/// Halt in place with the orders canceled and the positions closed by it
+#[derive(Debug, Serialize)]
+pub struct HaltReport {
+    pub halt: Halt,
+    pub canceled: Vec<OrderCancellation>,
+    pub closed: Vec<PositionClosure>,
+    /// Failures while winding down, the halt is in place anyway
+    pub errors: Vec<String>,
+}
+
+/// Record and persist `halt`, then cancel the open orders and close the
+/// positions of its scope when asked. The positions of a bot are the ones of
+/// its book.
+pub async fn halt_trading(
+    state: &AppState,
+    halt: Halt,
+    cancel_orders: bool,
+    flatten: bool,
+) -> HaltReport {
+    state.halt.halt(halt.clone());
+    let mut errors = vec![];
+    if let Err(e) = save_halt(&state.db, &halt).await {
+        error!("Cannot persist the trading halt: {}", e);
+        errors.push(format!("halt not persisted: {}", e));
+    }
+
+    let mut canceled = vec![];
+    if cancel_orders {
+        let params = CancelOrdersParams {
+            bot_id: halt.bot_id.clone(),
+            ..CancelOrdersParams::default()
+        };
+        match cancel_open_orders(state, &params).await {
+            Ok(cancellations) => canceled = cancellations,
+            Err(e) => {
+                error!("Cannot cancel the open orders of the halt: {}", e);
+                errors.push(format!("orders not canceled: {}", e));
+            }
+        }
+    }
+
+    let mut closed = vec![];
+    if flatten {
+        let closing = match &halt.bot_id {
+            None => state.broker.close_all_positions(false).await,
+            Some(bot_id) => close_bot_positions(state, bot_id).await,
+        };
+        match closing {
+            Ok(closures) => closed = closures,
+            Err(e) => {
+                error!("Cannot close the positions of the halt: {}", e);
+                errors.push(format!("positions not closed: {}", e));
+            }
+        }
+    }
+
+    HaltReport {
+        halt,
+        canceled,
+        closed,
+        errors,
+    }
+}
+
+/// Close the positions of the book of a bot. Each is closed up to the
+/// quantity the account holds in the same direction, the holdings of the
+/// other bots in the symbol are kept.
+pub async fn close_bot_positions(
+    state: &AppState,
+    bot_id: &str,
+) -> Result<Vec<PositionClosure>, RequestError> {
+    let booked = match state.allocator.portfolio(bot_id) {
+        Some(portfolio) => portfolio.positions,
+        None => return Ok(vec![]),
+    };
+    if booked.iter().all(|booked| booked.qty.is_zero()) {
+        return Ok(vec![]);
+    }
+    let positions = state.broker.get_positions().await?;
+
+    let mut closures = vec![];
+    for booked in booked.iter().filter(|booked| !booked.qty.is_zero()) {
+        let Some(position) = positions
+            .iter()
+            .find(|position| position.symbol.replace('/', "") == booked.symbol.replace('/', ""))
+        else {
+            continue;
+        };
+        if position.qty.is_negative() != booked.qty.is_negative() {
+            continue;
+        }
+        // a market order of the bot, so that its fills are booked to it
+        let close = Order {
+            symbol: booked.symbol.clone(),
+            qty: Some(abs(&booked.qty).min(abs(&position.qty))),
+            side: if booked.qty.is_negative() {
+                Side::Buy
+            } else {
+                Side::Sell
+            },
+            order_type: Type::Market,
+            time_in_force: TimeInForce::Day,
+            client_order_id: Some(bot_client_order_id(bot_id)),
+            ..Order::default()
+        };
+        let closure = match state.broker.submit_order(&close).await {
+            Ok(order) => {
+                state.journal.order(&order).await;
+                PositionClosure {
+                    symbol: position.symbol.clone(),
+                    status: StatusCode::OK.as_u16(),
+                    order: Some(order),
+                }
+            }
+            Err(e) => {
+                error!("Cannot close position in {}: {}", position.symbol, e);
+                PositionClosure {
+                    symbol: position.symbol.clone(),
+                    status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    order: None,
+                }
+            }
+        };
+        closures.push(closure);
+    }
+    Ok(closures)
+}
+
+fn abs(value: &Num) -> Num {
+    if value.is_negative() {
+        -value
+    } else {
+        value.clone()
+    }
+}
+
+/// Wind down the trading of a halt tripped automatically, as configured
+pub async fn trip(state: &AppState, halt: Halt) {
+    let config = state.halt.config();
+    let report = halt_trading(state, halt, config.cancel_orders, config.flatten).await;
+    info!(
+        "Automatic halt canceled {} orders and closed {} positions",
+        report.canceled.len(),
+        report.closed.len()
+    );
+}
+
+/// Restore the halts persisted before a restart
+pub async fn restore_halts(state: &AppState) {
+    match get_saved_halts(&state.db).await {
+        Ok(halts) => state.halt.restore(halts),
+        Err(e) => error!("Cannot load the trading halts: {}", e),
+    }
+}
+
+/// Halt the account when its drawdown goes beyond the limit, until the
+/// task is aborted
+pub async fn watch_drawdown(state: Arc<AppState>) {
+    if state.halt.config().max_drawdown.is_none() {
+        return;
+    }
+    let mut interval = tokio::time::interval(DRAWDOWN_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let account = match state.broker.get_account().await {
+            Ok(account) => account,
+            Err(e) => {
+                error!("Cannot check the drawdown: {}", e);
+                continue;
+            }
+        };
+        if let Some(halt) = state.halt.check_drawdown(&account, chrono::Utc::now()) {
+            trip(&state, halt).await;
+        }
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn get_halts(State(state): State<Arc<AppState>>) -> response::Response {
+    (StatusCode::OK, Json(state.halt.halts())).into_response()
+}
+
+/// Halt the trading of a bot, or of the whole account
+#[instrument(skip(state))]
+pub async fn halt(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<HaltRequest>,
+) -> response::Response {
+    let halt = Halt::new(
+        request.bot_id.as_deref(),
+        HaltTrigger::Manual,
+        request
+            .reason
+            .unwrap_or_else(|| "halted manually".to_string()),
+    );
+    let report = halt_trading(&state, halt, request.cancel_orders, request.flatten).await;
+    (StatusCode::OK, Json(report)).into_response()
+}
+
+/// Resume the trading of a bot, or of the whole account
+#[instrument(skip(state))]
+pub async fn resume(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ResumeRequest>,
+) -> response::Response {
+    let bot_id = request.bot_id.as_deref();
+    if let Err(e) = delete_halt(&state.db, bot_id).await {
+        error!("Cannot delete the trading halt: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to resume trading"})),
+        )
+            .into_response();
+    }
+    match state.halt.resume(bot_id) {
+        Some(halt) => (StatusCode::OK, Json(halt)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Trading is not halted"})),
+        )
+            .into_response(),
+    }
+}
@@ -1,39 +1,16 @@
-use crate::base::AppState;
 use crate::error::RequestError;
-use axum::body::Body;
-use axum::http::{Method, StatusCode};
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde::de::DeserializeOwned;
-use traidano::RequestType;
 
 pub mod account;
+pub mod backtest;
 pub mod bar;
 pub mod bot;
+pub mod halt;
 pub mod market;
 pub mod order;
-
-pub async fn rate_limited_request<T>(
-    app_state: &AppState,
-    method: Method,
-    path: &str,
-    body: Body,
-    request_type: RequestType,
-) -> Result<T, RequestError>
-where
-    T: DeserializeOwned,
-{
-    // Acquire the rate limiter lock
-    let mut guard = app_state.rate_limiter.lock().await;
-    guard.acquire().await;
-
-    drop(guard);
-
-    // Send the request using the Alpaca client
-    app_state
-        .alpaca_client
-        .send::<T>(method, path, body, request_type)
-        .await
-}
+pub mod position;
+pub mod strategy;
 
 impl IntoResponse for RequestError {
     fn into_response(self) -> Response {
@@ -42,6 +19,10 @@ impl IntoResponse for RequestError {
             RequestError::HttpBuild(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             RequestError::Json(e) => (StatusCode::BAD_REQUEST, e.to_string()),
             RequestError::ApiError(status) => (status, "API Error".to_string()),
+            RequestError::MalformedResponse(e) => (StatusCode::BAD_GATEWAY, e.to_string()),
+            RequestError::InvalidOrder(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+            RequestError::Risk(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+            RequestError::Halted(reason) => (StatusCode::LOCKED, reason),
             // Add other variants as needed
             _ => (StatusCode::NOT_FOUND, "NotFound".to_string()),
         };
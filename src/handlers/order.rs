@@ -1,15 +1,173 @@
 use crate::base::AppState;
-use crate::models::order::{Order, OrderParams};
-use axum::body::Body;
-use axum::extract::{Query, State};
-use axum::http::{Method, StatusCode};
+use crate::bot::BotConfig;
+use crate::core::halt::OrderOutcome;
+use crate::dao::bot::get_bot;
+use crate::dao::order::get_order_events as get_events;
+use crate::error::RequestError;
+use crate::handlers::halt::trip;
+use crate::models::order::{
+    client_order_bot_id, BrokerOrder, CancelOrdersParams, Order, OrderCancellation, OrderParams,
+    ReplaceOrder,
+};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::{response, Json};
 use axum_macros::debug_handler;
 use serde_json::json;
 use std::sync::Arc;
 use tracing::{error, info, instrument};
-use traidano::RequestType;
+
+/// Round the order to the increments of its asset, validate it, run the risk
+/// checks and submit it to the broker, used by the api and the bots. The
+/// order is recorded and the cash of the orders of a bot is reserved in its
+/// book.
+///
+/// Orders are refused while the trading of `bot` is halted, and their
+/// outcome may trip an automatic halt.
+pub async fn place_order(
+    state: &AppState,
+    order: &Order,
+    bot: Option<&BotConfig>,
+) -> Result<BrokerOrder, RequestError> {
+    let bot_id = bot.map(|bot| bot.id.as_str());
+    if let Some(halt) = state.halt.halted(bot_id) {
+        return Err(RequestError::Halted(halt.reason));
+    }
+
+    let placed = submit_checked_order(state, order, bot).await;
+    if let Some(halt) = state.halt.record(bot_id, OrderOutcome::of(&placed)) {
+        trip(state, halt).await;
+    }
+    placed
+}
+
+async fn submit_checked_order(
+    state: &AppState,
+    order: &Order,
+    bot: Option<&BotConfig>,
+) -> Result<BrokerOrder, RequestError> {
+    let asset = state.broker.get_asset(&order.symbol).await?;
+    let order = order.round_to(&asset)?;
+    order.validate()?;
+    state
+        .risk
+        .check(state.broker.as_ref(), &state.allocator, &order, bot)
+        .await?;
+    let placed = state.broker.submit_order(&order).await?;
+    state.risk.record_submitted();
+    state.journal.order(&placed).await;
+    if let Some(bot) = bot {
+        state.allocator.reserve(&bot.id, &placed.id, &order);
+    }
+    Ok(placed)
+}
+
+/// Round the changes to the increments of the asset of the order, validate
+/// the resulting order and run it through the risk checks, then replace it.
+/// The new order is returned.
+pub async fn change_order(
+    state: &AppState,
+    order_id: &str,
+    replace: &ReplaceOrder,
+) -> Result<BrokerOrder, RequestError> {
+    replace.validate()?;
+    let order = state.broker.get_order(order_id).await?;
+    let bot_id = order
+        .client_order_id
+        .as_deref()
+        .and_then(client_order_bot_id);
+    if let Some(halt) = state.halt.halted(bot_id) {
+        return Err(RequestError::Halted(halt.reason));
+    }
+    let bot = match bot_id {
+        Some(bot_id) => get_bot(&state.db, bot_id).await?.map(|bot| bot.config),
+        None => None,
+    };
+    let asset = state.broker.get_asset(&order.symbol).await?;
+    let replace = replace.round_to(&asset, &order.side);
+    let changed = replace.apply(&Order::from(&order));
+    changed.validate()?;
+    state
+        .risk
+        .check(
+            state.broker.as_ref(),
+            &state.allocator,
+            &changed,
+            bot.as_ref(),
+        )
+        .await?;
+    let replaced = state.broker.replace_order(order_id, &replace).await?;
+    state.risk.record_submitted();
+    state.journal.order(&replaced).await;
+    if let Some(bot) = &bot {
+        state.allocator.reserve(&bot.id, &replaced.id, &changed);
+    }
+    Ok(replaced)
+}
+
+/// Cancel the open orders matching `params`, every open order when empty
+pub async fn cancel_open_orders(
+    state: &AppState,
+    params: &CancelOrdersParams,
+) -> Result<Vec<OrderCancellation>, RequestError> {
+    if params.is_empty() {
+        return state.broker.cancel_all_orders().await;
+    }
+
+    let open = state.broker.list_open_orders(params.symbol.clone()).await?;
+
+    let mut canceled = vec![];
+    for order in open.iter().filter(|order| params.matches(order)) {
+        let status = match state.broker.cancel_order(&order.id).await {
+            Ok(()) => StatusCode::OK,
+            Err(RequestError::ApiError(status)) => status,
+            Err(e) => {
+                error!("Error canceling order {}: {:?}", order.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        canceled.push(OrderCancellation {
+            id: order.id.clone(),
+            status: status.as_u16(),
+        });
+    }
+    Ok(canceled)
+}
+
+/// Response of a failed order request, the broker status is kept for the
+/// missing and not open orders
+fn order_error(e: RequestError, action: &str) -> response::Response {
+    let (status, message) = match e {
+        RequestError::Risk(rejection) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": rejection.message, "reason": rejection.reason })),
+            )
+                .into_response();
+        }
+        RequestError::InvalidOrder(e) => {
+            info!("Order rejected: {}", e);
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        RequestError::Halted(reason) => (StatusCode::LOCKED, format!("Trading halted: {}", reason)),
+        RequestError::ApiError(StatusCode::NOT_FOUND) => {
+            (StatusCode::NOT_FOUND, "Order not found".to_string())
+        }
+        RequestError::ApiError(StatusCode::UNPROCESSABLE_ENTITY) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Order is not open".to_string(),
+        ),
+        e => {
+            error!("Error trying to {}: {:?}", action, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to {}", action),
+            )
+        }
+    };
+    (status, Json(json!({ "error": message }))).into_response()
+}
 
 #[instrument(skip(state))]
 #[debug_handler]
@@ -19,28 +177,78 @@ pub async fn create_order(
 ) -> response::Response {
     info!("receive '{:?}' order", &request.side);
 
-    match state
-        .alpaca_client
-        .send::<serde_json::Value>(
-            Method::POST,
-            "orders",
-            Body::from(serde_json::to_string(&request).unwrap()),
-            RequestType::Order,
-        )
-        .await
-    {
+    match place_order(&state, &request, None).await {
         Ok(response) => {
             info!("order created");
             (StatusCode::OK, Json(response)).into_response()
         }
-        Err(e) => {
-            error!("Error creating order: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to create order"})),
-            )
-                .into_response()
+        Err(e) => order_error(e, "create order"),
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn get_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> response::Response {
+    match state.broker.get_order(&id).await {
+        Ok(order) => (StatusCode::OK, Json(order)).into_response(),
+        Err(e) => order_error(e, "get order"),
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn get_order_by_client_id(
+    State(state): State<Arc<AppState>>,
+    Path(client_order_id): Path<String>,
+) -> response::Response {
+    match state.broker.get_order_by_client_id(&client_order_id).await {
+        Ok(order) => (StatusCode::OK, Json(order)).into_response(),
+        Err(e) => order_error(e, "get order"),
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn replace_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<ReplaceOrder>,
+) -> response::Response {
+    match change_order(&state, &id, &request).await {
+        Ok(order) => {
+            info!("order {} replaced by {}", id, order.id);
+            (StatusCode::OK, Json(order)).into_response()
         }
+        Err(e) => order_error(e, "replace order"),
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn cancel_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> response::Response {
+    match state.broker.cancel_order(&id).await {
+        Ok(()) => {
+            info!("order {} canceled", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => order_error(e, "cancel order"),
+    }
+}
+
+/// Cancel the open orders, of a symbol or a bot when given
+#[instrument(skip(state))]
+pub async fn cancel_orders(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CancelOrdersParams>,
+) -> response::Response {
+    match cancel_open_orders(&state, &params).await {
+        Ok(canceled) => {
+            info!("{} orders canceled", canceled.len());
+            (StatusCode::MULTI_STATUS, Json(canceled)).into_response()
+        }
+        Err(e) => order_error(e, "cancel orders"),
     }
 }
 
@@ -57,28 +265,40 @@ pub async fn get_all_order(
 ) -> response::Response {
     info!("get all order");
 
-    let mut url_query: String = "orders?".to_string();
-    url_query.push_str(&params.query());
-
-    match state
-        .alpaca_client
-        .send::<serde_json::Value>(
-            Method::GET,
-            url_query.as_str(),
-            Body::empty(),
-            RequestType::Order,
-        )
-        .await
-    {
+    match state.broker.list_orders(&params).await {
         Ok(response) => {
-            info!("order created");
+            info!("orders retrieved");
             (StatusCode::OK, Json(response)).into_response()
         }
         Err(e) => {
-            error!("Error creating order: {:?}", e);
+            error!("Error getting orders: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to get orders"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Lifecycle transitions of an order, oldest first
+#[instrument(skip(state))]
+pub async fn get_order_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> response::Response {
+    match get_events(&state.db, &id).await {
+        Ok(events) if events.is_empty() => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Order not found"})),
+        )
+            .into_response(),
+        Ok(events) => (StatusCode::OK, Json(events)).into_response(),
+        Err(e) => {
+            error!("Error getting order events: {:?}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to create order"})),
+                Json(json!({"error": "Failed to get order events"})),
             )
                 .into_response()
         }
@@ -0,0 +1,92 @@
+use crate::base::AppState;
+use crate::error::RequestError;
+use crate::models::position::{ClosePosition, ClosePositionsParams};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{response, Json};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info, instrument, warn};
+
+/// Response of a failed position request
+fn position_error(e: RequestError, action: &str) -> response::Response {
+    let (status, message) = match e {
+        RequestError::InvalidOrder(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+        RequestError::ApiError(StatusCode::NOT_FOUND) => {
+            (StatusCode::NOT_FOUND, "Position not found".to_string())
+        }
+        e => {
+            error!("Error trying to {}: {:?}", action, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to {}", action),
+            )
+        }
+    };
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+#[instrument(skip(state))]
+pub async fn get_positions(State(state): State<Arc<AppState>>) -> response::Response {
+    match state.broker.get_positions().await {
+        Ok(positions) => (StatusCode::OK, Json(positions)).into_response(),
+        Err(e) => position_error(e, "get positions"),
+    }
+}
+
+/// Position in a symbol, crypto pairs are given without the slash or with
+/// it encoded (`BTCUSD` or `BTC%2FUSD`)
+#[instrument(skip(state))]
+pub async fn get_position(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> response::Response {
+    match state.broker.get_position(&symbol).await {
+        Ok(position) => (StatusCode::OK, Json(position)).into_response(),
+        Err(e) => position_error(e, "get position"),
+    }
+}
+
+/// Close a position with a market order, only `qty` or `percentage` of it
+/// when given
+#[instrument(skip(state))]
+pub async fn close_position(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<ClosePosition>,
+) -> response::Response {
+    if let Err(e) = params.validate() {
+        return position_error(e.into(), "close position");
+    }
+    match state.broker.close_position(&symbol, &params).await {
+        Ok(order) => {
+            info!("closing position in {} with order {}", symbol, order.id);
+            (StatusCode::OK, Json(order)).into_response()
+        }
+        Err(e) => position_error(e, "close position"),
+    }
+}
+
+/// Close every position, the open orders are canceled first unless
+/// `cancel_orders=false`
+#[instrument(skip(state))]
+pub async fn close_positions(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ClosePositionsParams>,
+) -> response::Response {
+    let cancel_orders = params.cancel_orders.unwrap_or(true);
+    match state.broker.close_all_positions(cancel_orders).await {
+        Ok(closures) => {
+            for closure in closures.iter().filter(|closure| closure.order.is_none()) {
+                warn!(
+                    "position in {} not closed, status {}",
+                    closure.symbol, closure.status
+                );
+            }
+            info!("closing {} positions", closures.len());
+            (StatusCode::MULTI_STATUS, Json(closures)).into_response()
+        }
+        Err(e) => position_error(e, "close positions"),
+    }
+}
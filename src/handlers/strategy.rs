@@ -0,0 +1,8 @@
+use crate::bot::strategies::strategy_schemas;
+use axum::response::IntoResponse;
+use axum::Json;
+
+/// Strategies a bot can run, with the JSON schemas of their parameters
+pub async fn get_strategies() -> impl IntoResponse {
+    Json(strategy_schemas())
+}
@@ -105,6 +105,7 @@ impl From<OrderError> for StatusCode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RequestType {
     StockData,
     CryptoData,
@@ -1,11 +1,12 @@
+use num_decimal::Num;
 use serde::{Deserialize, Serialize};
-use serde_this_or_that::as_f64;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Account {
     pub id: String,
-    #[serde(deserialize_with = "as_f64")]
-    pub equity: f64,
-    #[serde(deserialize_with = "as_f64")]
-    pub buying_power: f64,
+    pub equity: Num,
+    pub buying_power: Num,
+    /// Equity at the close of the previous trading day
+    #[serde(default)]
+    pub last_equity: Option<Num>,
 }
@@ -0,0 +1,153 @@
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+
+/// Smallest fraction of a share of a fractionable equity
+const FRACTIONAL_LOT: (i32, i32) = (1, 1_000_000_000);
+
+/// Instrument traded by the broker, with the increments its orders must respect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    pub id: String,
+    pub symbol: String,
+    #[serde(rename = "class")]
+    pub asset_class: String,
+    pub exchange: String,
+    pub tradable: bool,
+    #[serde(default)]
+    pub fractionable: bool,
+    /// Smallest quantity of an order
+    #[serde(default)]
+    pub min_order_size: Option<Num>,
+    /// Quantity step of an order
+    #[serde(default)]
+    pub min_trade_increment: Option<Num>,
+    /// Price step of an order
+    #[serde(default)]
+    pub price_increment: Option<Num>,
+}
+
+impl Asset {
+    /// Asset without metadata, prices and quantities are kept as they are
+    /// except for the equity tick size
+    pub fn unknown(symbol: &str) -> Self {
+        let crypto = symbol.contains('/');
+        Self {
+            id: symbol.to_string(),
+            symbol: symbol.to_string(),
+            asset_class: if crypto { "crypto" } else { "us_equity" }.to_string(),
+            exchange: String::new(),
+            tradable: true,
+            fractionable: true,
+            min_order_size: None,
+            min_trade_increment: None,
+            price_increment: None,
+        }
+    }
+
+    pub fn is_crypto(&self) -> bool {
+        self.asset_class == "crypto"
+    }
+
+    /// Price step at `price`, equities trade by the cent above one dollar
+    /// and by the hundredth of a cent below
+    pub fn tick_size(&self, price: &Num) -> Option<Num> {
+        if let Some(increment) = &self.price_increment {
+            return Some(increment.clone());
+        }
+        if self.is_crypto() {
+            return None;
+        }
+        if *price >= Num::from(1) {
+            Some(Num::new(1, 100))
+        } else {
+            Some(Num::new(1, 10_000))
+        }
+    }
+
+    /// Quantity step of an order
+    pub fn lot_size(&self) -> Option<Num> {
+        if let Some(increment) = &self.min_trade_increment {
+            return Some(increment.clone());
+        }
+        match (self.is_crypto(), self.fractionable) {
+            (true, _) => None,
+            (false, true) => Some(Num::new(FRACTIONAL_LOT.0, FRACTIONAL_LOT.1)),
+            (false, false) => Some(Num::from(1)),
+        }
+    }
+}
+
+/// Largest multiple of `step` not above `value`
+pub fn round_down(value: &Num, step: &Num) -> Num {
+    let steps = (value / step).trunc();
+    let rounded = &steps * step;
+    if rounded > *value {
+        (steps - 1) * step
+    } else {
+        rounded
+    }
+}
+
+/// Smallest multiple of `step` not below `value`
+pub fn round_up(value: &Num, step: &Num) -> Num {
+    let rounded = round_down(value, step);
+    if rounded < *value {
+        rounded + step
+    } else {
+        rounded
+    }
+}
+
+/// Multiple of `step` closest to `value`
+pub fn round_nearest(value: &Num, step: &Num) -> Num {
+    (value / step).round() * step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(value: &str) -> Num {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn round_to_steps() {
+        let step = num("0.05");
+        assert_eq!(round_down(&num("1.07"), &step), num("1.05"));
+        assert_eq!(round_up(&num("1.07"), &step), num("1.1"));
+        assert_eq!(round_up(&num("1.05"), &step), num("1.05"));
+        assert_eq!(round_nearest(&num("1.08"), &step), num("1.1"));
+        assert_eq!(round_down(&num("-1.07"), &step), num("-1.1"));
+    }
+
+    #[test]
+    fn increments_of_the_asset_class() {
+        let equity = Asset {
+            fractionable: false,
+            ..Asset::unknown("AAPL")
+        };
+        assert_eq!(equity.tick_size(&num("150")), Some(num("0.01")));
+        assert_eq!(equity.tick_size(&num("0.5")), Some(num("0.0001")));
+        assert_eq!(equity.lot_size(), Some(num("1")));
+
+        let crypto: Asset = serde_json::from_str(
+            r#"{
+                "id": "64bbff51-59d6-4b3c-9351-13ad85e3c752",
+                "class": "crypto",
+                "exchange": "CRYPTO",
+                "symbol": "BTC/USD",
+                "name": "Bitcoin  / US Dollar",
+                "status": "active",
+                "tradable": true,
+                "fractionable": true,
+                "min_order_size": "0.0001",
+                "min_trade_increment": "0.000000001",
+                "price_increment": "1"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(crypto.tick_size(&num("60000")), Some(num("1")));
+        assert_eq!(crypto.lot_size(), Some(num("0.000000001")));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::models::timeframe::Timeframe;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,7 +10,7 @@ pub struct Bar {
     pub high_price: f64,
     #[serde(rename = "l")]
     pub low_price: f64,
-    #[serde(rename = "n")]
+    #[serde(rename = "n", default)]
     pub n: u32,
     #[serde(rename = "o")]
     pub open_price: f64,
@@ -16,9 +18,127 @@ pub struct Bar {
     pub timestamp: String,
     #[serde(rename = "v")]
     pub volume: f64,
-    #[serde(rename = "vw")]
+    #[serde(rename = "vw", default)]
     pub vw: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct BarQueryParams {}
+/// Source of the equity bars
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataFeed {
+    Iex,
+    Sip,
+    DelayedSip,
+    Otc,
+}
+
+impl DataFeed {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataFeed::Iex => "iex",
+            DataFeed::Sip => "sip",
+            DataFeed::DelayedSip => "delayed_sip",
+            DataFeed::Otc => "otc",
+        }
+    }
+}
+
+/// Corporate actions the equity bars are adjusted for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Adjustment {
+    Raw,
+    Split,
+    Dividend,
+    All,
+}
+
+impl Adjustment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Adjustment::Raw => "raw",
+            Adjustment::Split => "split",
+            Adjustment::Dividend => "dividend",
+            Adjustment::All => "all",
+        }
+    }
+}
+
+/// Historical bars to get, at most `limit` bars per symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BarQueryParams {
+    pub timeframe: Timeframe,
+    pub start: DateTime<Utc>,
+    /// Up to now when none
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+    pub limit: usize,
+    /// Feed of the equity bars, the one of the subscription when none
+    #[serde(default)]
+    pub feed: Option<DataFeed>,
+    /// Adjustment of the equity bars, raw when none
+    #[serde(default)]
+    pub adjustment: Option<Adjustment>,
+}
+
+impl BarQueryParams {
+    /// Latest `limit` bars since `start`
+    pub fn new(timeframe: Timeframe, start: DateTime<Utc>, limit: usize) -> Self {
+        Self {
+            timeframe,
+            start,
+            end: None,
+            limit,
+            feed: None,
+            adjustment: None,
+        }
+    }
+}
+
+/// Time range of a symbol and timeframe whose bars are all stored, from the
+/// first to the last stored bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarCoverage {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl BarCoverage {
+    /// Coverage once the bars of `fetched` are stored: both ranges when they
+    /// overlap, the latest one otherwise as the bars in between are missing
+    pub fn merge(self, fetched: BarCoverage) -> BarCoverage {
+        if fetched.start <= self.end && self.start <= fetched.end {
+            BarCoverage {
+                start: self.start.min(fetched.start),
+                end: self.end.max(fetched.end),
+            }
+        } else if fetched.end > self.end {
+            fetched
+        } else {
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage(start: u32, end: u32) -> BarCoverage {
+        let at = |hour| format!("2024-01-01T{:02}:00:00Z", hour).parse().unwrap();
+        BarCoverage {
+            start: at(start),
+            end: at(end),
+        }
+    }
+
+    #[test]
+    fn merge_coverage() {
+        assert_eq!(coverage(2, 5).merge(coverage(4, 8)), coverage(2, 8));
+        assert_eq!(coverage(2, 5).merge(coverage(0, 2)), coverage(0, 5));
+        assert_eq!(coverage(2, 5).merge(coverage(3, 4)), coverage(2, 5));
+        // a gap drops the older range
+        assert_eq!(coverage(2, 5).merge(coverage(7, 9)), coverage(7, 9));
+        assert_eq!(coverage(7, 9).merge(coverage(2, 5)), coverage(7, 9));
+    }
+}
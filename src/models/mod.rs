@@ -1,12 +1,30 @@
+use num_decimal::Num;
 use serde::{Deserialize, Serialize};
 
 pub mod account;
+pub mod asset;
 pub mod bar;
 pub mod order;
 pub mod position;
+pub mod signal;
+pub mod timeframe;
 pub mod trade;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Clock {
     pub is_open: bool,
 }
+
+/// Exact decimal of a float computed by the strategies, `None` for NaN and infinities
+pub fn num_from_f64(value: f64) -> Option<Num> {
+    if !value.is_finite() {
+        return None;
+    }
+    // the display of a float is the shortest decimal that reads back the same
+    value.to_string().parse().ok()
+}
+
+/// Float approximation of a decimal, for the indicators and the metrics
+pub fn num_to_f64(value: &Num) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
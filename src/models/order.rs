@@ -1,42 +1,668 @@
-use crate::models::trade::{Side, TimeInForce, Type};
+use crate::models::asset::{round_down, round_nearest, round_up, Asset};
+use crate::models::trade::{OrderClass, Side, TimeInForce, Type};
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
 use serde::{Deserialize, Serialize};
-use std::any::Any;
 use std::collections::HashMap;
-use tracing_subscriber::util::SubscriberInitExt;
+use std::fmt;
+use std::fmt::Formatter;
+use traidano::OrderError;
+use uuid::Uuid;
 
 pub enum AnyValue {
     U32(Option<u32>),
     Str(Option<String>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Qty {
-    Int(i32),
-    Float(f32),
+/// Limit exit of an order with legs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TakeProfit {
+    pub limit_price: Num,
 }
 
-impl Default for Qty {
-    fn default() -> Self {
-        Qty::Int(0)
-    }
+/// Stop exit of an order with legs, a stop limit when `limit_price` is set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StopLoss {
+    pub stop_price: Num,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<Num>,
 }
 
-// Todo: Add stop loss and take profit and order class
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Order {
     pub symbol: String,
-    pub qty: Option<Qty>,
-    pub national: Option<i32>,
+    pub qty: Option<Num>,
+    #[serde(rename = "notional", alias = "national")]
+    pub national: Option<Num>,
+    pub side: Side,
+    #[serde(rename = "type")]
+    pub order_type: Type,
+    pub time_in_force: TimeInForce,
+    pub limit_price: Option<Num>,
+    pub stop_price: Option<Num>,
+    pub trail_price: Option<Num>,
+    pub trail_percent: Option<Num>,
+    pub extended_hours: Option<bool>,
+    pub client_order_id: Option<String>,
+    #[serde(default)]
+    pub order_class: OrderClass,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub take_profit: Option<TakeProfit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_loss: Option<StopLoss>,
+}
+
+impl Order {
+    /// Side of the take profit and stop loss legs
+    pub fn exit_side(&self) -> Side {
+        match self.order_class {
+            // the legs of an oco order are the order itself
+            OrderClass::Oco => self.side.clone(),
+            _ => self.side.opposite(),
+        }
+    }
+
+    /// Reject inconsistent orders before they reach the broker
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if self.symbol.is_empty() {
+            return invalid("symbol is required");
+        }
+
+        match (&self.qty, &self.national) {
+            (Some(_), Some(_)) => return invalid("qty and notional are exclusive"),
+            (None, None) => return invalid("qty or notional is required"),
+            (Some(qty), None) if !qty.is_positive() => return invalid("qty must be positive"),
+            (None, Some(notional)) if !notional.is_positive() => {
+                return invalid("notional must be positive")
+            }
+            (None, Some(_))
+                if self.order_type != Type::Market
+                    || self.time_in_force != TimeInForce::Day
+                    || self.order_class != OrderClass::Simple =>
+            {
+                return invalid("notional orders must be simple day market orders")
+            }
+            _ => {}
+        }
+
+        let prices = [
+            &self.limit_price,
+            &self.stop_price,
+            &self.trail_price,
+            &self.trail_percent,
+        ];
+        if prices
+            .iter()
+            .copied()
+            .flatten()
+            .any(|price| !price.is_positive())
+        {
+            return invalid("prices must be positive");
+        }
+
+        let has_trail = self.trail_price.is_some() || self.trail_percent.is_some();
+        match self.order_type {
+            Type::Market if self.limit_price.is_some() || self.stop_price.is_some() => {
+                return invalid("market orders take no limit_price nor stop_price")
+            }
+            Type::Limit if self.limit_price.is_none() || self.stop_price.is_some() => {
+                return invalid("limit orders require a limit_price and no stop_price")
+            }
+            Type::Stop if self.stop_price.is_none() || self.limit_price.is_some() => {
+                return invalid("stop orders require a stop_price and no limit_price")
+            }
+            Type::StopLimit if self.stop_price.is_none() || self.limit_price.is_none() => {
+                return invalid("stop_limit orders require a stop_price and a limit_price")
+            }
+            Type::TrailingStop => {
+                if self.trail_price.is_some() == self.trail_percent.is_some() {
+                    return invalid(
+                        "trailing_stop orders require one of trail_price or trail_percent",
+                    );
+                }
+                if self.limit_price.is_some() || self.stop_price.is_some() {
+                    return invalid("trailing_stop orders take no limit_price nor stop_price");
+                }
+            }
+            _ if has_trail => {
+                return invalid("trail_price and trail_percent only apply to trailing_stop orders")
+            }
+            _ => {}
+        }
+
+        let market_or_limit = matches!(self.order_type, Type::Market | Type::Limit);
+        match self.time_in_force {
+            TimeInForce::OnOpen | TimeInForce::OnClose if !market_or_limit => {
+                return invalid("opg and cls only apply to market and limit orders")
+            }
+            TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill if !market_or_limit => {
+                return invalid("ioc and fok only apply to market and limit orders")
+            }
+            _ => {}
+        }
+
+        if self.extended_hours == Some(true)
+            && (self.order_type != Type::Limit
+                || self.time_in_force != TimeInForce::Day
+                || self.order_class != OrderClass::Simple)
+        {
+            return invalid("extended hours orders must be simple day limit orders");
+        }
+
+        self.validate_legs()
+    }
+
+    fn validate_legs(&self) -> Result<(), OrderError> {
+        let day_or_gtc = matches!(
+            self.time_in_force,
+            TimeInForce::Day | TimeInForce::GoodUntilCancel
+        );
+        match self.order_class {
+            OrderClass::Simple => {
+                if self.take_profit.is_some() || self.stop_loss.is_some() {
+                    return invalid(
+                        "take_profit and stop_loss require a bracket, oco or oto order",
+                    );
+                }
+                return Ok(());
+            }
+            OrderClass::Bracket if self.take_profit.is_none() || self.stop_loss.is_none() => {
+                return invalid("bracket orders require a take_profit and a stop_loss");
+            }
+            OrderClass::Oco if self.take_profit.is_none() || self.stop_loss.is_none() => {
+                return invalid("oco orders require a take_profit and a stop_loss");
+            }
+            OrderClass::Oco if self.order_type != Type::Limit => {
+                return invalid("oco orders must be limit orders");
+            }
+            OrderClass::Oto if self.take_profit.is_some() == self.stop_loss.is_some() => {
+                return invalid("oto orders require one of take_profit or stop_loss");
+            }
+            OrderClass::Bracket | OrderClass::Oto
+                if !matches!(self.order_type, Type::Market | Type::Limit) =>
+            {
+                return invalid("the entry of an order with legs must be a market or limit order");
+            }
+            _ => {}
+        }
+        if !day_or_gtc {
+            return invalid("orders with legs must be day or gtc");
+        }
+
+        let take_profit = self.take_profit.as_ref().map(|leg| &leg.limit_price);
+        let stop = self.stop_loss.as_ref().map(|leg| &leg.stop_price);
+        let stop_limit = self
+            .stop_loss
+            .as_ref()
+            .and_then(|leg| leg.limit_price.as_ref());
+        if [take_profit, stop, stop_limit]
+            .iter()
+            .flatten()
+            .any(|price| !price.is_positive())
+        {
+            return invalid("leg prices must be positive");
+        }
+
+        // a sell exit protects a long position: profit above, stop below
+        let long = self.exit_side() == Side::Sell;
+        let ordered = |high: &Num, low: &Num| if long { high > low } else { high < low };
+        if let (Some(take_profit), Some(stop)) = (take_profit, stop) {
+            if !ordered(take_profit, stop) {
+                return invalid("take_profit and stop_loss are on the wrong side of each other");
+            }
+        }
+        if let (Some(stop), Some(limit)) = (stop, stop_limit) {
+            if limit != stop && !ordered(stop, limit) {
+                return invalid("the stop_loss limit_price must not be better than its stop_price");
+            }
+        }
+        if let (Some(entry), false) = (&self.limit_price, self.order_class == OrderClass::Oco) {
+            if take_profit.is_some_and(|take_profit| !ordered(take_profit, entry))
+                || stop.is_some_and(|stop| !ordered(entry, stop))
+            {
+                return invalid(
+                    "take_profit and stop_loss must be on each side of the entry limit_price",
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Order {
+    /// Round the order to the increments of `asset`.
+    ///
+    /// Quantities are rounded down to the lot size, limit prices to the tick
+    /// on the passive side so the order never pays more than asked, stop
+    /// prices to the nearest tick.
+    pub fn round_to(&self, asset: &Asset) -> Result<Order, OrderError> {
+        if !asset.tradable {
+            return Err(OrderError::InvalidParameters(format!(
+                "{} is not tradable",
+                asset.symbol
+            )));
+        }
+
+        let mut order = self.clone();
+        if let (Some(qty), Some(lot)) = (&self.qty, asset.lot_size()) {
+            order.qty = Some(round_down(qty, &lot));
+        }
+        if let (Some(qty), Some(min)) = (&order.qty, &asset.min_order_size) {
+            if qty < min {
+                return Err(OrderError::InvalidParameters(format!(
+                    "qty {} is below the minimum order size {} of {}",
+                    qty, min, asset.symbol
+                )));
+            }
+        }
+
+        let limit = |price: &Num, side: &Side| limit_tick(asset, price, side);
+        let stop = |price: &Num| stop_tick(asset, price);
+        let exit_side = self.exit_side();
+
+        order.limit_price = self
+            .limit_price
+            .as_ref()
+            .map(|price| limit(price, &self.side));
+        order.stop_price = self.stop_price.as_ref().map(stop);
+        order.trail_price = self.trail_price.as_ref().map(stop);
+        if let Some(take_profit) = &mut order.take_profit {
+            take_profit.limit_price = limit(&take_profit.limit_price, &exit_side);
+        }
+        if let Some(stop_loss) = &mut order.stop_loss {
+            stop_loss.stop_price = stop(&stop_loss.stop_price);
+            stop_loss.limit_price = stop_loss
+                .limit_price
+                .as_ref()
+                .map(|price| limit(price, &exit_side));
+        }
+        Ok(order)
+    }
+}
+
+/// Limit price rounded to the tick of `asset` on the passive side
+fn limit_tick(asset: &Asset, price: &Num, side: &Side) -> Num {
+    match (asset.tick_size(price), side) {
+        (Some(tick), Side::Buy) => round_down(price, &tick),
+        (Some(tick), Side::Sell) => round_up(price, &tick),
+        (None, _) => price.clone(),
+    }
+}
+
+/// Stop price rounded to the nearest tick of `asset`
+fn stop_tick(asset: &Asset, price: &Num) -> Num {
+    match asset.tick_size(price) {
+        Some(tick) => round_nearest(price, &tick),
+        None => price.clone(),
+    }
+}
+
+/// Changes to an open order, the fields left out are kept
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReplaceOrder {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qty: Option<Num>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_in_force: Option<TimeInForce>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<Num>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_price: Option<Num>,
+    /// New trail price or percent of a trailing stop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trail: Option<Num>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+}
+
+impl ReplaceOrder {
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if self.qty.is_none()
+            && self.time_in_force.is_none()
+            && self.limit_price.is_none()
+            && self.stop_price.is_none()
+            && self.trail.is_none()
+            && self.client_order_id.is_none()
+        {
+            return invalid("nothing to replace");
+        }
+        let values = [&self.qty, &self.limit_price, &self.stop_price, &self.trail];
+        if values
+            .iter()
+            .copied()
+            .flatten()
+            .any(|value| !value.is_positive())
+        {
+            return invalid("qty and prices must be positive");
+        }
+        Ok(())
+    }
+
+    /// `order` with the changes applied
+    pub fn apply(&self, order: &Order) -> Order {
+        let mut order = order.clone();
+        if let Some(qty) = &self.qty {
+            order.qty = Some(qty.clone());
+        }
+        if let Some(time_in_force) = &self.time_in_force {
+            order.time_in_force = time_in_force.clone();
+        }
+        if let Some(limit_price) = &self.limit_price {
+            order.limit_price = Some(limit_price.clone());
+        }
+        if let Some(stop_price) = &self.stop_price {
+            order.stop_price = Some(stop_price.clone());
+        }
+        if let Some(trail) = &self.trail {
+            match order.trail_price {
+                Some(_) => order.trail_price = Some(trail.clone()),
+                None => order.trail_percent = Some(trail.clone()),
+            }
+        }
+        if let Some(client_order_id) = &self.client_order_id {
+            order.client_order_id = Some(client_order_id.clone());
+        }
+        order
+    }
+
+    /// Round the changes of an order of `side` to the increments of `asset`
+    pub fn round_to(&self, asset: &Asset, side: &Side) -> ReplaceOrder {
+        ReplaceOrder {
+            qty: match (&self.qty, asset.lot_size()) {
+                (Some(qty), Some(lot)) => Some(round_down(qty, &lot)),
+                (qty, None) => qty.clone(),
+                (None, _) => None,
+            },
+            limit_price: self
+                .limit_price
+                .as_ref()
+                .map(|price| limit_tick(asset, price, side)),
+            stop_price: self
+                .stop_price
+                .as_ref()
+                .map(|price| stop_tick(asset, price)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Client order id of an order placed by the bot `bot_id`
+pub fn bot_client_order_id(bot_id: &str) -> String {
+    format!("{}:{}", bot_id, Uuid::new_v4().simple())
+}
+
+/// Bot that placed an order, from its client order id
+pub fn client_order_bot_id(client_order_id: &str) -> Option<&str> {
+    client_order_id
+        .rsplit_once(':')
+        .map(|(bot_id, _)| bot_id)
+        .filter(|bot_id| !bot_id.is_empty())
+}
+
+fn invalid(message: &str) -> Result<(), OrderError> {
+    Err(OrderError::InvalidParameters(message.to_string()))
+}
+
+/// Order as reported back by the broker
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BrokerOrder {
+    pub id: String,
+    pub client_order_id: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub submitted_at: Option<String>,
+    pub filled_at: Option<String>,
+    pub symbol: String,
+    pub qty: Option<Num>,
+    pub notional: Option<Num>,
+    pub filled_qty: Option<Num>,
+    pub filled_avg_price: Option<Num>,
     pub side: Side,
     #[serde(rename = "type")]
     pub order_type: Type,
     pub time_in_force: TimeInForce,
-    pub limit_price: Option<i32>,
-    pub stop_price: Option<i32>,
-    pub trail_price: Option<i32>,
-    pub trail_percent: Option<i32>,
+    #[serde(default)]
+    pub order_class: OrderClass,
+    pub limit_price: Option<Num>,
+    pub stop_price: Option<Num>,
+    #[serde(default)]
+    pub trail_price: Option<Num>,
+    #[serde(default)]
+    pub trail_percent: Option<Num>,
+    /// Highest (or lowest) price seen by a trailing stop
+    #[serde(default)]
+    pub hwm: Option<Num>,
+    pub status: String,
     pub extended_hours: Option<bool>,
+    /// Take profit and stop loss orders of a bracket, oco or oto order
+    #[serde(default)]
+    pub legs: Option<Vec<BrokerOrder>>,
+    /// Order replacing this one
+    #[serde(default)]
+    pub replaced_by: Option<String>,
+    /// Order replaced by this one
+    #[serde(default)]
+    pub replaces: Option<String>,
+}
+
+/// Request of an order reported by the broker, its legs are orders of their own
+impl From<&BrokerOrder> for Order {
+    fn from(order: &BrokerOrder) -> Self {
+        Order {
+            symbol: order.symbol.clone(),
+            qty: order.qty.clone(),
+            national: order.notional.clone(),
+            side: order.side.clone(),
+            order_type: order.order_type.clone(),
+            time_in_force: order.time_in_force.clone(),
+            limit_price: order.limit_price.clone(),
+            stop_price: order.stop_price.clone(),
+            trail_price: order.trail_price.clone(),
+            trail_percent: order.trail_percent.clone(),
+            extended_hours: order.extended_hours,
+            client_order_id: order.client_order_id.clone(),
+            ..Order::default()
+        }
+    }
+}
+
+/// Outcome of the cancellation of one order, `status` is the http status
+/// the broker answered for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderCancellation {
+    pub id: String,
+    pub status: u16,
+}
+
+/// Lifecycle state of an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+    Replaced,
+}
+
+impl OrderStatus {
+    /// No more fills can happen on the order
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    /// State after `event`, `None` when the event is not allowed in this state
+    pub fn transition(self, event: &TradeEvent) -> Option<OrderStatus> {
+        let next = match event {
+            TradeEvent::New | TradeEvent::PendingNew => OrderStatus::New,
+            TradeEvent::PartialFill => OrderStatus::PartiallyFilled,
+            TradeEvent::Fill => OrderStatus::Filled,
+            TradeEvent::Canceled => OrderStatus::Canceled,
+            TradeEvent::Rejected => OrderStatus::Rejected,
+            TradeEvent::Expired => OrderStatus::Expired,
+            TradeEvent::Replaced => OrderStatus::Replaced,
+            // informational events do not move the order
+            _ if !self.is_terminal() => return Some(self),
+            _ => return None,
+        };
+
+        let allowed = match (self, next) {
+            (OrderStatus::New, _) => true,
+            (OrderStatus::PartiallyFilled, OrderStatus::New | OrderStatus::Rejected) => false,
+            (OrderStatus::PartiallyFilled, _) => true,
+            _ => false,
+        };
+        allowed.then_some(next)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            OrderStatus::New => write!(f, "new"),
+            OrderStatus::PartiallyFilled => write!(f, "partially_filled"),
+            OrderStatus::Filled => write!(f, "filled"),
+            OrderStatus::Canceled => write!(f, "canceled"),
+            OrderStatus::Rejected => write!(f, "rejected"),
+            OrderStatus::Expired => write!(f, "expired"),
+            OrderStatus::Replaced => write!(f, "replaced"),
+        }
+    }
+}
+
+/// Event of the broker trade updates stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeEvent {
+    New,
+    Fill,
+    PartialFill,
+    Canceled,
+    Expired,
+    DoneForDay,
+    Replaced,
+    Rejected,
+    PendingNew,
+    Stopped,
+    PendingCancel,
+    PendingReplace,
+    Calculated,
+    Suspended,
+    OrderReplaceRejected,
+    OrderCancelRejected,
+    #[serde(other)]
+    Other,
+}
+
+impl fmt::Display for TradeEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(event)) => write!(f, "{}", event),
+            _ => write!(f, "other"),
+        }
+    }
+}
+
+/// Update of an order pushed by the broker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeUpdate {
+    pub event: TradeEvent,
+    pub order: BrokerOrder,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    /// Price of the fill
+    #[serde(default)]
+    pub price: Option<Num>,
+    /// Quantity of the fill
+    #[serde(default)]
+    pub qty: Option<Num>,
+    /// Position held in the symbol after the fill
+    #[serde(default)]
+    pub position_qty: Option<Num>,
+}
+
+/// Recorded transition of an order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderEvent {
+    pub id: i64,
+    pub order_id: String,
+    pub client_order_id: Option<String>,
+    pub symbol: String,
+    pub event: String,
+    pub status: String,
+    pub price: Option<Num>,
+    pub qty: Option<Num>,
+    pub filled_qty: Option<Num>,
+    pub position_qty: Option<Num>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Recorded order, with its latest status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRecord {
+    pub id: String,
     pub client_order_id: Option<String>,
+    pub bot_id: Option<String>,
+    pub symbol: String,
+    pub side: String,
+    pub order_type: String,
+    pub time_in_force: String,
+    pub order_class: String,
+    pub qty: Option<Num>,
+    pub notional: Option<Num>,
+    pub limit_price: Option<Num>,
+    pub stop_price: Option<Num>,
+    pub filled_qty: Option<Num>,
+    pub filled_avg_price: Option<Num>,
+    pub status: String,
+    /// Order replaced by this one
+    pub replaces: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Recorded execution of an order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub id: i64,
+    pub order_id: String,
+    pub bot_id: Option<String>,
+    pub symbol: String,
+    pub side: String,
+    pub qty: Num,
+    pub price: Num,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Filters of a mass cancellation, every open order when empty
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CancelOrdersParams {
+    pub symbol: Option<String>,
+    pub bot_id: Option<String>,
+}
+
+impl CancelOrdersParams {
+    pub fn is_empty(&self) -> bool {
+        self.symbol.is_none() && self.bot_id.is_none()
+    }
+
+    /// Whether the open order `order` is to be canceled
+    pub fn matches(&self, order: &BrokerOrder) -> bool {
+        let symbol = self
+            .symbol
+            .as_ref()
+            .is_none_or(|symbol| *symbol == order.symbol);
+        let bot = self.bot_id.as_deref().is_none_or(|bot_id| {
+            order
+                .client_order_id
+                .as_deref()
+                .and_then(client_order_bot_id)
+                == Some(bot_id)
+        });
+        symbol && bot
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -103,4 +729,348 @@ mod tests {
 
         assert_eq!(query, "limit=3&status=param1".to_string())
     }
+
+    fn order(order_type: Type) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            qty: Some(Num::from(10)),
+            side: Side::Buy,
+            order_type,
+            ..Order::default()
+        }
+    }
+
+    fn bracket(take_profit: i32, stop_price: i32) -> Order {
+        Order {
+            limit_price: Some(Num::from(100)),
+            order_class: OrderClass::Bracket,
+            take_profit: Some(TakeProfit {
+                limit_price: Num::from(take_profit),
+            }),
+            stop_loss: Some(StopLoss {
+                stop_price: Num::from(stop_price),
+                limit_price: None,
+            }),
+            ..order(Type::Limit)
+        }
+    }
+
+    #[test]
+    fn valid_orders() {
+        let orders = [
+            order(Type::Market),
+            Order {
+                limit_price: Some(Num::from(100)),
+                time_in_force: TimeInForce::OnOpen,
+                ..order(Type::Limit)
+            },
+            Order {
+                stop_price: Some(Num::from(90)),
+                ..order(Type::Stop)
+            },
+            Order {
+                stop_price: Some(Num::from(90)),
+                limit_price: Some(Num::from(91)),
+                time_in_force: TimeInForce::GoodUntilCancel,
+                ..order(Type::StopLimit)
+            },
+            Order {
+                trail_percent: Some(Num::from(2)),
+                ..order(Type::TrailingStop)
+            },
+            Order {
+                qty: None,
+                national: Some(Num::from(500)),
+                ..order(Type::Market)
+            },
+            bracket(110, 95),
+            Order {
+                side: Side::Sell,
+                limit_price: Some(Num::from(110)),
+                order_class: OrderClass::Oco,
+                take_profit: Some(TakeProfit {
+                    limit_price: Num::from(110),
+                }),
+                stop_loss: Some(StopLoss {
+                    stop_price: Num::from(95),
+                    limit_price: Some(Num::from(94)),
+                }),
+                ..order(Type::Limit)
+            },
+            Order {
+                order_class: OrderClass::Oto,
+                stop_loss: Some(StopLoss {
+                    stop_price: Num::from(95),
+                    limit_price: None,
+                }),
+                ..order(Type::Market)
+            },
+        ];
+
+        for order in orders {
+            assert!(order.validate().is_ok(), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn inconsistent_orders_are_rejected() {
+        let orders = [
+            order(Type::Limit),
+            Order {
+                limit_price: Some(Num::from(100)),
+                ..order(Type::Market)
+            },
+            Order {
+                stop_price: Some(Num::from(90)),
+                ..order(Type::StopLimit)
+            },
+            Order {
+                trail_price: Some(Num::from(1)),
+                trail_percent: Some(Num::from(2)),
+                ..order(Type::TrailingStop)
+            },
+            Order {
+                trail_price: Some(Num::from(1)),
+                limit_price: Some(Num::from(100)),
+                ..order(Type::Limit)
+            },
+            Order {
+                qty: None,
+                national: Some(Num::from(500)),
+                limit_price: Some(Num::from(100)),
+                ..order(Type::Limit)
+            },
+            Order {
+                national: Some(Num::from(500)),
+                ..order(Type::Market)
+            },
+            Order {
+                qty: Some(Num::from(0)),
+                ..order(Type::Market)
+            },
+            Order {
+                stop_price: Some(Num::from(90)),
+                time_in_force: TimeInForce::ImmediateOrCancel,
+                ..order(Type::Stop)
+            },
+            Order {
+                extended_hours: Some(true),
+                ..order(Type::Market)
+            },
+            Order {
+                order_class: OrderClass::Simple,
+                ..bracket(110, 95)
+            },
+            Order {
+                stop_loss: None,
+                ..bracket(110, 95)
+            },
+            Order {
+                time_in_force: TimeInForce::FillOrKill,
+                ..bracket(110, 95)
+            },
+            // stop above the take profit of a long position
+            bracket(95, 110),
+            // take profit below the entry
+            bracket(99, 95),
+            Order {
+                order_class: OrderClass::Oco,
+                ..bracket(110, 95)
+            },
+            Order {
+                order_class: OrderClass::Oto,
+                ..bracket(110, 95)
+            },
+            Order {
+                stop_loss: Some(StopLoss {
+                    stop_price: Num::from(95),
+                    limit_price: Some(Num::from(96)),
+                }),
+                ..bracket(110, 95)
+            },
+        ];
+
+        for order in orders {
+            assert!(order.validate().is_err(), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn order_with_legs_serializes_for_the_broker() {
+        let value = serde_json::to_value(bracket(110, 95)).unwrap();
+
+        assert_eq!(value["order_class"], "bracket");
+        assert_eq!(value["take_profit"]["limit_price"], "110");
+        assert_eq!(value["stop_loss"], serde_json::json!({"stop_price": "95"}));
+        assert!(serde_json::to_value(order(Type::Market))
+            .unwrap()
+            .get("take_profit")
+            .is_none());
+    }
+
+    #[test]
+    fn orders_are_rounded_to_the_asset_increments() {
+        let num = |value: &str| value.parse::<Num>().unwrap();
+        let asset = Asset {
+            fractionable: false,
+            ..Asset::unknown("AAPL")
+        };
+        let bracket = Order {
+            qty: Some(num("10.7")),
+            limit_price: Some(num("100.127")),
+            take_profit: Some(TakeProfit {
+                limit_price: num("110.001"),
+            }),
+            stop_loss: Some(StopLoss {
+                stop_price: num("95.006"),
+                limit_price: Some(num("94.999")),
+            }),
+            ..bracket(110, 95)
+        };
+
+        let rounded = bracket.round_to(&asset).unwrap();
+        assert_eq!(rounded.qty, Some(num("10")));
+        assert_eq!(rounded.limit_price, Some(num("100.12")));
+        assert_eq!(rounded.take_profit.unwrap().limit_price, num("110.01"));
+        let stop_loss = rounded.stop_loss.unwrap();
+        assert_eq!(stop_loss.stop_price, num("95.01"));
+        assert_eq!(stop_loss.limit_price, Some(num("95")));
+
+        let crypto = Asset {
+            min_order_size: Some(num("0.0001")),
+            min_trade_increment: Some(num("0.0001")),
+            ..Asset::unknown("BTC/USD")
+        };
+        let bitcoin = Order {
+            symbol: "BTC/USD".to_string(),
+            qty: Some(num("0.00012345")),
+            ..order(Type::Market)
+        };
+        assert_eq!(bitcoin.round_to(&crypto).unwrap().qty, Some(num("0.0001")));
+        let dust = Order {
+            qty: Some(num("0.00005")),
+            ..bitcoin
+        };
+        assert!(dust.round_to(&crypto).is_err());
+    }
+
+    #[test]
+    fn bot_of_an_order() {
+        let client_order_id = bot_client_order_id("5f0c3d0e-bot");
+        assert_eq!(client_order_bot_id(&client_order_id), Some("5f0c3d0e-bot"));
+        assert_eq!(client_order_bot_id("manual-order"), None);
+
+        let params = CancelOrdersParams {
+            symbol: Some("AAPL".to_string()),
+            bot_id: Some("5f0c3d0e-bot".to_string()),
+        };
+        let order = BrokerOrder {
+            symbol: "AAPL".to_string(),
+            client_order_id: Some(client_order_id),
+            ..BrokerOrder::default()
+        };
+        assert!(params.matches(&order));
+        assert!(!params.matches(&BrokerOrder {
+            client_order_id: None,
+            ..order.clone()
+        }));
+        assert!(CancelOrdersParams::default().matches(&order));
+    }
+
+    #[test]
+    fn replacement_is_rounded_and_validated() {
+        assert!(ReplaceOrder::default().validate().is_err());
+        let replace = ReplaceOrder {
+            qty: Some("2.5".parse().unwrap()),
+            limit_price: Some("101.237".parse().unwrap()),
+            ..ReplaceOrder::default()
+        };
+        assert!(replace.validate().is_ok());
+
+        let asset = Asset {
+            fractionable: false,
+            ..Asset::unknown("AAPL")
+        };
+        let rounded = replace.round_to(&asset, &Side::Sell);
+        assert_eq!(rounded.qty, Some(Num::from(2)));
+        assert_eq!(rounded.limit_price, Some(Num::new(10124, 100)));
+
+        let order = BrokerOrder {
+            symbol: "AAPL".to_string(),
+            qty: Some(Num::from(5)),
+            side: Side::Sell,
+            order_type: Type::Limit,
+            time_in_force: TimeInForce::Day,
+            limit_price: Some(Num::from(100)),
+            ..BrokerOrder::default()
+        };
+        let replaced = rounded.apply(&Order::from(&order));
+        assert_eq!(replaced.qty, Some(Num::from(2)));
+        assert_eq!(replaced.limit_price, Some(Num::new(10124, 100)));
+        assert_eq!(replaced.time_in_force, TimeInForce::Day);
+        assert!(replaced.validate().is_ok());
+    }
+
+    #[test]
+    fn order_lifecycle_transitions() {
+        let status = OrderStatus::New
+            .transition(&TradeEvent::PartialFill)
+            .unwrap();
+        assert_eq!(status, OrderStatus::PartiallyFilled);
+        let status = status.transition(&TradeEvent::PartialFill).unwrap();
+        assert_eq!(status.transition(&TradeEvent::DoneForDay), Some(status));
+        let status = status.transition(&TradeEvent::Fill).unwrap();
+        assert_eq!(status, OrderStatus::Filled);
+        assert!(status.is_terminal());
+    }
+
+    #[test]
+    fn terminal_orders_do_not_move() {
+        for status in [
+            OrderStatus::Filled,
+            OrderStatus::Canceled,
+            OrderStatus::Rejected,
+            OrderStatus::Expired,
+        ] {
+            assert_eq!(status.transition(&TradeEvent::Fill), None);
+            assert_eq!(status.transition(&TradeEvent::New), None);
+        }
+        assert_eq!(
+            OrderStatus::PartiallyFilled.transition(&TradeEvent::Rejected),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_trade_update() {
+        let update: TradeUpdate = serde_json::from_str(
+            r#"{
+                "event": "partial_fill",
+                "execution_id": "e1",
+                "timestamp": "2024-01-02T15:04:05.123456Z",
+                "price": "100.5",
+                "qty": "2",
+                "position_qty": "2",
+                "order": {
+                    "id": "o1",
+                    "client_order_id": "c1",
+                    "symbol": "AAPL",
+                    "qty": "5",
+                    "filled_qty": "2",
+                    "side": "buy",
+                    "type": "limit",
+                    "time_in_force": "day",
+                    "limit_price": "101",
+                    "status": "partially_filled"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(update.event, TradeEvent::PartialFill);
+        assert_eq!(update.order.id, "o1");
+        assert_eq!(update.price, Some(Num::new(201, 2)));
+        assert_eq!(update.order.limit_price, Some(Num::from(101)));
+        assert_eq!(TradeEvent::PendingCancel.to_string(), "pending_cancel");
+    }
 }
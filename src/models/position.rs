@@ -1,12 +1,154 @@
-use serde::{Deserialize, Serialize};
-use serde_this_or_that::as_f64;
+use crate::models::order::BrokerOrder;
+use num_decimal::Num;
+use serde::{Deserialize, Deserializer, Serialize};
+use traidano::OrderError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionSide {
+    Long,
+    Short,
+}
+
+/// Open position, `qty` is negative for a short position
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub asset_id: String,
     pub symbol: String,
-    exchange: String,
-    asset_class: String,
-    avg_entry_price: String,
-    #[serde(deserialize_with = "as_f64")]
-    pub qty: f64,
+    pub exchange: String,
+    pub asset_class: String,
+    pub side: PositionSide,
+    pub qty: Num,
+    /// Quantity not held by open orders
+    #[serde(default)]
+    pub qty_available: Option<Num>,
+    pub avg_entry_price: Num,
+    pub current_price: Option<Num>,
+    pub market_value: Option<Num>,
+    pub cost_basis: Num,
+    pub unrealized_pl: Option<Num>,
+    /// Unrealized profit in percent of the cost basis, as a ratio
+    pub unrealized_plpc: Option<Num>,
+}
+
+/// Part of a position to close, all of it when empty
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ClosePosition {
+    pub qty: Option<Num>,
+    pub percentage: Option<Num>,
+}
+
+impl ClosePosition {
+    pub fn validate(&self) -> Result<(), OrderError> {
+        let invalid = |message: &str| Err(OrderError::InvalidParameters(message.to_string()));
+        match (&self.qty, &self.percentage) {
+            (Some(_), Some(_)) => invalid("qty and percentage are exclusive"),
+            (Some(qty), None) if !qty.is_positive() => invalid("qty must be positive"),
+            (None, Some(percentage))
+                if !percentage.is_positive() || *percentage > Num::from(100) =>
+            {
+                invalid("percentage must be between 0 and 100")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Quantity to close out of a position of `qty`, never more than held
+    pub fn qty_of(&self, qty: &Num) -> Num {
+        let held = if qty.is_negative() { -qty } else { qty.clone() };
+        match (&self.qty, &self.percentage) {
+            (Some(qty), _) => qty.clone().min(held),
+            (None, Some(percentage)) => held * percentage / 100,
+            (None, None) => held,
+        }
+    }
+}
+
+/// Options of the close of every position
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ClosePositionsParams {
+    /// Cancel the open orders first so none reopens a position, defaults to
+    /// true
+    pub cancel_orders: Option<bool>,
+}
+
+/// Outcome of the close of one position when flattening, `status` is the
+/// http status the broker answered for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionClosure {
+    pub symbol: String,
+    pub status: u16,
+    /// Order closing the position, none when the broker refused to close it
+    #[serde(default, alias = "body", deserialize_with = "closing_order")]
+    pub order: Option<BrokerOrder>,
+}
+
+/// The body of a failed closure is an error message rather than an order
+fn closing_order<'de, D>(deserializer: D) -> Result<Option<BrokerOrder>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let body = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(body).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_position() {
+        let position: Position = serde_json::from_str(
+            r#"{
+                "asset_id": "904837e3-3b76-47ec-b432-046db621571b",
+                "symbol": "AAPL",
+                "exchange": "NASDAQ",
+                "asset_class": "us_equity",
+                "avg_entry_price": "100.0",
+                "qty": "-5",
+                "qty_available": "-5",
+                "side": "short",
+                "market_value": "-600.0",
+                "cost_basis": "-500.0",
+                "unrealized_pl": "-100.0",
+                "unrealized_plpc": "-0.2",
+                "current_price": "120.0",
+                "lastday_price": "119.0",
+                "change_today": "0.0084"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(position.side, PositionSide::Short);
+        assert_eq!(position.qty, Num::from(-5));
+        assert_eq!(position.unrealized_plpc, Some(Num::new(-1, 5)));
+    }
+
+    #[test]
+    fn part_of_the_position_to_close() {
+        let held = Num::from(-10);
+        let close = ClosePosition {
+            percentage: Some(Num::from(25)),
+            ..ClosePosition::default()
+        };
+        assert!(close.validate().is_ok());
+        assert_eq!(close.qty_of(&held), Num::new(5, 2));
+        let close = ClosePosition {
+            qty: Some(Num::from(20)),
+            ..ClosePosition::default()
+        };
+        assert_eq!(close.qty_of(&held), Num::from(10));
+        assert_eq!(ClosePosition::default().qty_of(&held), Num::from(10));
+
+        let close = ClosePosition {
+            qty: Some(Num::from(1)),
+            percentage: Some(Num::from(50)),
+        };
+        assert!(close.validate().is_err());
+        let close = ClosePosition {
+            percentage: Some(Num::from(150)),
+            ..ClosePosition::default()
+        };
+        assert!(close.validate().is_err());
+    }
 }
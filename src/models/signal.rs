@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use num_decimal::Num;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What became of a signal of a bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalOutcome {
+    /// An order was placed
+    Placed,
+    /// No order was sized for the signal, or the bot is halted
+    Skipped,
+    /// The order was refused by the checks
+    Rejected,
+    /// The order could not reach the broker
+    Failed,
+}
+
+impl SignalOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalOutcome::Placed => "placed",
+            SignalOutcome::Skipped => "skipped",
+            SignalOutcome::Rejected => "rejected",
+            SignalOutcome::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for SignalOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Recorded signal of a bot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalRecord {
+    pub id: i64,
+    pub bot_id: String,
+    pub symbol: String,
+    pub side: String,
+    pub price: Num,
+    pub stop_price: Option<Num>,
+    /// Quantity sized for the signal
+    pub qty: Option<Num>,
+    pub outcome: String,
+    /// Why no order was placed
+    pub reason: Option<String>,
+    pub order_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
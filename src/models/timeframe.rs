@@ -0,0 +1,105 @@
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// Period of a bar, written the alpaca way: `1Min`, `15Min`, `1Hour`,
+/// `1Day`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Timeframe {
+    /// 1 to 59 minutes
+    Minute(u32),
+    /// 1 to 23 hours
+    Hour(u32),
+    Day,
+}
+
+impl Timeframe {
+    /// Timeframe the higher ones are built from
+    pub const BASE: Timeframe = Timeframe::Minute(1);
+
+    pub fn minutes(&self) -> u32 {
+        match *self {
+            Timeframe::Minute(n) => n,
+            Timeframe::Hour(n) => n * 60,
+            Timeframe::Day => 24 * 60,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::minutes(self.minutes() as i64)
+    }
+}
+
+impl FromStr for Timeframe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid timeframe '{}', expected like 5Min, 1Hour or 1Day",
+                s
+            )
+        };
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let count: u32 = s[..split].parse().map_err(|_| invalid())?;
+        match (&s[split..], count) {
+            ("Min", 1..=59) => Ok(Timeframe::Minute(count)),
+            ("Hour", 1..=23) => Ok(Timeframe::Hour(count)),
+            ("Day", 1) => Ok(Timeframe::Day),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for Timeframe {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timeframe> for String {
+    fn from(timeframe: Timeframe) -> Self {
+        timeframe.to_string()
+    }
+}
+
+impl fmt::Display for Timeframe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Timeframe::Minute(n) => write!(f, "{}Min", n),
+            Timeframe::Hour(n) => write!(f, "{}Hour", n),
+            Timeframe::Day => write!(f, "1Day"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alpaca_timeframes() {
+        for (s, timeframe) in [
+            ("1Min", Timeframe::Minute(1)),
+            ("15Min", Timeframe::Minute(15)),
+            ("4Hour", Timeframe::Hour(4)),
+            ("1Day", Timeframe::Day),
+        ] {
+            assert_eq!(s.parse::<Timeframe>(), Ok(timeframe));
+            assert_eq!(timeframe.to_string(), s);
+        }
+        for s in ["", "Min", "0Min", "60Min", "1min", "2Day", "1Week"] {
+            assert!(s.parse::<Timeframe>().is_err(), "{}", s);
+        }
+        assert_eq!(Timeframe::Hour(2).minutes(), 120);
+
+        let timeframes: Vec<Timeframe> = serde_json::from_str(r#"["5Min", "1Day"]"#).unwrap();
+        assert_eq!(timeframes, vec![Timeframe::Minute(5), Timeframe::Day]);
+        assert!(serde_json::from_str::<Timeframe>(r#""5m""#).is_err());
+    }
+}
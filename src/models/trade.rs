@@ -11,18 +11,105 @@ pub enum Side {
     Sell,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+impl Side {
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub enum Type {
+    #[serde(rename = "market")]
+    Market,
     #[serde(rename = "limit")]
     #[default]
     Limit,
+    #[serde(rename = "stop")]
+    Stop,
+    #[serde(rename = "stop_limit")]
+    StopLimit,
+    #[serde(rename = "trailing_stop")]
+    TrailingStop,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+impl Type {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Type::Market => "market",
+            Type::Limit => "limit",
+            Type::Stop => "stop",
+            Type::StopLimit => "stop_limit",
+            Type::TrailingStop => "trailing_stop",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub enum TimeInForce {
     #[serde(rename = "day")]
     #[default]
     Day,
     #[serde(rename = "gtc")]
     GoodUntilCancel,
+    /// Market or limit on open, executed in the opening auction
+    #[serde(rename = "opg")]
+    OnOpen,
+    /// Market or limit on close, executed in the closing auction
+    #[serde(rename = "cls")]
+    OnClose,
+    #[serde(rename = "ioc")]
+    ImmediateOrCancel,
+    #[serde(rename = "fok")]
+    FillOrKill,
+}
+
+impl TimeInForce {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Day => "day",
+            TimeInForce::GoodUntilCancel => "gtc",
+            TimeInForce::OnOpen => "opg",
+            TimeInForce::OnClose => "cls",
+            TimeInForce::ImmediateOrCancel => "ioc",
+            TimeInForce::FillOrKill => "fok",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum OrderClass {
+    // the broker reports simple orders with an empty class
+    #[serde(rename = "simple", alias = "")]
+    #[default]
+    Simple,
+    /// Entry with a take profit and a stop loss exit
+    #[serde(rename = "bracket")]
+    Bracket,
+    /// Take profit and stop loss exits, one cancels the other
+    #[serde(rename = "oco")]
+    Oco,
+    /// Entry with either a take profit or a stop loss exit
+    #[serde(rename = "oto")]
+    Oto,
+}
+
+impl OrderClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderClass::Simple => "simple",
+            OrderClass::Bracket => "bracket",
+            OrderClass::Oco => "oco",
+            OrderClass::Oto => "oto",
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use crate::models::bar::Bar;
+use serde::{Deserialize, Serialize};
+
+/// Latest best bid and ask of a symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quote {
+    #[serde(rename = "S")]
+    pub symbol: String,
+    #[serde(rename = "bp")]
+    pub bid_price: f64,
+    #[serde(rename = "bs")]
+    pub bid_size: f64,
+    #[serde(rename = "ap")]
+    pub ask_price: f64,
+    #[serde(rename = "as")]
+    pub ask_size: f64,
+    #[serde(rename = "t")]
+    pub timestamp: String,
+}
+
+/// Trade printed on the tape
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    #[serde(rename = "S")]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub price: f64,
+    #[serde(rename = "s")]
+    pub size: f64,
+    #[serde(rename = "t")]
+    pub timestamp: String,
+}
+
+/// Message of the market data websocket, frames hold a list of them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "T")]
+pub enum StreamMessage {
+    #[serde(rename = "success")]
+    Success { msg: String },
+    #[serde(rename = "error")]
+    Error { code: i64, msg: String },
+    #[serde(rename = "subscription")]
+    Subscription {
+        #[serde(default)]
+        bars: Vec<String>,
+        #[serde(default)]
+        quotes: Vec<String>,
+        #[serde(default)]
+        trades: Vec<String>,
+    },
+    #[serde(rename = "b")]
+    Bar {
+        #[serde(rename = "S")]
+        symbol: String,
+        #[serde(flatten)]
+        bar: Bar,
+    },
+    #[serde(rename = "q")]
+    Quote(Quote),
+    #[serde(rename = "t")]
+    Trade(Trade),
+    /// Updated and daily bars, statuses, lulds...
+    #[serde(other)]
+    Other,
+}
+
+/// Parse a text frame of the market data websocket
+pub fn parse_frame(frame: &str) -> Result<Vec<StreamMessage>, serde_json::Error> {
+    serde_json::from_str(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_market_data_frame() {
+        let frame = r#"[
+            {"T":"b","S":"AAPL","o":100.5,"h":101,"l":100,"c":100.75,"v":1200,"t":"2024-01-02T15:04:00Z","n":42,"vw":100.6},
+            {"T":"q","S":"AAPL","bx":"U","bp":100.7,"bs":3,"ax":"Q","ap":100.8,"as":2,"t":"2024-01-02T15:04:01Z","c":["R"],"z":"C"},
+            {"T":"t","i":96921,"S":"AAPL","x":"D","p":100.75,"s":10,"t":"2024-01-02T15:04:02Z","c":["@"],"z":"C"},
+            {"T":"u","S":"AAPL","o":1,"h":1,"l":1,"c":1,"v":1,"t":"2024-01-02T15:04:00Z"}
+        ]"#;
+
+        let messages = parse_frame(frame).unwrap();
+
+        assert_eq!(messages.len(), 4);
+        match &messages[0] {
+            StreamMessage::Bar { symbol, bar } => {
+                assert_eq!(symbol, "AAPL");
+                assert_eq!(bar.close_price, 100.75);
+                assert_eq!(bar.n, 42);
+            }
+            other => panic!("expected a bar, got {:?}", other),
+        }
+        assert!(matches!(&messages[1], StreamMessage::Quote(quote) if quote.ask_price == 100.8));
+        assert!(matches!(&messages[2], StreamMessage::Trade(trade) if trade.size == 10.0));
+        assert!(matches!(messages[3], StreamMessage::Other));
+    }
+
+    #[test]
+    fn parse_control_frames() {
+        let messages =
+            parse_frame(r#"[{"T":"error","code":402,"msg":"auth failed"},{"T":"subscription","bars":["AAPL"]}]"#)
+                .unwrap();
+
+        assert!(matches!(
+            &messages[0],
+            StreamMessage::Error { code: 402, .. }
+        ));
+        assert!(
+            matches!(&messages[1], StreamMessage::Subscription { bars, trades, .. } if bars == &["AAPL"] && trades.is_empty())
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use crate::base::ApiConfig;
+use crate::bot::MarketType;
+use crate::error::StreamError;
+use crate::models::bar::Bar;
+use crate::models::timeframe::Timeframe;
+use futures_util::{SinkExt, StreamExt};
+use message::{parse_frame, StreamMessage};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+pub mod message;
+pub mod trade_updates;
+
+/// Number of events a slow subscriber can lag behind before missing some
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Timeframe of the bars published on the stream
+pub const STREAM_TIMEFRAME: Timeframe = Timeframe::BASE;
+
+/// Connection settings of a websocket stream
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    pub url: String,
+    pub api_key: String,
+    pub secret_key: String,
+    /// Delay before the first reconnection, doubled on each failure
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl StreamConfig {
+    pub fn from_api_config(api_config: &ApiConfig) -> Self {
+        Self {
+            url: api_config.stream_url.clone(),
+            api_key: api_config.api_key.clone(),
+            secret_key: api_config.secret_key.clone(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Market data published to the bots, the bars only: the quotes and trades
+/// are not subscribed
+#[derive(Debug, Clone)]
+pub enum MarketEvent {
+    Bar { symbol: String, bar: Bar },
+}
+
+impl MarketEvent {
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Bar { symbol, .. } => symbol,
+        }
+    }
+}
+
+/// Market and symbols wanted by each live subscription
+struct Registry {
+    subscriptions: Mutex<HashMap<u64, (MarketType, Vec<String>)>>,
+    /// Union of the symbols of each market
+    symbols: HashMap<MarketType, watch::Sender<BTreeSet<String>>>,
+}
+
+impl Registry {
+    fn update(&self, change: impl FnOnce(&mut HashMap<u64, (MarketType, Vec<String>)>)) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        change(&mut subscriptions);
+        for (market, sender) in &self.symbols {
+            let symbols: BTreeSet<String> = subscriptions
+                .values()
+                .filter(|(subscribed, _)| subscribed == market)
+                .flat_map(|(_, symbols)| symbols.iter().cloned())
+                .collect();
+            sender.send_if_modified(|current| {
+                if *current == symbols {
+                    return false;
+                }
+                *current = symbols;
+                true
+            });
+        }
+    }
+}
+
+/// Fan out of the market data websockets.
+///
+/// Each market has its own connection, subscribing to the union of the
+/// symbols of the live [`Subscription`]s of the market and following it as
+/// bots start and stop.
+pub struct MarketStream {
+    events: broadcast::Sender<MarketEvent>,
+    registry: Arc<Registry>,
+    next_id: AtomicU64,
+}
+
+impl Default for MarketStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarketStream {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let symbols = [MarketType::Crypto, MarketType::Equity]
+            .into_iter()
+            .map(|market| (market, watch::channel(BTreeSet::new()).0))
+            .collect();
+        Self {
+            events,
+            registry: Arc::new(Registry {
+                subscriptions: Mutex::new(HashMap::new()),
+                symbols,
+            }),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Receive the events of `symbols` of `market` until the subscription is
+    /// dropped
+    pub fn subscribe(&self, market: &MarketType, symbols: &[String]) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.registry.update(|subscriptions| {
+            subscriptions.insert(id, (market.clone(), symbols.to_vec()));
+        });
+        Subscription {
+            id,
+            symbols: symbols.iter().cloned().collect(),
+            receiver: self.events.subscribe(),
+            registry: self.registry.clone(),
+        }
+    }
+
+    /// Receive every event without changing the subscribed symbols
+    pub fn events(&self) -> broadcast::Receiver<MarketEvent> {
+        self.events.subscribe()
+    }
+
+    /// Union of the symbols of the live subscriptions of `market`
+    pub fn symbols(&self, market: &MarketType) -> BTreeSet<String> {
+        self.registry.symbols[market].borrow().clone()
+    }
+
+    pub fn publish(&self, event: MarketEvent) {
+        // no receiver is not an error, bots may all be stopped
+        let _ = self.events.send(event);
+    }
+
+    /// Connect to the websocket of `market` in the background, reconnecting
+    /// with an exponential backoff whenever the connection drops
+    pub fn spawn(&self, market: &MarketType, config: StreamConfig) -> JoinHandle<()> {
+        let events = self.events.clone();
+        let symbols = self.registry.symbols[market].subscribe();
+        tokio::spawn(run(config, events, symbols))
+    }
+}
+
+/// Bot side of the stream, filtered on the symbols it was created with
+pub struct Subscription {
+    id: u64,
+    symbols: BTreeSet<String>,
+    receiver: broadcast::Receiver<MarketEvent>,
+    registry: Arc<Registry>,
+}
+
+impl Subscription {
+    /// Next event of the subscribed symbols, `None` once the stream is gone
+    pub async fn recv(&mut self) -> Option<MarketEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.symbols.contains(event.symbol()) => return Some(event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Market stream subscriber lagged, {} events lost", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.registry.update(|subscriptions| {
+            subscriptions.remove(&id);
+        });
+    }
+}
+
+async fn run(
+    config: StreamConfig,
+    events: broadcast::Sender<MarketEvent>,
+    mut symbols: watch::Receiver<BTreeSet<String>>,
+) {
+    let mut backoff = config.min_backoff;
+    loop {
+        match session(&config, &events, &mut symbols, &mut backoff).await {
+            // the market stream is gone, nobody can subscribe anymore
+            Ok(()) => return,
+            Err(e) => tracing::warn!(
+                "Market stream disconnected: {}, reconnecting in {:?}",
+                e,
+                backoff
+            ),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(config.max_backoff);
+    }
+}
+
+/// One connection: authenticate, then keep the subscriptions in sync and
+/// publish the market data until the connection drops
+async fn session(
+    config: &StreamConfig,
+    events: &broadcast::Sender<MarketEvent>,
+    symbols: &mut watch::Receiver<BTreeSet<String>>,
+    backoff: &mut Duration,
+) -> Result<(), StreamError> {
+    let (socket, _) = connect_async(config.url.as_str()).await?;
+    let (mut write, mut read) = socket.split();
+
+    let auth = json!({
+        "action": "auth",
+        "key": config.api_key,
+        "secret": config.secret_key,
+    });
+    write.send(Message::Text(auth.to_string())).await?;
+
+    // wait for the authentication
+    loop {
+        let frame = match read.next().await {
+            Some(Ok(Message::Text(frame))) => frame,
+            Some(Ok(Message::Close(_))) | None => return Err(StreamError::Closed),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        let messages = parse_frame(&frame)?;
+        if messages.iter().any(
+            |message| matches!(message, StreamMessage::Success { msg } if msg == "authenticated"),
+        ) {
+            break;
+        }
+        if let Some(StreamMessage::Error { code, msg }) = messages
+            .into_iter()
+            .find(|message| matches!(message, StreamMessage::Error { .. }))
+        {
+            return Err(StreamError::Auth(format!("{} {}", code, msg)));
+        }
+    }
+    tracing::info!("Market stream connected to {}", config.url);
+    *backoff = config.min_backoff;
+
+    // everything is subscribed again after a reconnection
+    let mut subscribed = BTreeSet::new();
+    loop {
+        let wanted = symbols.borrow_and_update().clone();
+        let added: Vec<&String> = wanted.difference(&subscribed).collect();
+        if !added.is_empty() {
+            write
+                .send(Message::Text(subscription_request("subscribe", &added)))
+                .await?;
+        }
+        let removed: Vec<&String> = subscribed.difference(&wanted).collect();
+        if !removed.is_empty() {
+            write
+                .send(Message::Text(subscription_request("unsubscribe", &removed)))
+                .await?;
+        }
+        subscribed = wanted;
+
+        tokio::select! {
+            frame = read.next() => match frame {
+                Some(Ok(Message::Text(frame))) => publish_frame(&frame, events),
+                Some(Ok(Message::Close(_))) | None => return Err(StreamError::Closed),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            changed = symbols.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Only the bars are subscribed, the quotes and trades nobody reads would
+/// crowd them out of the channel
+fn subscription_request(action: &str, symbols: &[&String]) -> String {
+    json!({
+        "action": action,
+        "bars": symbols,
+    })
+    .to_string()
+}
+
+fn publish_frame(frame: &str, events: &broadcast::Sender<MarketEvent>) {
+    let messages = match parse_frame(frame) {
+        Ok(messages) => messages,
+        Err(e) => {
+            tracing::warn!("Cannot parse market stream frame: {}", e);
+            return;
+        }
+    };
+
+    for message in messages {
+        let event = match message {
+            StreamMessage::Bar { symbol, bar } => MarketEvent::Bar { symbol, bar },
+            StreamMessage::Error { code, msg } => {
+                tracing::error!("Market stream error {}: {}", code, msg);
+                continue;
+            }
+            StreamMessage::Subscription { bars, .. } => {
+                tracing::debug!("Market stream bars subscription: {:?}", bars);
+                continue;
+            }
+            StreamMessage::Quote(_)
+            | StreamMessage::Trade(_)
+            | StreamMessage::Success { .. }
+            | StreamMessage::Other => continue,
+        };
+        let _ = events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{accept_async, WebSocketStream};
+
+    fn symbols(symbols: &[&str]) -> Vec<String> {
+        symbols.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn bar_frame(symbol: &str) -> String {
+        format!(
+            r#"[{{"T":"b","S":"{}","o":1,"h":2,"l":0.5,"c":1.5,"v":100,"t":"2024-01-02T15:04:00Z","n":3,"vw":1.2}}]"#,
+            symbol
+        )
+    }
+
+    /// Accept a connection and run the authentication handshake
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut socket = accept_async(tcp).await.unwrap();
+        socket
+            .send(Message::Text(
+                r#"[{"T":"success","msg":"connected"}]"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        let auth = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(auth.contains(r#""action":"auth""#));
+        socket
+            .send(Message::Text(
+                r#"[{"T":"success","msg":"authenticated"}]"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        socket
+    }
+
+    async fn next_request(socket: &mut WebSocketStream<TcpStream>) -> serde_json::Value {
+        let request = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        serde_json::from_str(&request).unwrap()
+    }
+
+    #[test]
+    fn symbols_follow_subscriptions() {
+        let stream = MarketStream::new();
+
+        let first = stream.subscribe(&MarketType::Equity, &symbols(&["AAPL", "MSFT"]));
+        let second = stream.subscribe(&MarketType::Equity, &symbols(&["MSFT", "TSLA"]));
+        let crypto = stream.subscribe(&MarketType::Crypto, &symbols(&["BTC/USD"]));
+        assert_eq!(stream.symbols(&MarketType::Equity).len(), 3);
+        assert_eq!(
+            stream
+                .symbols(&MarketType::Crypto)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            symbols(&["BTC/USD"])
+        );
+
+        drop(first);
+        assert_eq!(
+            stream
+                .symbols(&MarketType::Equity)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            symbols(&["MSFT", "TSLA"])
+        );
+
+        drop(second);
+        drop(crypto);
+        assert!(stream.symbols(&MarketType::Equity).is_empty());
+        assert!(stream.symbols(&MarketType::Crypto).is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscription_filters_symbols() {
+        let stream = MarketStream::new();
+        let mut subscription = stream.subscribe(&MarketType::Equity, &symbols(&["AAPL"]));
+
+        for symbol in ["MSFT", "AAPL"] {
+            let frame = bar_frame(symbol);
+            publish_frame(&frame, &stream.events);
+        }
+
+        let event = subscription.recv().await.unwrap();
+        assert_eq!(event.symbol(), "AAPL");
+    }
+
+    #[tokio::test]
+    async fn resubscribes_after_reconnection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = StreamConfig {
+            url: format!("ws://{}", listener.local_addr().unwrap()),
+            api_key: "key".to_string(),
+            secret_key: "secret".to_string(),
+            min_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+        };
+
+        let stream = MarketStream::new();
+        let mut subscription = stream.subscribe(&MarketType::Equity, &symbols(&["AAPL"]));
+        let handle = stream.spawn(&MarketType::Equity, config);
+
+        let mut socket = accept(&listener).await;
+        let request = next_request(&mut socket).await;
+        assert_eq!(request["action"], "subscribe");
+        assert_eq!(request["bars"], json!(["AAPL"]));
+        assert_eq!(request.get("quotes"), None);
+        socket.send(Message::Text(bar_frame("AAPL"))).await.unwrap();
+        assert!(matches!(
+            subscription.recv().await,
+            Some(MarketEvent::Bar { symbol, .. }) if symbol == "AAPL"
+        ));
+
+        // a new bot adds its symbols to the connection
+        let other = stream.subscribe(&MarketType::Equity, &symbols(&["MSFT"]));
+        // crypto symbols go to the crypto connection
+        let _crypto = stream.subscribe(&MarketType::Crypto, &symbols(&["BTC/USD"]));
+        let request = next_request(&mut socket).await;
+        assert_eq!(request["action"], "subscribe");
+        assert_eq!(request["bars"], json!(["MSFT"]));
+        drop(other);
+        let request = next_request(&mut socket).await;
+        assert_eq!(request["action"], "unsubscribe");
+
+        // the connection drops, everything is subscribed again
+        drop(socket);
+        let mut socket = accept(&listener).await;
+        let request = next_request(&mut socket).await;
+        assert_eq!(request["action"], "subscribe");
+        assert_eq!(request["bars"], json!(["AAPL"]));
+
+        handle.abort();
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::StreamError;
+use crate::models::order::TradeUpdate;
+use crate::stream::StreamConfig;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Message of the trading websocket
+#[derive(Debug, Deserialize)]
+struct StreamFrame {
+    stream: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+/// Websocket url of the trade updates of a trading api url,
+/// `https://paper-api.alpaca.markets/v2/` streams on
+/// `wss://paper-api.alpaca.markets/stream`
+pub fn trade_stream_url(base_url: &str) -> Option<String> {
+    let (scheme, rest) = base_url.split_once("://")?;
+    let scheme = match scheme {
+        "https" => "wss",
+        "http" => "ws",
+        _ => return None,
+    };
+    let host = rest.split('/').next().filter(|host| !host.is_empty())?;
+    Some(format!("{}://{}/stream", scheme, host))
+}
+
+/// Listen to the trade updates in the background and send them to `updates`,
+/// reconnecting with an exponential backoff whenever the connection drops
+pub fn spawn(config: StreamConfig, updates: mpsc::UnboundedSender<TradeUpdate>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut backoff = config.min_backoff;
+        loop {
+            match session(&config, &updates, &mut backoff).await {
+                // nobody consumes the updates anymore
+                Ok(()) => return,
+                Err(e) => tracing::warn!(
+                    "Trade updates stream disconnected: {}, reconnecting in {:?}",
+                    e,
+                    backoff
+                ),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(config.max_backoff);
+        }
+    })
+}
+
+async fn session(
+    config: &StreamConfig,
+    updates: &mpsc::UnboundedSender<TradeUpdate>,
+    backoff: &mut Duration,
+) -> Result<(), StreamError> {
+    let (socket, _) = connect_async(config.url.as_str()).await?;
+    let (mut write, mut read) = socket.split();
+
+    let auth = json!({
+        "action": "auth",
+        "key": config.api_key,
+        "secret": config.secret_key,
+    });
+    write.send(Message::Text(auth.to_string())).await?;
+
+    loop {
+        // the trading stream sends binary frames on the paper api
+        let frame = match read.next().await {
+            Some(Ok(Message::Text(frame))) => frame,
+            Some(Ok(Message::Binary(frame))) => String::from_utf8_lossy(&frame).into_owned(),
+            Some(Ok(Message::Close(_))) | None => return Err(StreamError::Closed),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        let frame: StreamFrame = serde_json::from_str(&frame)?;
+
+        match frame.stream.as_str() {
+            "authorization" => {
+                if frame.data["status"] != "authorized" {
+                    return Err(StreamError::Auth(frame.data.to_string()));
+                }
+                let listen = json!({
+                    "action": "listen",
+                    "data": { "streams": ["trade_updates"] },
+                });
+                write.send(Message::Text(listen.to_string())).await?;
+            }
+            "listening" => {
+                tracing::info!("Trade updates stream connected to {}", config.url);
+                *backoff = config.min_backoff;
+            }
+            "trade_updates" => match serde_json::from_value::<TradeUpdate>(frame.data) {
+                Ok(update) => {
+                    if updates.send(update).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => tracing::warn!("Cannot parse trade update: {}", e),
+            },
+            stream => tracing::debug!("Ignoring '{}' message of the trading stream", stream),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::order::TradeEvent;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    #[test]
+    fn stream_url_from_base_url() {
+        assert_eq!(
+            trade_stream_url("https://paper-api.alpaca.markets/v2/").as_deref(),
+            Some("wss://paper-api.alpaca.markets/stream")
+        );
+        assert_eq!(
+            trade_stream_url("http://localhost:8080").as_deref(),
+            Some("ws://localhost:8080/stream")
+        );
+        assert_eq!(trade_stream_url("base_url"), None);
+    }
+
+    #[tokio::test]
+    async fn forwards_trade_updates() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = StreamConfig {
+            url: format!("ws://{}/stream", listener.local_addr().unwrap()),
+            api_key: "key".to_string(),
+            secret_key: "secret".to_string(),
+            min_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+        };
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let handle = spawn(config, sender);
+
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut socket = accept_async(tcp).await.unwrap();
+        let auth = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(auth.contains(r#""action":"auth""#));
+        socket
+            .send(Message::Binary(
+                br#"{"stream":"authorization","data":{"action":"authenticate","status":"authorized"}}"#
+                    .to_vec(),
+            ))
+            .await
+            .unwrap();
+        let listen = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(listen.contains("trade_updates"));
+        socket
+            .send(Message::Text(
+                r#"{"stream":"trade_updates","data":{"event":"fill","price":"10","qty":"1","order":{"id":"o1","symbol":"AAPL","side":"buy","type":"market","time_in_force":"day","status":"filled"}}}"#
+                    .to_string(),
+            ))
+            .await
+            .unwrap();
+
+        let update = receiver.recv().await.unwrap();
+        assert_eq!(update.event, TradeEvent::Fill);
+        assert_eq!(update.order.id, "o1");
+
+        handle.abort();
+    }
+}